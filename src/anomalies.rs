@@ -0,0 +1,89 @@
+use crate::health::label;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// Data quality problems seen in the marne.io responses
+pub struct Anomalies {
+    pub bom_stripped: AtomicU64,
+    /// counts per kind, server id and server name
+    per_server: Mutex<BTreeMap<(&'static str, i64, String), u64>>,
+    /// values already warned about, to not flood the logs every update
+    warned: Mutex<Vec<String>>,
+}
+
+pub static ANOMALIES: Anomalies = Anomalies {
+    bom_stripped: AtomicU64::new(0),
+    per_server: Mutex::new(BTreeMap::new()),
+    warned: Mutex::new(Vec::new()),
+};
+
+/// Unknown internal map name of a server
+pub const UNKNOWN_MAP: &str = "unknown_map";
+/// Unknown game mode of a server
+pub const UNKNOWN_GAME_MODE: &str = "unknown_game_mode";
+/// player or mod list that came as a string instead of a list
+pub const DESERIALIZATION_FALLBACK: &str = "deserialization_fallback";
+/// server of the server list that didn't parse and was left out
+pub const DROPPED_SERVER: &str = "dropped_server";
+
+impl Anomalies {
+    /// Counts an anomaly, logs a warning the first time `value` is seen
+    pub fn count(&self, counter: &AtomicU64, kind: &str, value: &str) {
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.warn_once(kind, value);
+    }
+
+    /// Counts an anomaly of a server, logs a warning the first time `value` is seen
+    pub fn count_server(
+        &self,
+        metric_kind: &'static str,
+        server_id: i64,
+        server_name: &str,
+        kind: &str,
+        value: &str,
+    ) {
+        *self
+            .per_server
+            .lock()
+            .unwrap()
+            .entry((metric_kind, server_id, server_name.to_string()))
+            .or_default() += 1;
+        self.warn_once(kind, value);
+    }
+
+    fn warn_once(&self, kind: &str, value: &str) {
+        let key = format!("{}: {}", kind, value);
+        let mut warned = self.warned.lock().unwrap();
+        if !warned.contains(&key) {
+            log::warn!("marne.io anomaly, {}", key);
+            warned.push(key);
+        }
+    }
+
+    /// Prometheus text format of the counters
+    pub fn metrics(&self) -> String {
+        let mut metrics = String::from(
+            "# HELP marne_bot_payload_anomalies_total Data quality problems in marne.io responses\n\
+            # TYPE marne_bot_payload_anomalies_total counter\n",
+        );
+        metrics += &format!(
+            "marne_bot_payload_anomalies_total{{kind=\"bom_stripped\",server_id=\"\",server_name=\"\"}} {}\n",
+            self.bom_stripped.load(Ordering::Relaxed)
+        );
+        for ((kind, server_id, server_name), count) in self.per_server.lock().unwrap().iter() {
+            metrics += &format!(
+                "marne_bot_payload_anomalies_total{{kind=\"{}\",server_id=\"{}\",server_name=\"{}\"}} {}\n",
+                kind,
+                server_id,
+                label(server_name),
+                count
+            );
+        }
+        metrics
+    }
+}
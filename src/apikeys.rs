@@ -0,0 +1,139 @@
+use crate::config;
+use anyhow::Result;
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, RwLock},
+};
+use warp::http::StatusCode;
+
+/// Access to the HTTP API, only the hash of the key is stored
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiKey {
+    pub name: String,
+    /// hex encoded SHA-256 of the key
+    pub hash: String,
+    pub requests_per_minute: usize,
+    /// unix time
+    pub created: i64,
+}
+
+/// HTTP API settings
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Api {
+    /// only answer `/api/` routes with a valid `X-Api-Key` header
+    pub require_key: bool,
+    /// rate limit of new keys
+    pub requests_per_minute: usize,
+    /// managed with `/apikey`
+    pub keys: Vec<ApiKey>,
+}
+
+impl ::std::default::Default for Api {
+    fn default() -> Self {
+        Self {
+            require_key: false,
+            requests_per_minute: 60,
+            keys: vec![],
+        }
+    }
+}
+
+fn hash(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The API keys with their requests in the last minute
+pub struct ApiKeys {
+    api: RwLock<Api>,
+    requests: Mutex<HashMap<String, VecDeque<i64>>>,
+}
+
+impl ApiKeys {
+    pub fn new(api: Api) -> Self {
+        Self {
+            api: RwLock::new(api),
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a key, returns it so it can be handed out once
+    pub fn create(&self, name: &str, requests_per_minute: Option<usize>) -> Result<String> {
+        let mut bytes = [0u8; 24];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let key = format!(
+            "mb_{}",
+            bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        );
+        let mut api = self.api.write().unwrap();
+        if api.keys.iter().any(|key| key.name == name) {
+            anyhow::bail!("a key named `{}` already exists", name);
+        }
+        let requests_per_minute = requests_per_minute.unwrap_or(api.requests_per_minute);
+        api.keys.push(ApiKey {
+            name: name.to_string(),
+            hash: hash(&key),
+            requests_per_minute,
+            created: Utc::now().timestamp(),
+        });
+        store(&api.keys)?;
+        Ok(key)
+    }
+
+    /// Removes a key, returns if it existed
+    pub fn revoke(&self, name: &str) -> Result<bool> {
+        let mut api = self.api.write().unwrap();
+        let before = api.keys.len();
+        api.keys.retain(|key| key.name != name);
+        if api.keys.len() == before {
+            return Ok(false);
+        }
+        store(&api.keys)?;
+        Ok(true)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let api = self.api.read().unwrap();
+        api.keys.iter().map(|key| key.name.clone()).collect()
+    }
+
+    /// Checks the key of a request, `UNAUTHORIZED` for a missing or unknown key when keys are
+    /// required and `TOO_MANY_REQUESTS` when the key is over its rate limit
+    pub fn authorize(&self, key: Option<&str>) -> Result<(), StatusCode> {
+        let api = self.api.read().unwrap();
+        let key = match key.map(hash) {
+            Some(hash) => api.keys.iter().find(|key| key.hash == hash),
+            None => None,
+        };
+        let key = match (key, api.require_key) {
+            (Some(key), _) => key,
+            (None, true) => return Err(StatusCode::UNAUTHORIZED),
+            (None, false) => return Ok(()),
+        };
+        let now = Utc::now().timestamp();
+        let mut requests = self.requests.lock().unwrap();
+        let times = requests.entry(key.hash.clone()).or_default();
+        while times.front().is_some_and(|time| now - time >= 60) {
+            times.pop_front();
+        }
+        if times.len() >= key.requests_per_minute {
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+fn store(keys: &[ApiKey]) -> Result<()> {
+    config::update(|cfg| cfg.api.keys = keys.to_vec())
+}
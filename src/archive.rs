@@ -0,0 +1,134 @@
+use crate::marne;
+use anyhow::Result;
+use chrono::Utc;
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// Pause between the servers of a snapshot, so the archive never competes with the updates
+const PAUSE_BETWEEN_SERVERS: Duration = Duration::from_secs(2);
+
+/// Set while a snapshot is being taken, a slow one makes the next ones skip
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Raw server details of the monitored servers, kept gzipped so later features can be filled
+/// from them
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Archive {
+    pub enabled: bool,
+    /// a snapshot every this many updates
+    pub every_polls: u64,
+    /// folder of the snapshots, a subfolder per server id
+    pub dir: String,
+    /// snapshots older than this are deleted
+    pub keep_days: u64,
+    /// the oldest snapshots are deleted when the folder grows over this
+    pub max_mb: u64,
+}
+
+impl ::std::default::Default for Archive {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            every_polls: 10,
+            dir: "archive".into(),
+            keep_days: 30,
+            max_mb: 500,
+        }
+    }
+}
+
+impl Archive {
+    /// If the update of this cycle takes a snapshot
+    pub fn due(&self, cycle: u64) -> bool {
+        self.enabled && cycle.is_multiple_of(self.every_polls.max(1))
+    }
+}
+
+/// Stores the details of the servers as `<dir>/<server id>/<time>.json.gz` and removes the
+/// snapshots over the retention limits. Runs in the background of the monitor loop
+pub async fn snapshot(settings: Archive, game: String, server_ids: Vec<i64>) {
+    if RUNNING.swap(true, Ordering::Relaxed) {
+        log::debug!("Previous archive snapshot still running, skipping this one");
+        return;
+    }
+    for server_id in server_ids {
+        let detail = match marne::server_detail_raw(&game, server_id).await {
+            Ok(detail) => detail,
+            Err(e) => {
+                log::warn!(
+                    "Failed to get the details of {} to archive: {}",
+                    server_id,
+                    e
+                );
+                continue;
+            }
+        };
+        let dir = Path::new(&settings.dir).join(server_id.to_string());
+        let written = tokio::task::spawn_blocking(move || write(&dir, &detail)).await;
+        match written {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => log::error!("Failed to archive server {}: {}", server_id, e),
+            Err(e) => log::error!("Failed to archive server {}: {}", server_id, e),
+        }
+        tokio::time::sleep(PAUSE_BETWEEN_SERVERS).await;
+    }
+    let pruned = tokio::task::spawn_blocking(move || prune(&settings)).await;
+    match pruned {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => log::error!("Failed to prune the archive: {}", e),
+        Err(e) => log::error!("Failed to prune the archive: {}", e),
+    }
+    RUNNING.store(false, Ordering::Relaxed);
+}
+
+fn write(dir: &Path, detail: &serde_json::Value) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json.gz", Utc::now().format("%Y%m%dT%H%M%S")));
+    let mut encoder = GzEncoder::new(fs::File::create(path)?, Compression::default());
+    encoder.write_all(&serde_json::to_vec(detail)?)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Deletes the snapshots older than `keep_days`, then the oldest until the folder is under
+/// `max_mb`
+fn prune(settings: &Archive) -> Result<()> {
+    let mut snapshots: Vec<(SystemTime, u64, PathBuf)> = vec![];
+    let root = match fs::read_dir(&settings.dir) {
+        Ok(root) => root,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for server_dir in root.flatten() {
+        if !server_dir.path().is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(server_dir.path())?.flatten() {
+            let metadata = file.metadata()?;
+            if metadata.is_file() {
+                snapshots.push((metadata.modified()?, metadata.len(), file.path()));
+            }
+        }
+    }
+    snapshots.sort();
+
+    let cutoff = SystemTime::now() - Duration::from_secs(settings.keep_days * 24 * 60 * 60);
+    let mut total: u64 = snapshots.iter().map(|(_, size, _)| size).sum();
+    let max_bytes = settings.max_mb * 1024 * 1024;
+    for (modified, size, path) in snapshots {
+        if modified >= cutoff && total <= max_bytes {
+            break;
+        }
+        fs::remove_file(&path)?;
+        total -= size;
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use serenity::model::id::{ChannelId, MessageId};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Longest message Discord accepts
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// A sent message that the next events of its window are added to
+#[derive(Clone, Debug)]
+pub struct Batch {
+    pub message_id: MessageId,
+    /// unix time the message was sent
+    started: i64,
+    lines: Vec<String>,
+}
+
+impl Batch {
+    /// The message with the events of the window. Over `max_lines` the first event is kept,
+    /// the ones after it are counted and the latest are shown
+    pub fn content(&self, max_lines: usize) -> String {
+        let max_lines = max_lines.max(2);
+        let content = match self.lines.len() > max_lines {
+            true => {
+                let skipped = self.lines.len() - max_lines + 1;
+                let mut lines = vec![
+                    self.lines[0].clone(),
+                    format!("*… {} more events …*", skipped),
+                ];
+                lines.extend(self.lines[skipped + 1..].iter().cloned());
+                lines.join("\n")
+            }
+            false => self.lines.join("\n"),
+        };
+        match content.chars().count() > MAX_MESSAGE_LENGTH {
+            true => format!(
+                "{}...",
+                content
+                    .chars()
+                    .take(MAX_MESSAGE_LENGTH - 3)
+                    .collect::<String>()
+            ),
+            false => content,
+        }
+    }
+}
+
+/// Coalesces the events sent to a channel in quick succession into one message that is edited,
+/// so a burst of events doesn't flood the channel or the message budget
+pub struct Batcher {
+    window_secs: i64,
+    max_lines: usize,
+    batches: Mutex<HashMap<ChannelId, Batch>>,
+}
+
+impl Batcher {
+    /// A window of 0 seconds sends every event as its own message
+    pub fn new(window_secs: u64, max_lines: usize) -> Self {
+        Self {
+            window_secs: i64::try_from(window_secs).unwrap_or(i64::MAX),
+            max_lines,
+            batches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn max_lines(&self) -> usize {
+        self.max_lines
+    }
+
+    /// Adds an event to the open window of a channel and returns the batch to edit, `None` if
+    /// there is no open window and a new message has to be sent
+    pub fn add(&self, channel_id: ChannelId, line: String) -> Option<Batch> {
+        let now = chrono::Utc::now().timestamp();
+        let mut batches = self.batches.lock().unwrap();
+        let batch = batches.get_mut(&channel_id)?;
+        if now - batch.started >= self.window_secs {
+            batches.remove(&channel_id);
+            return None;
+        }
+        batch.lines.push(line);
+        Some(batch.clone())
+    }
+
+    /// Opens a window with a message that was just sent
+    pub fn start(&self, channel_id: ChannelId, message_id: MessageId, line: String) {
+        if self.window_secs == 0 {
+            return;
+        }
+        self.batches.lock().unwrap().insert(
+            channel_id,
+            Batch {
+                message_id,
+                started: chrono::Utc::now().timestamp(),
+                lines: vec![line],
+            },
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use serenity::{
+    cache::Cache,
+    model::id::{ChannelId, GuildId},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+/// The cached guild with the channel, budgets are counted per guild
+pub fn guild_of(cache: &Cache, channel_id: ChannelId) -> Option<GuildId> {
+    cache.guilds().into_iter().find(|guild_id| {
+        cache
+            .guild(*guild_id)
+            .is_some_and(|guild| guild.channels.contains_key(&channel_id))
+    })
+}
+
+/// Discord actions that count against a budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    Message,
+    Edit,
+    Rename,
+    Avatar,
+    Crosspost,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Message => "message",
+            Action::Edit => "edit",
+            Action::Rename => "rename",
+            Action::Avatar => "avatar",
+            Action::Crosspost => "crosspost",
+        }
+    }
+}
+
+/// Maximum amount of actions per hour per guild
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Budgets {
+    pub messages_per_hour: usize,
+    pub edits_per_hour: usize,
+    pub renames_per_hour: usize,
+    pub avatars_per_hour: usize,
+    pub crossposts_per_hour: usize,
+    /// warn when this percentage of a budget is used
+    pub warn_at_percent: usize,
+}
+
+impl ::std::default::Default for Budgets {
+    fn default() -> Self {
+        Self {
+            messages_per_hour: 60,
+            edits_per_hour: 120,
+            // discord allows 2 channel renames per 10 minutes
+            renames_per_hour: 12,
+            avatars_per_hour: 6,
+            // discord allows 10 published messages per hour per channel
+            crossposts_per_hour: 10,
+            warn_at_percent: 80,
+        }
+    }
+}
+
+impl Budgets {
+    pub fn limit(&self, action: Action) -> usize {
+        match action {
+            Action::Message => self.messages_per_hour,
+            Action::Edit => self.edits_per_hour,
+            Action::Rename => self.renames_per_hour,
+            Action::Avatar => self.avatars_per_hour,
+            Action::Crosspost => self.crossposts_per_hour,
+        }
+    }
+}
+
+/// Usage of a budget in the last hour, `guild_id` is `None` for actions on the bot itself
+#[derive(Serialize, Debug, Clone)]
+pub struct Usage {
+    pub guild_id: Option<u64>,
+    pub action: &'static str,
+    pub used: usize,
+    pub limit: usize,
+}
+
+/// Times of the actions in the window per guild and action
+type Actions = HashMap<(Option<u64>, Action), VecDeque<i64>>;
+
+/// Counts the Discord actions of the bot in a rolling window of an hour
+pub struct RateBudget {
+    budgets: Budgets,
+    actions: Mutex<Actions>,
+}
+
+impl RateBudget {
+    pub fn new(budgets: Budgets) -> Self {
+        Self {
+            budgets,
+            actions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, guild_id: Option<GuildId>, action: Action) {
+        let now = chrono::Utc::now().timestamp();
+        let guild_id = guild_id.map(|guild_id| guild_id.get());
+        let used = {
+            let mut actions = self.actions.lock().unwrap();
+            let times = actions.entry((guild_id, action)).or_default();
+            times.push_back(now);
+            prune(times, now);
+            times.len()
+        };
+        let limit = self.budgets.limit(action);
+        if used * 100 >= limit * self.budgets.warn_at_percent {
+            log::warn!(
+                "{} of {} {} actions per hour used in guild {}",
+                used,
+                limit,
+                action.name(),
+                guild_id.map_or("-".to_string(), |guild_id| guild_id.to_string())
+            );
+        }
+    }
+
+    /// If another action fits in the budget of the last hour
+    pub fn allows(&self, guild_id: Option<GuildId>, action: Action) -> bool {
+        let now = chrono::Utc::now().timestamp();
+        let guild_id = guild_id.map(|guild_id| guild_id.get());
+        let mut actions = self.actions.lock().unwrap();
+        let used = match actions.get_mut(&(guild_id, action)) {
+            Some(times) => {
+                prune(times, now);
+                times.len()
+            }
+            None => 0,
+        };
+        used < self.budgets.limit(action)
+    }
+
+    /// Action in a channel, counted for the guild of the channel if it is cached
+    pub fn record_channel(&self, cache: &Cache, channel_id: ChannelId, action: Action) {
+        self.record(guild_of(cache, channel_id), action);
+    }
+
+    pub fn usage(&self) -> Vec<Usage> {
+        let now = chrono::Utc::now().timestamp();
+        let mut actions = self.actions.lock().unwrap();
+        let mut usage: Vec<Usage> = actions
+            .iter_mut()
+            .map(|((guild_id, action), times)| {
+                prune(times, now);
+                Usage {
+                    guild_id: *guild_id,
+                    action: action.name(),
+                    used: times.len(),
+                    limit: self.budgets.limit(*action),
+                }
+            })
+            .collect();
+        usage.sort_by_key(|usage| (usage.guild_id, usage.action));
+        usage
+    }
+
+    /// Prometheus text format of the usage
+    pub fn metrics(&self) -> String {
+        let mut metrics = String::from(
+            "# HELP marne_bot_discord_actions_last_hour Discord actions in the last hour\n\
+            # TYPE marne_bot_discord_actions_last_hour gauge\n",
+        );
+        for usage in self.usage() {
+            metrics += &format!(
+                "marne_bot_discord_actions_last_hour{{guild_id=\"{}\",action=\"{}\",budget=\"{}\"}} {}\n",
+                usage.guild_id.map_or("".to_string(), |guild_id| guild_id.to_string()),
+                usage.action,
+                usage.limit,
+                usage.used
+            );
+        }
+        metrics
+    }
+}
+
+fn prune(times: &mut VecDeque<i64>, now: i64) {
+    while times.front().is_some_and(|time| now - time > 3600) {
+        times.pop_front();
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+    budget, config,
+    marne::{internal_map, map_name, mode_name, MarneServerInfo},
+};
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::{builder::EditChannel, client::Context};
+use std::collections::VecDeque;
+
+/// Discord allows 2 renames of a channel per 10 minutes
+const RENAMES_PER_WINDOW: usize = 2;
+const RENAME_WINDOW_SECS: i64 = 600;
+/// Longest channel name Discord accepts
+const MAX_NAME_LENGTH: usize = 100;
+
+/// A (voice) channel that is renamed to the player count, like server stats bots do
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ChannelCounter {
+    pub channel_id: Option<u64>,
+    /// `{players}`, `{max_players}` and `{servers}` are the totals of the monitored servers,
+    /// `{map}` and `{mode}` those of the first one
+    pub format: String,
+    /// used when none of the servers are in the server list
+    pub offline_format: String,
+}
+
+impl ::std::default::Default for ChannelCounter {
+    fn default() -> Self {
+        Self {
+            channel_id: None,
+            format: "🟢 Players: {players}/{max_players}".into(),
+            offline_format: "🔴 Server offline".into(),
+        }
+    }
+}
+
+impl ChannelCounter {
+    pub fn name(&self, servers: &[MarneServerInfo]) -> String {
+        let name = match servers.first() {
+            Some(first) => self
+                .format
+                .replace(
+                    "{players}",
+                    &servers
+                        .iter()
+                        .map(|server| server.current_players)
+                        .sum::<i64>()
+                        .to_string(),
+                )
+                .replace(
+                    "{max_players}",
+                    &servers
+                        .iter()
+                        .map(|server| server.max_players)
+                        .sum::<i64>()
+                        .to_string(),
+                )
+                .replace("{servers}", &servers.len().to_string())
+                .replace("{map}", &map_name(internal_map(&first.map_name)))
+                .replace("{mode}", &mode_name(&first.game_mode)),
+            None => self.offline_format.clone(),
+        };
+        name.chars().take(MAX_NAME_LENGTH).collect()
+    }
+}
+
+/// Name the counter was last given and when it was renamed
+#[derive(Default)]
+pub struct CounterState {
+    name: Option<String>,
+    renames: VecDeque<i64>,
+}
+
+/// Renames the counter channel when its name changes. Renames over Discord's limit or the
+/// rename budget are skipped, the next update tries again
+pub async fn update(
+    ctx: &Context,
+    counter: &ChannelCounter,
+    budget: &budget::RateBudget,
+    state: &mut CounterState,
+    servers: &[MarneServerInfo],
+) -> Result<()> {
+    let channel_id = match config::channel_id(counter.channel_id) {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let guild_id = budget::guild_of(&ctx.cache, channel_id);
+    let current = guild_id
+        .and_then(|guild_id| ctx.cache.guild(guild_id))
+        .and_then(|guild| Some(guild.channels.get(&channel_id)?.name.clone()));
+    let name = counter.name(servers);
+    if state.name.as_ref().or(current.as_ref()) == Some(&name) {
+        return Ok(());
+    }
+
+    let now = Utc::now().timestamp();
+    while state
+        .renames
+        .front()
+        .is_some_and(|time| now - time >= RENAME_WINDOW_SECS)
+    {
+        state.renames.pop_front();
+    }
+    if state.renames.len() >= RENAMES_PER_WINDOW {
+        log::debug!(
+            "Channel counter renamed too often, waiting to rename it to {}",
+            name
+        );
+        return Ok(());
+    }
+    if !budget.allows(guild_id, budget::Action::Rename) {
+        log::debug!("Rename budget used up, waiting to rename the channel counter");
+        return Ok(());
+    }
+
+    channel_id
+        .edit(&ctx.http, EditChannel::new().name(&name))
+        .await?;
+    budget.record(guild_id, budget::Action::Rename);
+    state.renames.push_back(now);
+    state.name = Some(name);
+    Ok(())
+}
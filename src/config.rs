@@ -0,0 +1,930 @@
+use crate::{
+    apikeys, archive, budget, channel_counter, digest, events, http_auth, http_client, images,
+    links, logging,
+    marne::{self, map_image, MarneServerInfo},
+    nickname, status_embed, watchlist, webhooks, whenplay,
+};
+use rand::Rng;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::{gateway::ActivityData, model::id::ChannelId};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::{Mutex, PoisonError},
+    time,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Static {
+    pub token: String,
+    pub server_name: Option<String>,
+    pub server_id: Option<i64>,
+    /// extra servers to monitor next to `server_name`/`server_id`
+    pub servers: Vec<ServerTarget>,
+    /// run a bot per entry in this process instead of the one of `token`, see `Bot`
+    pub bots: Vec<Bot>,
+    /// how server names are matched against the server list
+    pub name_matching: NameMatching,
+    /// most edits between the configured and actual name with fuzzy matching
+    pub fuzzy_max_distance: usize,
+    /// where the admin slash commands are registered
+    pub command_scope: CommandScope,
+    /// how the presence shows multiple servers
+    pub presence: PresenceMode,
+    /// presence of a single server, see `presence_text` for the placeholders
+    pub presence_format: String,
+    /// shown before the presence text
+    pub activity_type: ActivityType,
+    pub groups: Vec<ServerGroup>,
+    /// suggest another server of a group when one is full
+    pub overflow: events::Overflow,
+    pub artwork: Artwork,
+    pub game: Option<String>,
+    /// base urls of the marne.io API and its mirrors, the next one is used when one keeps failing
+    pub marne_urls: Vec<String>,
+    pub set_banner_image: bool,
+    pub mins_between_avatar_change: i32,
+    /// how the avatar is drawn from the map image, in order
+    pub avatar_steps: Vec<images::Step>,
+    /// player count drawn on the avatar
+    pub avatar_players: images::AvatarPlayers,
+    /// colors, position and size of the mode on the avatar
+    pub avatar_theme: images::AvatarTheme,
+    /// font files of the images in order of preference, the built-in font is the last fallback
+    pub fonts: Vec<String>,
+    /// seconds between updates, at least `MIN_UPDATE_INTERVAL_SECS`
+    pub update_interval_secs: u64,
+    /// (optional) random extra seconds added to every update interval
+    pub update_jitter_secs: u64,
+    pub scrim: Scrim,
+    /// channel and role that get the server events
+    pub alerts: events::Alerts,
+    /// updates a server has to be missing from the server list before it counts as offline
+    pub offline_after_updates: u32,
+    /// announcement when a server starts seeding
+    pub seeding: events::Seeding,
+    /// announcement when a server goes up to a top spot of the server list
+    pub ranking: events::Ranking,
+    /// discord actions per hour before warning
+    pub budgets: budget::Budgets,
+    /// privacy settings per guild id
+    pub privacy: HashMap<String, Privacy>,
+    /// publish the events in announcement channels per guild id, set with `/crosspost`
+    pub crosspost: HashMap<String, bool>,
+    pub redactions: Vec<Redaction>,
+    /// SQLite database with the player count history
+    pub history_db: String,
+    /// exit when marne.io or Discord keep failing for this long, disabled if not set
+    pub hard_fail_after_mins: Option<i64>,
+    /// check GitHub for new releases on startup and daily
+    pub check_for_updates: bool,
+    /// (optional) channel that is notified about new releases
+    pub owner_channel_id: Option<u64>,
+    /// (optional) channel that gets the warnings and errors of the monitor loop
+    pub log_channel_id: Option<u64>,
+    /// (optional) channel that gets a summary of the config on startup and of the run on shutdown
+    pub summary_channel_id: Option<u64>,
+    /// (optional) DSN of a Sentry project that gets the panics and errors, read on startup
+    pub sentry_dsn: Option<String>,
+    /// set to false to not start the HTTP server with the health check, metrics and API
+    pub health_enabled: bool,
+    /// address the HTTP server listens on
+    pub health_bind: String,
+    pub health_port: u16,
+    /// the health check fails when the last update is longer ago than this
+    pub health_stale_minutes: i64,
+    /// networks like `10.0.0.0/8` that may use `/metrics` and the `/api/` routes, everyone if
+    /// empty. The health check stays open
+    pub health_allowlist: Vec<String>,
+    /// HTML page with the servers, their players and mods on `/` for browsers
+    pub status_page: bool,
+    /// token or username and password of the private routes of the HTTP server
+    pub http_auth: http_auth::HttpAuth,
+    /// API keys of the HTTP API
+    pub api: apikeys::Api,
+    /// weekly summary of the history posted in a channel
+    pub digest: digest::Digest,
+    /// timeouts and proxy of outgoing requests
+    pub http_client: http_client::HttpClient,
+    /// log level and format, read on startup
+    pub logging: logging::Logging,
+    /// message in a channel that is kept up to date with the status
+    pub status_embed: status_embed::StatusEmbed,
+    /// channel renamed to the player count
+    pub channel_counter: channel_counter::ChannelCounter,
+    /// nickname of the bot set to the player count
+    pub nickname: nickname::Nickname,
+    /// poll for the time to play, see `/whenplay`
+    pub whenplay: whenplay::WhenPlay,
+    /// urls that get the events of the monitored servers as JSON
+    pub webhooks: Vec<webhooks::Webhook>,
+    /// raw server details kept for later backfills
+    pub archive: archive::Archive,
+    /// get the player lists every update to record who joined and left for `/recent`, needs
+    /// the history
+    pub track_players: bool,
+    /// set by the bot, members and their in-game names for `/online`, see `/link`
+    pub links: Vec<links::Link>,
+    /// set by the bot, players members get a DM about, see `/watch`
+    pub watches: Vec<watchlist::Watch>,
+    /// set to false to only use the status message, not the presence and avatar
+    pub update_presence: bool,
+}
+
+/// `MyConfig` implements `Default`
+impl ::std::default::Default for Static {
+    fn default() -> Self {
+        Self {
+            token: "".into(),
+            server_name: None,
+            server_id: None,
+            servers: vec![],
+            bots: vec![],
+            name_matching: NameMatching::Exact,
+            fuzzy_max_distance: 3,
+            command_scope: CommandScope::Global,
+            presence: PresenceMode::Rotate,
+            presence_format: "{players}/{max} - {map}".into(),
+            activity_type: ActivityType::Playing,
+            groups: vec![],
+            overflow: events::Overflow::default(),
+            artwork: Artwork::default(),
+            game: Some("bf1".into()),
+            marne_urls: vec![marne::DEFAULT_API_URL.into()],
+            set_banner_image: true,
+            mins_between_avatar_change: 1,
+            avatar_steps: images::default_steps(),
+            avatar_players: images::AvatarPlayers::default(),
+            avatar_theme: images::AvatarTheme::default(),
+            fonts: vec![],
+            update_interval_secs: 60,
+            update_jitter_secs: 0,
+            scrim: Scrim::default(),
+            alerts: events::Alerts::default(),
+            offline_after_updates: 3,
+            seeding: events::Seeding::default(),
+            ranking: events::Ranking::default(),
+            budgets: budget::Budgets::default(),
+            privacy: HashMap::new(),
+            crosspost: HashMap::new(),
+            redactions: vec![],
+            history_db: "history.db".into(),
+            hard_fail_after_mins: None,
+            check_for_updates: true,
+            owner_channel_id: None,
+            log_channel_id: None,
+            summary_channel_id: None,
+            sentry_dsn: None,
+            health_enabled: true,
+            health_bind: "0.0.0.0".into(),
+            health_port: 3030,
+            health_stale_minutes: 5,
+            health_allowlist: vec![],
+            status_page: false,
+            http_auth: http_auth::HttpAuth::default(),
+            api: apikeys::Api::default(),
+            digest: digest::Digest::default(),
+            http_client: http_client::HttpClient::default(),
+            logging: logging::Logging::default(),
+            status_embed: status_embed::StatusEmbed::default(),
+            channel_counter: channel_counter::ChannelCounter::default(),
+            nickname: nickname::Nickname::default(),
+            whenplay: whenplay::WhenPlay::default(),
+            webhooks: vec![],
+            archive: archive::Archive::default(),
+            track_players: false,
+            links: vec![],
+            watches: vec![],
+            update_presence: true,
+        }
+    }
+}
+
+/// Lowest allowed `update_interval_secs`, to stay friendly to marne.io and discord
+pub const MIN_UPDATE_INTERVAL_SECS: u64 = 15;
+
+impl Static {
+    /// Address of the HTTP server, `None` if it is disabled or the address is invalid
+    pub fn health_addr(&self) -> Option<SocketAddr> {
+        if !self.health_enabled {
+            return None;
+        }
+        match self.health_bind.parse::<IpAddr>() {
+            Ok(ip) => Some(SocketAddr::new(ip, self.health_port)),
+            Err(e) => {
+                log::error!("Invalid health_bind {}: {}", self.health_bind, e);
+                None
+            }
+        }
+    }
+
+    /// Time until the next update, the interval with a random part of the jitter
+    pub fn update_interval(&self) -> time::Duration {
+        let jitter = match self.update_jitter_secs {
+            0 => 0,
+            jitter => rand::thread_rng().gen_range(0..=jitter),
+        };
+        time::Duration::from_secs(self.update_interval_secs.max(MIN_UPDATE_INTERVAL_SECS) + jitter)
+    }
+
+    /// All monitored servers, `server_name`/`server_id` first
+    pub fn targets(&self) -> Vec<ServerTarget> {
+        let mut targets = vec![];
+        if self.server_name.is_some() || self.server_id.is_some() {
+            targets.push(ServerTarget {
+                name: self.server_name.clone(),
+                id: self.server_id,
+            });
+        }
+        targets.extend(self.servers.iter().cloned());
+        targets
+    }
+
+    /// The channel, role and message ids of the settings by name
+    fn discord_ids(&mut self) -> Vec<(&'static str, &mut Option<u64>)> {
+        vec![
+            ("owner_channel_id", &mut self.owner_channel_id),
+            ("log_channel_id", &mut self.log_channel_id),
+            ("summary_channel_id", &mut self.summary_channel_id),
+            ("scrim.staff_channel_id", &mut self.scrim.staff_channel_id),
+            ("alerts.channel_id", &mut self.alerts.channel_id),
+            ("alerts.role_id", &mut self.alerts.role_id),
+            ("seeding.channel_id", &mut self.seeding.channel_id),
+            ("seeding.role_id", &mut self.seeding.role_id),
+            ("digest.channel_id", &mut self.digest.channel_id),
+            ("status_embed.channel_id", &mut self.status_embed.channel_id),
+            ("status_embed.message_id", &mut self.status_embed.message_id),
+            (
+                "channel_counter.channel_id",
+                &mut self.channel_counter.channel_id,
+            ),
+        ]
+    }
+
+    /// Leaves out the ids set to 0, Discord ids are never 0 and serenity panics on them
+    fn without_zero_ids(mut self) -> Self {
+        for (name, id) in self.discord_ids() {
+            if *id == Some(0) {
+                log::warn!("{} is 0, leaving it unset", name);
+                *id = None;
+            }
+        }
+        self
+    }
+}
+
+/// The channel of an id of the config, `None` if it isn't set or 0
+pub fn channel_id(id: Option<u64>) -> Option<ChannelId> {
+    id.filter(|id| *id != 0).map(ChannelId::new)
+}
+
+/// A bot of the supervisor mode with its own token and server. The bots share the marne.io
+/// polling, the history and the HTTP server, the other settings apply to all of them
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Bot {
+    pub token: String,
+    pub server_name: Option<String>,
+    pub server_id: Option<i64>,
+}
+
+/// A server to monitor, found by name or id
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ServerTarget {
+    pub name: Option<String>,
+    pub id: Option<i64>,
+}
+
+impl ServerTarget {
+    /// How far the server is from the target, `None` if it doesn't match and 0 for an exact
+    /// match. Targets without a name, or with an empty one like in the template, only match on id
+    pub fn distance(
+        &self,
+        server: &MarneServerInfo,
+        matching: NameMatching,
+        max_distance: usize,
+    ) -> Option<usize> {
+        let name = match &self.name {
+            Some(name) if !name.trim().is_empty() => name,
+            _ => return (self.id == Some(server.id)).then_some(0),
+        };
+        if &server.name == name {
+            return Some(0);
+        }
+        let wanted = name.trim().to_lowercase();
+        let actual = server.name.trim().to_lowercase();
+        match matching {
+            NameMatching::Exact => None,
+            NameMatching::CaseInsensitive => (wanted == actual).then_some(1),
+            NameMatching::Substring => actual
+                .contains(&wanted)
+                .then(|| actual.chars().count() - wanted.chars().count() + 1),
+            NameMatching::Fuzzy => {
+                let distance = levenshtein(&wanted, &actual);
+                (distance <= max_distance).then_some(distance + 1)
+            }
+        }
+    }
+}
+
+/// Where the slash commands are registered
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandScope {
+    /// all commands are global, Discord can take up to an hour to show changes
+    Global,
+    /// the admin commands are registered per guild and show up right away
+    Guild,
+}
+
+/// How a configured server name is matched against the names in the server list
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NameMatching {
+    /// the names have to be the same
+    Exact,
+    /// ignores case and leading or trailing spaces
+    CaseInsensitive,
+    /// the server name contains the configured name, ignoring case
+    Substring,
+    /// allows up to `fuzzy_max_distance` edits, ignoring case
+    Fuzzy,
+}
+
+/// Amount of single character edits to get from one string to the other
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How the presence shows multiple monitored servers
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceMode {
+    /// show the servers one after another every update
+    Rotate,
+    /// show the player count of all servers together
+    Sum,
+    /// show the player count of the groups one after another every update
+    Group,
+}
+
+/// Word Discord shows before the presence text
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityType {
+    /// `Playing 34/64 - Amiens`
+    Playing,
+    /// `Watching 34/64 - Amiens`
+    Watching,
+    /// `Listening to 34/64 - Amiens`
+    Listening,
+    /// `Competing in 34/64 - Amiens`
+    Competing,
+}
+
+impl ActivityType {
+    pub fn activity(&self, text: impl Into<String>) -> ActivityData {
+        match self {
+            ActivityType::Playing => ActivityData::playing(text),
+            ActivityType::Watching => ActivityData::watching(text),
+            ActivityType::Listening => ActivityData::listening(text),
+            ActivityType::Competing => ActivityData::competing(text),
+        }
+    }
+}
+
+/// Named set of monitored servers shown with their combined population
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ServerGroup {
+    pub name: String,
+    /// names or ids of the monitored servers in the group
+    pub servers: Vec<String>,
+}
+
+impl ServerGroup {
+    pub fn members<'a>(&self, servers: &'a [MarneServerInfo]) -> Vec<&'a MarneServerInfo> {
+        servers
+            .iter()
+            .filter(|server| {
+                self.servers
+                    .iter()
+                    .any(|member| member == &server.name || member == &server.id.to_string())
+            })
+            .collect()
+    }
+}
+
+/// Replacement image of a map with where it came from
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MapArtwork {
+    pub url: String,
+    pub attribution: Option<String>,
+}
+
+/// Map images, the built-in ones with optional replacements per internal map name
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Artwork {
+    /// show the attribution of the map image in embed footers
+    pub show_attribution: bool,
+    /// attribution of the built-in images
+    pub default_attribution: String,
+    pub maps: HashMap<String, MapArtwork>,
+}
+
+impl ::std::default::Default for Artwork {
+    fn default() -> Self {
+        Self {
+            show_attribution: false,
+            default_attribution: "Map artwork: EA DICE, via gametools.network".into(),
+            maps: HashMap::new(),
+        }
+    }
+}
+
+impl Artwork {
+    pub fn image(&self, internal_map: &str) -> Option<String> {
+        match self.maps.get(internal_map) {
+            Some(artwork) => Some(artwork.url.clone()),
+            None => map_image(internal_map),
+        }
+    }
+
+    pub fn attribution<'a>(&'a self, internal_map: &str) -> Option<&'a str> {
+        match self.maps.get(internal_map) {
+            Some(artwork) => artwork.attribution.as_deref(),
+            None => Some(self.default_attribution.as_str()),
+        }
+    }
+
+    /// The attribution for embed footers, if they should show it
+    pub fn footer<'a>(&'a self, internal_map: &str) -> Option<&'a str> {
+        match self.show_attribution {
+            true => self.attribution(internal_map),
+            false => None,
+        }
+    }
+}
+
+/// Tournament/scrim mode, toggled by admins with `/scrim`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Scrim {
+    pub enabled: bool,
+    pub poll_interval_secs: u64,
+    pub staff_channel_id: Option<u64>,
+}
+
+impl ::std::default::Default for Scrim {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval_secs: 20,
+            staff_channel_id: None,
+        }
+    }
+}
+
+/// Per-guild switches for showing player names on public surfaces, set with `/privacy`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Privacy {
+    pub roster: bool,
+    pub join_leave_log: bool,
+    pub dashboard: bool,
+    /// only show player counts and team sizes
+    pub anonymized: bool,
+}
+
+impl ::std::default::Default for Privacy {
+    fn default() -> Self {
+        Self {
+            roster: true,
+            join_leave_log: true,
+            dashboard: true,
+            anonymized: false,
+        }
+    }
+}
+
+impl Privacy {
+    pub fn shows_names(&self, surface: Surface) -> bool {
+        !self.anonymized
+            && match surface {
+                Surface::Roster => self.roster,
+                Surface::JoinLeaveLog => self.join_leave_log,
+                Surface::Dashboard => self.dashboard,
+            }
+    }
+}
+
+/// Public places player names can show up
+#[derive(Clone, Copy, Debug)]
+pub enum Surface {
+    Roster,
+    JoinLeaveLog,
+    Dashboard,
+}
+
+/// Regex based rule to hide or mask player names
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Redaction {
+    pub pattern: String,
+    /// replaces the matched part of the name, hides the whole name if not set
+    pub replacement: Option<String>,
+}
+
+/// Compiled redaction rules, applied to every player name before it is shown
+pub struct Redactor {
+    rules: Vec<(Regex, Option<String>)>,
+}
+
+impl Redactor {
+    pub fn new(redactions: &[Redaction]) -> Self {
+        let mut rules = vec![];
+        for redaction in redactions {
+            match Regex::new(&redaction.pattern) {
+                Ok(regex) => rules.push((regex, redaction.replacement.clone())),
+                Err(e) => log::error!("Invalid redaction pattern {}: {}", redaction.pattern, e),
+            }
+        }
+        Self { rules }
+    }
+
+    /// Returns the redacted name, `None` if the name has to be hidden
+    pub fn apply(&self, name: &str) -> Option<String> {
+        let mut name = name.to_string();
+        for (regex, replacement) in &self.rules {
+            if !regex.is_match(&name) {
+                continue;
+            }
+            match replacement {
+                Some(replacement) => name = regex.replace_all(&name, replacement).into_owned(),
+                None => return None,
+            }
+        }
+        Some(name)
+    }
+}
+
+/// Config files as they were last read without errors, by path
+static LAST_GOOD: Mutex<BTreeMap<String, Static>> = Mutex::new(BTreeMap::new());
+
+/// Commented config with the common settings, written by `generate-config`
+pub const TEMPLATE: &str = include_str!("config_template.txt");
+
+/// Problems of a config file as messages that say what to change, empty if it is fine. Unlike
+/// loading the config this doesn't create or change the file
+pub fn check(path: &str) -> Vec<String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            return vec![format!(
+                "Can't read {}: {}. Create one with `generate-config`",
+                path, e
+            )]
+        }
+    };
+    let raw: toml::Value = match toml::from_str(&text) {
+        Ok(raw) => raw,
+        Err(e) => return vec![format!("{} isn't valid TOML: {}", path, e)],
+    };
+    let mut cfg: Static = match toml::from_str(&text) {
+        Ok(cfg) => cfg,
+        Err(e) => return vec![format!("{} has a setting of the wrong type: {}", path, e)],
+    };
+    let mut problems = vec![];
+    if let Ok(known) = serde_json::to_value(Static::default()) {
+        unknown_keys(&raw, &known, "", &mut problems);
+    }
+    let from_env = |name: &str| {
+        env::var(name).is_ok() || env::var(format!("{}{}", ENV_PREFIX, name.to_uppercase())).is_ok()
+    };
+    if cfg.bots.is_empty() {
+        if cfg.token.is_empty() && !from_env("token") {
+            problems.push(
+                "`token` is empty, set the token of the bot from the Discord developer portal or the `token` environment variable".into(),
+            );
+        }
+        let no_target = cfg.targets().iter().all(|target| {
+            target.name.as_deref().unwrap_or_default().is_empty() && target.id.is_none()
+        });
+        if no_target && !from_env("server_name") && !from_env("server_id") {
+            problems.push(
+                "Neither `server_name` nor `server_id` is set, the bot has no server to monitor"
+                    .into(),
+            );
+        }
+    }
+    for (index, bot) in cfg.bots.iter().enumerate() {
+        if bot.token.is_empty() {
+            problems.push(format!("`token` of bot {} in `bots` is empty", index + 1));
+        }
+        if bot.server_name.is_none() && bot.server_id.is_none() {
+            problems.push(format!(
+                "Bot {} in `bots` has neither `server_name` nor `server_id`",
+                index + 1
+            ));
+        }
+    }
+    if cfg.update_interval_secs < MIN_UPDATE_INTERVAL_SECS {
+        problems.push(format!(
+            "`update_interval_secs` of {} is below {}, {} is used instead",
+            cfg.update_interval_secs, MIN_UPDATE_INTERVAL_SECS, MIN_UPDATE_INTERVAL_SECS
+        ));
+    }
+    if let Some(ref game) = cfg.game {
+        if !["bf1", "bfv"].contains(&game.as_str()) {
+            problems.push(format!("`game` is `{}`, use `bf1` or `bfv`", game));
+        }
+    }
+    if cfg.health_enabled && cfg.health_bind.parse::<IpAddr>().is_err() {
+        problems.push(format!(
+            "`health_bind` `{}` isn't an IP address, like `0.0.0.0` or `127.0.0.1`",
+            cfg.health_bind
+        ));
+    }
+    if cfg.http_auth.username.is_some() != cfg.http_auth.password.is_some() {
+        problems.push(
+            "`http_auth` needs both a `username` and a `password` for basic auth".to_string(),
+        );
+    }
+    for font in &cfg.fonts {
+        if !std::path::Path::new(font).is_file() {
+            problems.push(format!("Font `{}` in `fonts` doesn't exist", font));
+        }
+    }
+    for url in &cfg.marne_urls {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            problems.push(format!("`marne_urls` entry `{}` isn't an http(s) url", url));
+        }
+    }
+    for webhook in &cfg.webhooks {
+        if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+            problems.push(format!(
+                "Webhook url `{}` isn't an http(s) url",
+                webhook.url
+            ));
+        }
+    }
+    for redaction in &cfg.redactions {
+        if let Err(e) = Regex::new(&redaction.pattern) {
+            problems.push(format!(
+                "Redaction pattern `{}` is invalid: {}",
+                redaction.pattern, e
+            ));
+        }
+    }
+    for (name, id) in cfg.discord_ids() {
+        if *id == Some(0) {
+            problems.push(format!(
+                "`{}` is 0, which isn't a Discord id. Leave it out to turn it off",
+                name
+            ));
+        }
+    }
+    problems
+}
+
+/// Adds the keys of the file that aren't settings, like typos that would silently be ignored
+fn unknown_keys(
+    raw: &toml::Value,
+    known: &serde_json::Value,
+    prefix: &str,
+    problems: &mut Vec<String>,
+) {
+    let (table, fields) = match (raw, known) {
+        (toml::Value::Table(table), serde_json::Value::Object(fields)) => (table, fields),
+        _ => return,
+    };
+    // maps like `privacy` have their own keys
+    if fields.is_empty() {
+        return;
+    }
+    for (key, value) in table {
+        let name = format!("{}{}", prefix, key);
+        match fields.get(key) {
+            Some(known) => unknown_keys(value, known, &format!("{}.", name), problems),
+            None => problems.push(format!(
+                "Unknown setting `{}`, check its spelling in the Readme",
+                name
+            )),
+        }
+    }
+}
+
+/// Held while config.txt is changed, so commands saving at the same time don't undo each other
+static CONFIG_FILE: Mutex<()> = Mutex::new(());
+
+/// Changes settings in config.txt: reads the file, applies `change` and stores it. The
+/// environment variables don't end up in the file, like with `load`
+pub fn update(change: impl FnOnce(&mut Static)) -> anyhow::Result<()> {
+    let _file = CONFIG_FILE.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut cfg = confy::load_path::<Static>("config.txt")?;
+    change(&mut cfg);
+    confy::store_path("config.txt", cfg)?;
+    Ok(())
+}
+
+/// Reads config.txt again for the settings changed while running, see `reload_path`
+pub fn reload() -> Static {
+    reload_path("config.txt")
+}
+
+/// Reads a config file again with the environment variables applied, like `load`. A broken file,
+/// like one that is being edited, gives the config as it was last read fine instead of the
+/// defaults
+pub fn reload_path(path: &str) -> Static {
+    let cfg = match confy::load_path::<Static>(path) {
+        Ok(cfg) => {
+            LAST_GOOD
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(path.to_string(), cfg.clone());
+            cfg
+        }
+        Err(e) => {
+            log::error!("error in {}, using the last working config: {}", path, e);
+            LAST_GOOD
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(path)
+                .cloned()
+                .unwrap_or_default()
+        }
+    };
+    env_overrides(env_vars(cfg)).without_zero_ids()
+}
+
+/// Loads config.txt with the environment variables applied, stores it without the
+/// `MARNE_BOT_` overrides
+pub fn load() -> Static {
+    let _file = CONFIG_FILE.lock().unwrap_or_else(PoisonError::into_inner);
+    let cfg: Static = match confy::load_path("config.txt") {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("error in config.txt: {}", e);
+            log::warn!("changing back to default..");
+            Static::default()
+        }
+    };
+    let cfg = env_vars(cfg);
+    // the MARNE_BOT_ variables aren't written to config.txt, so secrets stay out of it
+    if let Err(e) = confy::store_path("config.txt", cfg.clone()) {
+        log::warn!("Couldn't store config.txt: {}", e);
+    }
+    let mut cfg = env_overrides(cfg).without_zero_ids();
+    if cfg.update_interval_secs < MIN_UPDATE_INTERVAL_SECS {
+        log::warn!(
+            "update_interval_secs of {} is too low, using {}",
+            cfg.update_interval_secs,
+            MIN_UPDATE_INTERVAL_SECS
+        );
+        cfg.update_interval_secs = MIN_UPDATE_INTERVAL_SECS;
+    }
+    cfg
+}
+
+/// The environment variables without the prefix, like `token` and `server_name`
+fn env_vars(mut cfg: Static) -> Static {
+    cfg.token = match env::var("token") {
+        Ok(res) => res,
+        Err(_) => cfg.token,
+    };
+    cfg.game = match env::var("game") {
+        Ok(res) => Some(res),
+        Err(_) => cfg.game,
+    };
+    cfg.set_banner_image = match env::var("set_banner_image") {
+        Ok(res) => match res.as_str() {
+            "true" => true,
+            "t" => true,
+            "false" => false,
+            "f" => false,
+            _ => true,
+        },
+        Err(_) => cfg.set_banner_image,
+    };
+    cfg.hard_fail_after_mins = match env::var("hard_fail_after_mins") {
+        Ok(res) => res.parse::<i64>().ok(),
+        Err(_) => cfg.hard_fail_after_mins,
+    };
+    if env::var("server_name").is_ok() || env::var("server_id").is_ok() {
+        cfg.server_name = match env::var("server_name") {
+            Ok(res) => Some(res),
+            Err(_) => {
+                cfg.server_id = match env::var("server_id") {
+                    Ok(res) => Some(res.parse::<i64>().unwrap_or_default()),
+                    Err(_) => None,
+                };
+                None
+            }
+        };
+    }
+    cfg.update_interval_secs = match env::var("update_interval_secs") {
+        Ok(res) => res.parse::<u64>().unwrap_or(cfg.update_interval_secs),
+        Err(_) => cfg.update_interval_secs,
+    };
+    cfg
+}
+
+/// Prefix of the environment variables that override config.txt
+pub const ENV_PREFIX: &str = "MARNE_BOT_";
+
+/// Applies every `MARNE_BOT_<FIELD>` environment variable on top of the config, nested fields
+/// are separated by a double underscore (`MARNE_BOT_ALERTS__CHANNEL_ID`). Values are read as
+/// JSON when that fits the field (numbers, booleans, lists), otherwise as plain text
+fn env_overrides(cfg: Static) -> Static {
+    let mut overrides: Vec<(String, String)> = env::vars()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(ENV_PREFIX)
+                .map(|field| (field.to_lowercase(), value))
+        })
+        .collect();
+    // deterministic order, parents before their nested fields
+    overrides.sort();
+
+    let mut config = match serde_json::to_value(&cfg) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!(
+                "Couldn't apply the {} environment variables: {}",
+                ENV_PREFIX,
+                e
+            );
+            return cfg;
+        }
+    };
+    for (field, value) in overrides {
+        let path: Vec<&str> = field.split("__").collect();
+        if config.get(path[0]).is_none() {
+            log::warn!(
+                "Ignoring {}{}: no such setting",
+                ENV_PREFIX,
+                field.to_uppercase()
+            );
+            continue;
+        }
+        let parsed = serde_json::from_str(&value).ok();
+        let applied = parsed
+            .into_iter()
+            .chain([serde_json::Value::String(value.clone())])
+            .find_map(|value| {
+                let mut candidate = config.clone();
+                set_path(&mut candidate, &path, value);
+                serde_json::from_value::<Static>(candidate.clone())
+                    .ok()
+                    .map(|_| candidate)
+            });
+        match applied {
+            Some(candidate) => {
+                log::info!(
+                    "{}{} overrides config.txt",
+                    ENV_PREFIX,
+                    field.to_uppercase()
+                );
+                config = candidate;
+            }
+            None => log::warn!(
+                "Ignoring {}{}: {:?} isn't a valid value for it",
+                ENV_PREFIX,
+                field.to_uppercase(),
+                value
+            ),
+        }
+    }
+    serde_json::from_value(config).unwrap_or(cfg)
+}
+
+/// Sets the value at the path of object keys, creating the objects along the way
+fn set_path(config: &mut serde_json::Value, path: &[&str], value: serde_json::Value) {
+    let mut current = config;
+    for key in path {
+        if !current.is_object() {
+            *current = serde_json::Value::Object(serde_json::Map::new());
+        }
+        current = match current {
+            serde_json::Value::Object(object) => object
+                .entry(key.to_string())
+                .or_insert(serde_json::Value::Null),
+            _ => return,
+        };
+    }
+    *current = value;
+}
@@ -0,0 +1,168 @@
+use crate::{
+    budget::{self, RateBudget},
+    config,
+    history::{History, PeriodStats},
+    marne::map_name,
+};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateEmbed, CreateMessage},
+    client::Context,
+    model::channel::ChannelType,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time,
+};
+
+/// Weekly summary of the player count history, posted (and published) in a channel
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Digest {
+    pub channel_id: Option<u64>,
+    /// day of the week to post on, like `monday`
+    pub weekday: String,
+    /// hour of the day (UTC) to post at
+    pub hour: u32,
+    /// publish the digest to following servers when the channel is an announcement channel
+    pub publish: bool,
+    /// set by the bot, unix time of the last digest
+    pub last_posted: Option<i64>,
+}
+
+impl ::std::default::Default for Digest {
+    fn default() -> Self {
+        Self {
+            channel_id: None,
+            weekday: "monday".into(),
+            hour: 18,
+            publish: true,
+            last_posted: None,
+        }
+    }
+}
+
+impl Digest {
+    fn due(&self, now: DateTime<Utc>) -> bool {
+        let weekday = self.weekday.parse().unwrap_or(Weekday::Mon);
+        // a week minus a day, so a late post doesn't push the next one back
+        let posted_this_week = self
+            .last_posted
+            .is_some_and(|time| now.timestamp() - time < 6 * 24 * 60 * 60);
+        now.weekday() == weekday && now.hour() >= self.hour && !posted_this_week
+    }
+}
+
+/// Only one digest loop, `ready` runs again after a reconnect
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn top_maps(stats: &PeriodStats) -> String {
+    stats
+        .top_maps
+        .iter()
+        .map(|(map, samples)| format!("{} ({}%)", map_name(map), samples * 100 / stats.samples))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The digest of the week before `now`, `None` without history for that week
+fn embed(history: &History, now: DateTime<Utc>) -> Result<Option<CreateEmbed>> {
+    let week = history.period_stats(None, now - Duration::days(7), now)?;
+    if week.samples == 0 {
+        return Ok(None);
+    }
+    let previous = history.period_stats(None, now - Duration::days(14), now - Duration::days(7))?;
+    let mut average = format!("{:.1}", week.average_players);
+    if previous.samples > 0 {
+        average += &format!(
+            " ({:+.1} on the week before)",
+            week.average_players - previous.average_players
+        );
+    }
+    Ok(Some(
+        CreateEmbed::new()
+            .title("Weekly digest")
+            .description(format!(
+                "{} to {}",
+                (now - Duration::days(7)).format("%Y-%m-%d"),
+                now.format("%Y-%m-%d")
+            ))
+            .field("Average players", average, true)
+            .field("Peak", week.peak_players.to_string(), true)
+            .field("Top maps", top_maps(&week), false),
+    ))
+}
+
+async fn post(
+    ctx: &Context,
+    digest: &Digest,
+    budget: &RateBudget,
+    embed: CreateEmbed,
+) -> Result<()> {
+    let channel_id = match config::channel_id(digest.channel_id) {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let message = channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await?;
+    budget.record_channel(&ctx.cache, channel_id, budget::Action::Message);
+
+    let guild_id = match message.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let news = ctx.cache.guild(guild_id).is_some_and(|guild| {
+        guild
+            .channels
+            .get(&channel_id)
+            .is_some_and(|channel| channel.kind == ChannelType::News)
+    });
+    if !news || !digest.publish {
+        return Ok(());
+    }
+    // the alerts share the 10 published messages per hour of discord
+    if !budget.allows(Some(guild_id), budget::Action::Crosspost) {
+        log::warn!("Crosspost budget used up, the weekly digest isn't published");
+        return Ok(());
+    }
+    message.crosspost(&ctx.http).await?;
+    budget.record(Some(guild_id), budget::Action::Crosspost);
+    Ok(())
+}
+
+/// Posts the weekly digest on the configured day, checked every 10 minutes
+pub async fn watch(
+    ctx: Context,
+    mut digest: Digest,
+    history: Arc<History>,
+    budget: Arc<RateBudget>,
+) {
+    if RUNNING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    loop {
+        let now = Utc::now();
+        if digest.due(now) {
+            match embed(&history, now) {
+                Ok(Some(embed)) => match post(&ctx, &digest, &budget, embed).await {
+                    Ok(_) => log::info!("Posted the weekly digest"),
+                    Err(e) => log::error!("Failed to post the weekly digest: {}", e),
+                },
+                Ok(None) => log::warn!("No history for the weekly digest"),
+                Err(e) => log::error!("Failed to read history: {}", e),
+            }
+            // don't retry every 10 minutes when posting fails
+            digest.last_posted = Some(now.timestamp());
+            if let Err(e) = config::update(|cfg| cfg.digest.last_posted = digest.last_posted) {
+                log::error!("Failed to save the digest time: {}", e);
+            }
+        }
+        tokio::time::sleep(time::Duration::from_secs(10 * 60)).await;
+    }
+}
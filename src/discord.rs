@@ -0,0 +1,3163 @@
+use crate::{
+    apikeys::ApiKeys,
+    archive, budget, channel_counter,
+    config::{
+        self, Artwork, Bot, CommandScope, PresenceMode, Privacy, Redactor, ServerGroup,
+        ServerTarget, Static, Surface,
+    },
+    digest, error_reporting, events, fill_rate, health,
+    health::Watchdog,
+    history, http_client,
+    images::{self, gen_img},
+    links::Links,
+    locales, log_channel, maps,
+    marne::{
+        self, internal_map, map_name, mode_name, population, small_mode, MarneServerInfo, Player,
+    },
+    nickname, server_browser, sessions, status_embed, status_page, summary, update, version,
+    watchlist::Watchlist,
+    webhooks, whenplay,
+};
+use anyhow::Result;
+use chrono::Utc;
+use sentry::SentryFutureExt;
+use serenity::{
+    builder::{
+        CreateActionRow, CreateAttachment, CreateButton, CreateCommand, CreateCommandOption,
+        CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+        CreateInteractionResponseMessage, CreateMessage, EditProfile,
+    },
+    client::{Client, Context, EventHandler},
+    gateway::{ConnectionStage, ShardStageUpdateEvent},
+    http::Http,
+    model::{
+        application::{
+            Command, CommandInteraction, CommandOptionType, ComponentInteraction, Interaction,
+            ResolvedValue,
+        },
+        gateway::Ready,
+        guild::Guild,
+        id::{GuildId, UserId},
+        Permissions,
+    },
+    prelude::GatewayIntents,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Add,
+    sync::{atomic, Arc, RwLock},
+    time,
+};
+use tracing::Instrument;
+
+struct Handler {
+    scrim: Arc<atomic::AtomicBool>,
+    privacy: Arc<RwLock<HashMap<String, Privacy>>>,
+    crosspost: Arc<RwLock<HashMap<String, bool>>>,
+    redactor: Arc<Redactor>,
+    history: Option<Arc<history::History>>,
+    watchdog: Arc<Watchdog>,
+    latest: Arc<RwLock<Latest>>,
+    build_info: Arc<version::BuildInfo>,
+    events: Arc<events::EventBus>,
+    /// `game` from the config, see `game_detection` for the one in use
+    game: Option<String>,
+    /// game of the server list the monitored servers were found in
+    game_detection: Arc<marne::GameDetection>,
+    budget: Arc<budget::RateBudget>,
+    server_metrics: Arc<health::ServerMetrics>,
+    last_status: Arc<health::LastStatus>,
+    api_keys: Arc<ApiKeys>,
+    groups: Vec<ServerGroup>,
+    artwork: Artwork,
+    /// `server_name`/`server_id`, changed with `/setserver`
+    primary: Arc<RwLock<ServerTarget>>,
+    /// `/whenplay` polls that are open or waiting for their seeding alert
+    polls: Arc<RwLock<Vec<whenplay::PendingPoll>>>,
+    /// members and their in-game names, set with `/link`
+    links: Arc<Links>,
+    /// `track_players` from the config, sessions for `/recent` are recorded
+    track_players: bool,
+    /// players members get a DM about, set with `/watch`
+    watchlist: Arc<Watchlist>,
+    /// minute of the last update for the health check, shared by the bots
+    last_update: Arc<atomic::AtomicI64>,
+    /// index in `bots` in the supervisor mode
+    bot: Option<usize>,
+    /// set by the first `ready`, which starts the monitor loop and the background tasks
+    started: Arc<atomic::AtomicBool>,
+    /// context of the latest `ready`, the monitor loop switches to it after a reconnect
+    context: Arc<RwLock<Option<Context>>>,
+}
+
+impl Handler {
+    /// The handler of a bot of `bots`. It shares the settings changed with commands, the
+    /// history and the budget with the other bots, the first bot also takes over the
+    /// `/whenplay` polls
+    fn for_bot(&self, cfg: &Static, index: usize, bot: &Bot) -> Self {
+        Self {
+            scrim: Arc::clone(&self.scrim),
+            privacy: Arc::clone(&self.privacy),
+            crosspost: Arc::clone(&self.crosspost),
+            redactor: Arc::clone(&self.redactor),
+            history: self.history.clone(),
+            watchdog: Arc::new(Watchdog::default()),
+            latest: Arc::new(RwLock::new(Latest::default())),
+            build_info: Arc::clone(&self.build_info),
+            events: Arc::new(events::EventBus::new(
+                cfg.alerts.clone(),
+                cfg.seeding.clone(),
+                Arc::clone(&self.budget),
+                Arc::clone(&self.crosspost),
+            )),
+            game: self.game.clone(),
+            game_detection: Arc::new(marne::GameDetection::default()),
+            budget: Arc::clone(&self.budget),
+            server_metrics: Arc::new(health::ServerMetrics::default()),
+            last_status: Arc::new(health::LastStatus::default()),
+            api_keys: Arc::clone(&self.api_keys),
+            // a bot monitors a single server
+            groups: vec![],
+            artwork: self.artwork.clone(),
+            primary: Arc::new(RwLock::new(ServerTarget {
+                name: bot.server_name.clone(),
+                id: bot.server_id,
+            })),
+            polls: match index {
+                0 => Arc::clone(&self.polls),
+                _ => Arc::new(RwLock::new(vec![])),
+            },
+            links: Arc::clone(&self.links),
+            track_players: self.track_players,
+            watchlist: Arc::clone(&self.watchlist),
+            last_update: Arc::clone(&self.last_update),
+            bot: Some(index),
+            started: Arc::new(atomic::AtomicBool::new(false)),
+            context: Arc::new(RwLock::new(None)),
+        }
+    }
+}
+
+/// Players shown per page of `/players`
+const ROSTER_PAGE_SIZE: usize = 20;
+/// Custom ids are limited to 100 characters, the filter is cut to fit
+const MAX_ROSTER_FILTER_LENGTH: usize = 50;
+
+/// Order of the players of `/players`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RosterSort {
+    /// split by team, by name within a team
+    Team,
+    Name,
+}
+
+/// Server, page, order and name filter of `/players`, kept in the custom id of its buttons so the
+/// buttons work after a restart
+#[derive(Clone, Debug)]
+struct RosterQuery {
+    server_id: i64,
+    page: usize,
+    sort: RosterSort,
+    filter: Option<String>,
+}
+
+impl RosterQuery {
+    /// Like `players:1:team:1234:abc`, the filter goes last as it may contain a `:`
+    fn custom_id(&self, page: usize, sort: RosterSort) -> String {
+        format!(
+            "players:{}:{}:{}:{}",
+            page,
+            match sort {
+                RosterSort::Team => "team",
+                RosterSort::Name => "name",
+            },
+            self.server_id,
+            self.filter.as_deref().unwrap_or_default()
+        )
+    }
+
+    fn from_custom_id(custom_id: &str) -> Option<Self> {
+        let mut parts = custom_id.strip_prefix("players:")?.splitn(4, ':');
+        let page = parts.next()?.parse().ok()?;
+        let sort = match parts.next()? {
+            "name" => RosterSort::Name,
+            _ => RosterSort::Team,
+        };
+        let server_id = parts.next()?.parse().ok()?;
+        let filter = Some(parts.next()?.to_string()).filter(|filter| !filter.is_empty());
+        Some(Self {
+            server_id,
+            page,
+            sort,
+            filter,
+        })
+    }
+}
+
+/// Latest info of the monitored servers, shared with the slash commands
+#[derive(Default)]
+pub struct Latest {
+    pub servers: Vec<MarneServerInfo>,
+    /// id of the server shown in the presence and avatar
+    pub shown: Option<i64>,
+}
+
+/// If player names may be shown on a public surface of a guild. Surfaces outside of a guild,
+/// like the status page, need every guild to allow them
+fn shows_names(
+    scrim: &atomic::AtomicBool,
+    privacy: &RwLock<HashMap<String, Privacy>>,
+    guild_id: Option<GuildId>,
+    surface: Surface,
+) -> bool {
+    if scrim.load(atomic::Ordering::Relaxed) {
+        return false;
+    }
+    let privacy = privacy.read().unwrap();
+    match guild_id {
+        Some(guild_id) => privacy
+            .get(&guild_id.to_string())
+            .cloned()
+            .unwrap_or_default()
+            .shows_names(surface),
+        None => privacy.values().all(|privacy| privacy.shows_names(surface)),
+    }
+}
+
+/// The name to show for a player on a public surface of a guild, `None` if it has to be hidden
+pub fn public_name(
+    scrim: &atomic::AtomicBool,
+    privacy: &RwLock<HashMap<String, Privacy>>,
+    redactor: &Redactor,
+    guild_id: Option<GuildId>,
+    surface: Surface,
+    name: &str,
+) -> Option<String> {
+    match shows_names(scrim, privacy, guild_id, surface) {
+        true => redactor.apply(name),
+        false => None,
+    }
+}
+
+impl Handler {
+    /// If player names may be shown on a public surface of a guild, see `shows_names`
+    pub fn shows_names(&self, guild_id: Option<GuildId>, surface: Surface) -> bool {
+        shows_names(&self.scrim, &self.privacy, guild_id, surface)
+    }
+
+    /// The name to show for a player on a public surface, see `public_name`
+    pub fn public_name(
+        &self,
+        guild_id: Option<GuildId>,
+        surface: Surface,
+        name: &str,
+    ) -> Option<String> {
+        public_name(
+            &self.scrim,
+            &self.privacy,
+            &self.redactor,
+            guild_id,
+            surface,
+            name,
+        )
+    }
+
+    /// `/status`, an embed per monitored server with the avatar image on the shown server
+    async fn status(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let (mut servers, shown) = {
+            let latest = self.latest.read().unwrap();
+            (latest.servers.clone(), latest.shown)
+        };
+        if servers.is_empty() {
+            return ephemeral("No server info yet, try again in a minute".to_string());
+        }
+        let mut message = CreateInteractionResponseMessage::new();
+        let mut embeds = vec![];
+        let group_name = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_str());
+        if let Some(group_name) = group_name {
+            let group = match self.groups.iter().find(|group| group.name == group_name) {
+                Some(group) => group,
+                None => return ephemeral(format!("Unknown group `{}`", group_name)),
+            };
+            servers = group.members(&servers).into_iter().cloned().collect();
+            embeds.push(
+                CreateEmbed::new()
+                    .title(&group.name)
+                    .description(population(&servers.iter().collect::<Vec<_>>())),
+            );
+        }
+        // discord allows up to 10 embeds per message
+        for server in servers.iter().take(10 - embeds.len()) {
+            let mods = self.last_status.mods(server.id).map(|(_, mods)| mods.len());
+            let mut embed = server_embed(server, &self.artwork, mods);
+            if shown == Some(server.id) {
+                if let Some(avatar) = self.last_status.avatar() {
+                    message = message.add_file(CreateAttachment::bytes(avatar, "map_mode.jpg"));
+                    embed = embed.image("attachment://map_mode.jpg");
+                }
+            }
+            embeds.push(embed);
+        }
+        message.embeds(embeds)
+    }
+
+    /// A monitored server by name, the first one if no name is given
+    fn find_server(&self, name: Option<&str>) -> Option<MarneServerInfo> {
+        let latest = self.latest.read().unwrap();
+        match name {
+            Some(name) => latest.servers.iter().find(|server| server.name == name),
+            None => latest.servers.first(),
+        }
+        .cloned()
+    }
+
+    /// `/players [server] [filter] [sort]`, the current players split by team or by name
+    async fn players(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let option = |name: &str| {
+            command
+                .data
+                .options
+                .iter()
+                .find(|option| option.name == name)
+                .and_then(|option| option.value.as_str())
+        };
+        let server = match self.find_server(option("server")) {
+            Some(server) => server,
+            None => return ephemeral("Server not found, try again in a minute".to_string()),
+        };
+        let query = RosterQuery {
+            server_id: server.id,
+            page: 0,
+            sort: match option("sort") {
+                Some("name") => RosterSort::Name,
+                _ => RosterSort::Team,
+            },
+            filter: option("filter")
+                .map(|filter| filter.chars().take(MAX_ROSTER_FILTER_LENGTH).collect()),
+        };
+        self.roster(command.guild_id, &query)
+            .await
+            .unwrap_or_else(ephemeral)
+    }
+
+    /// A page of the players of a server with buttons to the other pages and the other order,
+    /// names follow the privacy and redaction settings. `Err` has the message for the user
+    async fn roster(
+        &self,
+        guild_id: Option<GuildId>,
+        query: &RosterQuery,
+    ) -> Result<CreateInteractionResponseMessage, String> {
+        let detail = match marne::server_detail(
+            &self.game_detection.game(&self.game),
+            query.server_id,
+        )
+        .await
+        {
+            Ok(detail) => detail,
+            Err(e) => {
+                log::error!("Failed to get server details: {}", e);
+                return Err("Failed to get the players from marne.io".to_string());
+            }
+        };
+        let players = detail.players.players();
+        let mut team_sizes: BTreeMap<i64, usize> = BTreeMap::new();
+        let mut shown: Vec<(i64, String)> = vec![];
+        let mut hidden = 0;
+        for player in players {
+            *team_sizes.entry(player.team).or_default() += 1;
+            match self.public_name(guild_id, Surface::Roster, &player.name) {
+                Some(name) => shown.push((player.team, name)),
+                None => hidden += 1,
+            }
+        }
+        // hidden names can't be searched for
+        if let Some(ref filter) = query.filter {
+            let filter = filter.to_lowercase();
+            shown.retain(|(_, name)| name.to_lowercase().contains(&filter));
+        }
+        match query.sort {
+            RosterSort::Team => shown.sort_by_key(|(team, name)| (*team, name.to_lowercase())),
+            RosterSort::Name => shown.sort_by_key(|(_, name)| name.to_lowercase()),
+        }
+
+        let pages = shown.len().div_ceil(ROSTER_PAGE_SIZE).max(1);
+        let page = query.page.min(pages - 1);
+        let entries = shown
+            .iter()
+            .skip(page * ROSTER_PAGE_SIZE)
+            .take(ROSTER_PAGE_SIZE);
+        let team_name = |team: i64| {
+            maps::team_name(
+                internal_map(&detail.info.map_name),
+                &detail.info.game_mode,
+                team,
+            )
+        };
+        let mut embed = CreateEmbed::new().title(format!(
+            "{} - {}/{}",
+            detail.info.name,
+            players.len(),
+            detail.info.max_players
+        ));
+        match query.sort {
+            RosterSort::Team => {
+                let mut teams: BTreeMap<i64, Vec<&str>> = BTreeMap::new();
+                for (team, name) in entries {
+                    teams.entry(*team).or_default().push(name);
+                }
+                for (team, names) in teams {
+                    embed = embed.field(
+                        format!("{} ({})", team_name(team), team_sizes[&team]),
+                        names.join("\n"),
+                        true,
+                    );
+                }
+            }
+            RosterSort::Name => {
+                embed = embed.description(
+                    entries
+                        .map(|(team, name)| format!("{} ({})", name, team_name(*team)))
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                );
+            }
+        }
+        if shown.is_empty() {
+            embed = embed.description(match query.filter {
+                Some(ref filter) => format!("No players match `{}`", filter),
+                None => "No players".to_string(),
+            });
+        }
+        let mut footer = format!("Page {}/{}", page + 1, pages);
+        if let Some(ref filter) = query.filter {
+            footer.push_str(&format!(" · matching {}", filter));
+        }
+        if hidden > 0 {
+            footer.push_str(&format!(" · +{} hidden", hidden));
+        }
+        embed = embed.footer(CreateEmbedFooter::new(footer));
+
+        let other_sort = match query.sort {
+            RosterSort::Team => (RosterSort::Name, "Sort by name"),
+            RosterSort::Name => (RosterSort::Team, "Sort by team"),
+        };
+        let buttons = CreateActionRow::Buttons(vec![
+            CreateButton::new(query.custom_id(page.saturating_sub(1), query.sort))
+                .label("Previous")
+                .disabled(page == 0),
+            CreateButton::new(query.custom_id(page + 1, query.sort))
+                .label("Next")
+                .disabled(page + 1 >= pages),
+            CreateButton::new(query.custom_id(0, other_sort.0)).label(other_sort.1),
+        ]);
+        Ok(CreateInteractionResponseMessage::new()
+            .embed(embed)
+            .components(vec![buttons]))
+    }
+
+    /// `/servers [game] [region] [min_players] [password]`, a page of the Marne servers
+    async fn servers(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let filter =
+            server_browser::Filter::from_command(command, self.game_detection.game(&self.game));
+        match server_browser::page(&filter, 0).await {
+            Ok((embed, buttons)) => CreateInteractionResponseMessage::new()
+                .embed(embed)
+                .components(buttons),
+            Err(e) => {
+                log::error!("Failed to get the server list: {}", e);
+                ephemeral("Failed to get the server list from marne.io".to_string())
+            }
+        }
+    }
+
+    /// The buttons of `/servers` and `/players`, the message is replaced by the page
+    async fn component(&self, ctx: &Context, component: &ComponentInteraction) {
+        let custom_id = &component.data.custom_id;
+        let page = match (
+            server_browser::Filter::from_custom_id(custom_id),
+            RosterQuery::from_custom_id(custom_id),
+        ) {
+            (Some((filter, page)), _) => match server_browser::page(&filter, page).await {
+                Ok((embed, buttons)) => Ok(CreateInteractionResponseMessage::new()
+                    .embed(embed)
+                    .components(buttons)),
+                Err(e) => {
+                    log::error!("Failed to get the server list: {}", e);
+                    Err("Failed to get the server list from marne.io".to_string())
+                }
+            },
+            (None, Some(query)) => self.roster(component.guild_id, &query).await,
+            (None, None) => return,
+        };
+        let response = match page {
+            Ok(page) => CreateInteractionResponse::UpdateMessage(page),
+            Err(text) => CreateInteractionResponse::Message(ephemeral(text)),
+        };
+        if let Err(e) = component.create_response(&ctx.http, response).await {
+            log::error!("Failed to change the page of {}: {:#?}", custom_id, e);
+        }
+    }
+
+    /// `/history [period] [server]`, chart of the player count of the last 24 hours or 7 days
+    async fn history(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let history = match self.history {
+            Some(ref history) => history,
+            None => return ephemeral("History isn't being recorded".to_string()),
+        };
+        let option = |name: &str| {
+            command
+                .data
+                .options
+                .iter()
+                .find(|option| option.name == name)
+                .and_then(|option| option.value.as_str())
+        };
+        let server = match self.find_server(option("server")) {
+            Some(server) => server,
+            None => return ephemeral("Server not found, try again in a minute".to_string()),
+        };
+        // hourly points over a week, 15 minute points over a day
+        let (period, buckets, time_format) = match option("period") {
+            Some("7d") => (chrono::Duration::days(7), 168, "%a %d"),
+            _ => (chrono::Duration::hours(24), 96, "%H:%M"),
+        };
+        let to = Utc::now();
+        let from = to - period;
+        let samples = match history.players(server.id, from, to) {
+            Ok(samples) if !samples.is_empty() => samples,
+            Ok(_) => return ephemeral(format!("No history for {} yet", server.name)),
+            Err(e) => {
+                log::error!("Failed to read history: {}", e);
+                return ephemeral("Failed to read history".to_string());
+            }
+        };
+        let peak = samples
+            .iter()
+            .map(|(_, players)| *players)
+            .max()
+            .unwrap_or_default();
+        let chart = match images::history_chart(
+            &samples,
+            from.timestamp(),
+            to.timestamp(),
+            server.max_players,
+            buckets,
+            time_format,
+        )
+        .await
+        {
+            Ok(chart) => chart,
+            Err(e) => {
+                log::error!("Failed to draw history chart: {}", e);
+                return ephemeral("Failed to draw the chart".to_string());
+            }
+        };
+        let embed = CreateEmbed::new()
+            .title(format!(
+                "{} - last {}",
+                server.name,
+                option("period").unwrap_or("24h")
+            ))
+            .description(format!("Peak: {}/{}", peak, server.max_players))
+            .image("attachment://history.png")
+            .footer(CreateEmbedFooter::new("Times in UTC"));
+        CreateInteractionResponseMessage::new()
+            .add_file(CreateAttachment::bytes(chart, "history.png"))
+            .embed(embed)
+    }
+
+    /// `/rotationstats [days] [server]`, how evenly the maps of the rotation of a server are played
+    fn rotation_stats(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let history = match self.history {
+            Some(ref history) => history,
+            None => return ephemeral("History isn't being recorded".to_string()),
+        };
+        let option = |name: &str| {
+            command
+                .data
+                .options
+                .iter()
+                .find(|option| option.name == name)
+                .map(|option| &option.value)
+        };
+        let days = option("days").and_then(|value| value.as_i64()).unwrap_or(7);
+        let server = match self.find_server(option("server").and_then(|value| value.as_str())) {
+            Some(server) => server,
+            None => return ephemeral("Server not found, try again in a minute".to_string()),
+        };
+        let to = Utc::now();
+        let maps = match history.rotation_stats(server.id, to - chrono::Duration::days(days), to) {
+            Ok(maps) if !maps.is_empty() => maps,
+            Ok(_) => {
+                return ephemeral(format!(
+                    "No history for {} in the last {} days",
+                    server.name, days
+                ))
+            }
+            Err(e) => {
+                log::error!("Failed to read history: {}", e);
+                return ephemeral("Failed to read history".to_string());
+            }
+        };
+        let total_minutes: i64 = maps.iter().map(|map| map.minutes).sum::<i64>().max(1);
+        let fair_share = 100.0 / maps.len() as f64;
+        let lines: Vec<String> = maps
+            .iter()
+            .enumerate()
+            .map(|(rank, map)| {
+                let share = map.minutes as f64 * 100.0 / total_minutes as f64;
+                let mut line = format!(
+                    "{}. **{}** {:.0}% of playtime, {} rounds",
+                    rank + 1,
+                    map_name(&map.map),
+                    share,
+                    map.rounds
+                );
+                if map.skips > 0 {
+                    line += &format!(", {} skipped", map.skips);
+                }
+                match map.average_players_lost() {
+                    Some(lost) if lost >= 0.5 => {
+                        line += &format!("\nloses on average {:.0} players", lost)
+                    }
+                    Some(lost) if lost <= -0.5 => {
+                        line += &format!("\ngains on average {:.0} players", -lost)
+                    }
+                    _ => {}
+                }
+                if map.emptied > 0 {
+                    line += &format!("\n⚠️ emptied the server {} times", map.emptied);
+                }
+                line
+            })
+            .collect();
+        let mut description = String::new();
+        for line in lines {
+            // embed descriptions are limited to 4096 characters
+            if description.len() + line.len() + 1 > 4000 {
+                description += "\n…";
+                break;
+            }
+            description += &line;
+            description += "\n";
+        }
+        let embed = CreateEmbed::new()
+            .title(format!("{} - rotation over the last {} days", server.name, days))
+            .description(description)
+            .footer(CreateEmbedFooter::new(format!(
+                "An even rotation gives every map {:.0}% of the playtime. Skipped: changed within {} min, emptied: a quarter of the players left within {} min, players lost: {} min after the map change",
+                fair_share,
+                history::SKIP_MINUTES,
+                history::DROP_WINDOW_MINUTES,
+                history::POPULATION_AFTER_MINUTES
+            )));
+        CreateInteractionResponseMessage::new().embed(embed)
+    }
+
+    /// `/setserver server`, switches the first monitored server to a name or id and saves it
+    async fn set_server(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        // admins of a guild can hand the command to other roles, changing the server stays theirs
+        let is_admin = command
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| permissions.administrator());
+        if !is_admin {
+            return ephemeral("Only administrators can change the monitored server".to_string());
+        }
+        let value = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_str())
+            .unwrap_or_default()
+            .trim();
+        let target = match value.parse::<i64>() {
+            Ok(id) => ServerTarget {
+                name: None,
+                id: Some(id),
+            },
+            Err(_) => ServerTarget {
+                name: Some(value.to_string()),
+                id: None,
+            },
+        };
+        let cfg: Static = match confy::load_path("config.txt") {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                log::error!("Failed to load config.txt: {}", e);
+                return ephemeral("Failed to load the config, nothing changed".to_string());
+            }
+        };
+        // check the server exists, a typo would leave the bot without a server
+        let status = match marne::server_list(&self.game_detection.game(&self.game)).await {
+            Ok(status) => status,
+            Err(e) => {
+                log::error!("Failed to get the server list: {}", e);
+                return ephemeral("Failed to get the server list from marne.io".to_string());
+            }
+        };
+        let found = status
+            .servers
+            .iter()
+            .filter_map(|server| {
+                target
+                    .distance(server, cfg.name_matching, cfg.fuzzy_max_distance)
+                    .map(|distance| (distance, server))
+            })
+            .min_by_key(|(distance, _)| *distance);
+        let server_name = match found {
+            Some((_, server)) => server.name.clone(),
+            None => {
+                return ephemeral(format!(
+                "No server matching `{}` is in the server list, the monitored server isn't changed",
+                value
+            ))
+            }
+        };
+        let saved =
+            config::update(
+                |cfg| match self.bot.and_then(|index| cfg.bots.get_mut(index)) {
+                    Some(bot) => {
+                        bot.server_name = target.name.clone();
+                        bot.server_id = target.id;
+                    }
+                    None => {
+                        cfg.server_name = target.name.clone();
+                        cfg.server_id = target.id;
+                    }
+                },
+            );
+        if let Err(e) = saved {
+            log::error!("Failed to save the monitored server: {}", e);
+        }
+        *self.primary.write().unwrap() = target;
+        log::info!(
+            "{} switched the monitored server to {}",
+            command.user.name,
+            server_name
+        );
+        ephemeral(format!(
+            "Now monitoring **{}**, the status follows with the next update",
+            server_name
+        ))
+    }
+
+    /// `/whenplay [slots]`, posts a poll for the time to play in the channel of the command
+    async fn when_play(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> CreateInteractionResponseMessage {
+        if self.bot.is_some_and(|index| index > 0) {
+            return ephemeral("Polls are run by the first bot of this community".to_string());
+        }
+        let guild_id = match command.guild_id {
+            Some(guild_id) => guild_id,
+            None => return ephemeral("Polls can only be posted in a server".to_string()),
+        };
+        let slots = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_str());
+        let cfg = config::reload();
+        match whenplay::create(
+            ctx,
+            &cfg.whenplay,
+            &self.polls,
+            &self.budget,
+            guild_id,
+            command.channel_id,
+            slots,
+        )
+        .await
+        {
+            Ok(reply) => ephemeral(reply),
+            Err(e) => {
+                log::error!("Failed to post the /whenplay poll: {}", e);
+                ephemeral(format!("Failed to post the poll: {}", e))
+            }
+        }
+    }
+
+    /// `/mods [server]`, the mods needed to join with their download links
+    async fn mods(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let name = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_str());
+        let server = match self.find_server(name) {
+            Some(server) => server,
+            None => return ephemeral("Server not found, try again in a minute".to_string()),
+        };
+        let detail =
+            match marne::server_detail(&self.game_detection.game(&self.game), server.id).await {
+                Ok(detail) => detail,
+                Err(e) => {
+                    log::error!("Failed to get server details: {}", e);
+                    return ephemeral("Failed to get the mods from marne.io".to_string());
+                }
+            };
+        let mods = detail.mods.mods();
+        let mut embed = CreateEmbed::new().title(format!("{} - mods", server.name));
+        if mods.is_empty() {
+            embed = embed.description("This server doesn't need any mods");
+        }
+        // discord allows up to 25 fields per embed
+        for item in mods.iter().take(25) {
+            embed = embed.field(
+                format!("{} ({})", item.name, item.version),
+                format!("{}\n[{}]({})", item.category, item.file_name, item.link),
+                false,
+            );
+        }
+        if server.need_same_mods != 0 {
+            embed = embed.footer(CreateEmbedFooter::new(
+                "You need exactly these mods to join",
+            ));
+        } else if server.allow_more_mods != 0 {
+            embed = embed.footer(CreateEmbedFooter::new("Other mods are allowed too"));
+        }
+        CreateInteractionResponseMessage::new().embed(embed)
+    }
+
+    /// `/peak [server] [weeks]`, heatmap of the average player count per day of the week and
+    /// hour of the day
+    async fn peak(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let history = match self.history {
+            Some(ref history) => history,
+            None => return ephemeral("History isn't being recorded".to_string()),
+        };
+        let options = command.data.options();
+        let name = options
+            .iter()
+            .find(|option| option.name == "server")
+            .and_then(|option| match option.value {
+                ResolvedValue::String(name) => Some(name),
+                _ => None,
+            });
+        let weeks = options
+            .iter()
+            .find(|option| option.name == "weeks")
+            .and_then(|option| match option.value {
+                ResolvedValue::Integer(weeks) => Some(weeks),
+                _ => None,
+            })
+            .unwrap_or(4);
+        let server = match self.find_server(name) {
+            Some(server) => server,
+            None => return ephemeral("Server not found, try again in a minute".to_string()),
+        };
+        let since = Utc::now() - chrono::Duration::weeks(weeks);
+        let peak = match history.peak_hours(server.id, since) {
+            Ok(peak) => peak,
+            Err(e) => {
+                log::error!("Failed to read history: {}", e);
+                return ephemeral("Failed to read history".to_string());
+            }
+        };
+        let (day, hour, players) = match peak.busiest() {
+            Some(busiest) => busiest,
+            None => return ephemeral(format!("No history for {} yet", server.name)),
+        };
+        let chart = match images::peak_heatmap(&peak.averages, server.max_players).await {
+            Ok(chart) => chart,
+            Err(e) => {
+                log::error!("Failed to draw peak chart: {}", e);
+                return ephemeral("Failed to draw the chart".to_string());
+            }
+        };
+        const DAYS: [&str; 7] = [
+            "Monday",
+            "Tuesday",
+            "Wednesday",
+            "Thursday",
+            "Friday",
+            "Saturday",
+            "Sunday",
+        ];
+        let embed = CreateEmbed::new()
+            .title(format!("{} - busiest hours", server.name))
+            .description(format!(
+                "Fullest on {} at {:02}:00 with {:.0}/{} players on average",
+                DAYS[day], hour, players, server.max_players
+            ))
+            .image("attachment://peak.png")
+            .footer(CreateEmbedFooter::new(format!(
+                "Average of the last {} weeks, times in UTC",
+                weeks
+            )));
+        CreateInteractionResponseMessage::new()
+            .embed(embed)
+            .add_file(CreateAttachment::bytes(chart, "peak.png"))
+    }
+
+    /// `/population [by_region]`, the players on all servers of the game, for when the own
+    /// server is empty
+    fn population(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let population = match marne::community_population() {
+            Some(population) => population,
+            None => return ephemeral("No server info yet, try again in a minute".to_string()),
+        };
+        let by_region = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_bool())
+            .unwrap_or_default();
+        let mut embed = CreateEmbed::new()
+            .title(format!("{} Marne servers", population.game.to_uppercase()))
+            .description(format!(
+                "**{}** players on **{}** servers",
+                population.players, population.servers
+            ));
+        if by_region {
+            let mut regions: Vec<(&String, &(i64, usize))> = population.regions.iter().collect();
+            regions.sort_by_key(|(_, (players, _))| std::cmp::Reverse(*players));
+            // discord allows up to 25 fields per embed
+            for (region, (players, servers)) in regions.into_iter().take(25) {
+                let region = match region.is_empty() {
+                    true => "Unknown",
+                    false => region.as_str(),
+                };
+                embed = embed.field(
+                    region,
+                    format!("{} players on {} servers", players, servers),
+                    true,
+                );
+            }
+        }
+        CreateInteractionResponseMessage::new().embed(embed)
+    }
+
+    /// `/uptime [server]`, how much of the last 24 hours, 7 days and 30 days a monitored server
+    /// was in the server list
+    fn uptime(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let history = match self.history {
+            Some(ref history) => history,
+            None => return ephemeral("History isn't being recorded".to_string()),
+        };
+        let name = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_str());
+        let server = match self.find_server(name) {
+            Some(server) => server,
+            None => return ephemeral("Server not found, try again in a minute".to_string()),
+        };
+        let uptime = match history.uptimes(server.id) {
+            Ok(uptime) => uptime,
+            Err(e) => {
+                log::error!("Failed to get uptime: {}", e);
+                return ephemeral("Failed to read the history".to_string());
+            }
+        };
+        let percentage = |uptime: Option<f64>| match uptime {
+            Some(uptime) => format!("{:.2}%", uptime),
+            None => "no data".to_string(),
+        };
+        let embed = CreateEmbed::new()
+            .title(format!("{} - uptime", server.name))
+            .field("24 hours", percentage(uptime.day), true)
+            .field("7 days", percentage(uptime.week), true)
+            .field("30 days", percentage(uptime.month), true)
+            .footer(CreateEmbedFooter::new(
+                "Share of the updates the server was in the marne.io server list",
+            ));
+        CreateInteractionResponseMessage::new().embed(embed)
+    }
+
+    /// `/recent [server]`, the players that joined a monitored server in the last hour and how
+    /// long they stayed
+    fn recent(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let history = match self.history {
+            Some(ref history) => history,
+            None => return ephemeral("History isn't being recorded".to_string()),
+        };
+        if !self.track_players {
+            return ephemeral("Player sessions aren't being tracked".to_string());
+        }
+        let name = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_str());
+        let server = match self.find_server(name) {
+            Some(server) => server,
+            None => return ephemeral("Server not found, try again in a minute".to_string()),
+        };
+        let sessions =
+            match history.recent_sessions(server.id, Utc::now() - chrono::Duration::hours(1)) {
+                Ok(sessions) => sessions,
+                Err(e) => {
+                    log::error!("Failed to read player sessions: {}", e);
+                    return ephemeral("Failed to read the history".to_string());
+                }
+            };
+        let mut hidden = 0;
+        let mut lines = vec![];
+        for session in &sessions {
+            let name =
+                match self.public_name(command.guild_id, Surface::JoinLeaveLog, &session.name) {
+                    Some(name) => name,
+                    None => {
+                        hidden += 1;
+                        continue;
+                    }
+                };
+            let stayed = match session.left {
+                Some(left) => format!("stayed {}m", (left - session.joined) / 60),
+                None => "still on".to_string(),
+            };
+            lines.push(format!("`{}` <t:{}:R>, {}", name, session.joined, stayed));
+        }
+        let mut description = match sessions.is_empty() {
+            true => "Nobody joined in the last hour".to_string(),
+            false => lines.join("\n"),
+        };
+        if hidden > 0 {
+            description = format!("{}\n*{} hidden*", description, hidden);
+        }
+        // discord allows up to 4096 characters in an embed description
+        if description.chars().count() > 4096 {
+            description = format!("{}...", description.chars().take(4093).collect::<String>());
+        }
+        let embed = CreateEmbed::new()
+            .title(format!("{} - joined in the last hour", server.name))
+            .description(description);
+        CreateInteractionResponseMessage::new().embed(embed)
+    }
+
+    /// `/link player:<name>`, links the member to their in-game name for `/online`
+    fn link(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let player = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_str())
+            .unwrap_or_default()
+            .trim();
+        if player.is_empty() {
+            return ephemeral("Give your in-game name".to_string());
+        }
+        match self.links.set(command.user.id.get(), player) {
+            Ok(_) => ephemeral(format!(
+                "Linked to `{}`, you now show up in /online while playing",
+                player
+            )),
+            Err(e) => ephemeral(format!("Failed to link: {}", e)),
+        }
+    }
+
+    /// `/unlink`, removes the link of the member
+    fn unlink(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        match self.links.remove(command.user.id.get()) {
+            Ok(true) => ephemeral("Unlinked, you no longer show up in /online".to_string()),
+            Ok(false) => ephemeral("You aren't linked".to_string()),
+            Err(e) => ephemeral(format!("Failed to unlink: {}", e)),
+        }
+    }
+
+    /// `/watch add|remove|list`, players the member gets a DM about when they join a monitored
+    /// server
+    fn watch(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let user_id = command.user.id.get();
+        for option in command.data.options() {
+            let options = match option.value {
+                ResolvedValue::SubCommand(options) => options,
+                _ => continue,
+            };
+            let player = options
+                .iter()
+                .find(|option| option.name == "player")
+                .and_then(|option| match option.value {
+                    ResolvedValue::String(player) => Some(player.trim()),
+                    _ => None,
+                })
+                .filter(|player| !player.is_empty());
+            return match (option.name, player) {
+                ("add", Some(player)) => match self.watchlist.add(user_id, player) {
+                    Ok(true) => ephemeral(format!(
+                        "Watching `{}`, you get a DM when they join a monitored server. Make sure the bot can DM you",
+                        player
+                    )),
+                    Ok(false) => ephemeral(format!("You already watch `{}`", player)),
+                    Err(e) => ephemeral(format!("Failed to watch: {}", e)),
+                },
+                ("add", None) => ephemeral("Give the in-game name of the player".to_string()),
+                ("remove", player) => match self.watchlist.remove(user_id, player) {
+                    Ok(0) => ephemeral("You don't watch that player".to_string()),
+                    Ok(removed) => match player {
+                        Some(player) => ephemeral(format!("Stopped watching `{}`", player)),
+                        None => ephemeral(format!("Stopped watching {} players", removed)),
+                    },
+                    Err(e) => ephemeral(format!("Failed to remove: {}", e)),
+                },
+                _ => {
+                    let players = self.watchlist.players(user_id);
+                    match players.is_empty() {
+                        true => ephemeral("You don't watch any players".to_string()),
+                        false => ephemeral(format!("You watch: {}", players.join(", "))),
+                    }
+                }
+            };
+        }
+        ephemeral("Unknown subcommand".to_string())
+    }
+
+    /// `/online`, the linked members playing on the monitored servers, grouped by server
+    async fn online(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        if !self.shows_names(command.guild_id, Surface::Roster) {
+            return ephemeral("Player names are hidden on this server".to_string());
+        }
+        let servers = self.latest.read().unwrap().servers.clone();
+        if servers.is_empty() {
+            return ephemeral("No server info yet, try again in a minute".to_string());
+        }
+        let game = self.game_detection.game(&self.game);
+        let mut embed = CreateEmbed::new().title("Online now");
+        let mut online = 0;
+        let mut failed = 0;
+        // discord allows up to 25 fields per embed
+        for server in servers.iter().take(25) {
+            let detail = match marne::server_detail(&game, server.id).await {
+                Ok(detail) => detail,
+                Err(e) => {
+                    log::error!("Failed to get server details: {}", e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            let members: Vec<String> = detail
+                .players
+                .players()
+                .iter()
+                .filter_map(|player| {
+                    let user_id = self.links.member(&player.name)?;
+                    let name = self.public_name(command.guild_id, Surface::Roster, &player.name)?;
+                    Some(format!("<@{}> as {}", user_id, name))
+                })
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+            online += members.len();
+            embed = embed.field(
+                format!(
+                    "{} ({}/{})",
+                    server.name, server.current_players, server.max_players
+                ),
+                members.join("\n"),
+                false,
+            );
+        }
+        if online == 0 {
+            embed = embed.description("None of the linked members are playing right now");
+        }
+        if failed > 0 {
+            embed = embed.footer(CreateEmbedFooter::new(format!(
+                "{} of the servers didn't answer",
+                failed
+            )));
+        }
+        CreateInteractionResponseMessage::new().embed(embed)
+    }
+
+    /// `/debug budgets`, the discord actions of the last hour against their budget
+    fn debug(&self) -> CreateInteractionResponseMessage {
+        let source = format!(
+            "marne.io API: {}",
+            marne::source().unwrap_or("no answer yet".to_string())
+        );
+        let usage = self.budget.usage();
+        if usage.is_empty() {
+            return ephemeral(format!("{}\nNo discord actions in the last hour", source));
+        }
+        let lines: Vec<String> = usage
+            .iter()
+            .map(|usage| {
+                format!(
+                    "{}: {} {}/{}",
+                    usage
+                        .guild_id
+                        .map_or("bot".to_string(), |guild_id| guild_id.to_string()),
+                    usage.action,
+                    usage.used,
+                    usage.limit
+                )
+            })
+            .collect();
+        ephemeral(format!("{}\nLast hour:\n{}", source, lines.join("\n")))
+    }
+
+    /// `/simulate event:<server_full|offline|map_change>`, only for the owner of the bot
+    async fn simulate(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> CreateInteractionResponseMessage {
+        if let Some(denied) = owner_only(ctx, command, "simulate events").await {
+            return denied;
+        }
+        let name = command
+            .data
+            .options
+            .first()
+            .and_then(|option| option.value.as_str())
+            .unwrap_or_default();
+        let server = match self.latest.read().unwrap().servers.first() {
+            Some(server) => server.clone(),
+            None => return ephemeral("No server info yet, try again in a minute".to_string()),
+        };
+        let event = match events::Event::simulated(name, &server) {
+            Some(event) => event,
+            None => return ephemeral(format!("Unknown event `{}`", name)),
+        };
+        match self.events.publish(ctx, &event, true).await {
+            Ok(_) => ephemeral(format!("Sent simulated event: {}", event.message())),
+            Err(e) => ephemeral(format!("Failed to send simulated event: {}", e)),
+        }
+    }
+
+    /// `/apikey create|revoke|list`, keys for the HTTP API
+    async fn api_key(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> CreateInteractionResponseMessage {
+        if let Some(denied) = owner_only(ctx, command, "manage API keys").await {
+            return denied;
+        }
+        for option in command.data.options() {
+            let options = match option.value {
+                ResolvedValue::SubCommand(options) => options,
+                _ => continue,
+            };
+            let name = options
+                .iter()
+                .find(|option| option.name == "name")
+                .and_then(|option| match option.value {
+                    ResolvedValue::String(name) => Some(name),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            let requests_per_minute = options
+                .iter()
+                .find(|option| option.name == "requests_per_minute")
+                .and_then(|option| match option.value {
+                    ResolvedValue::Integer(limit) => usize::try_from(limit).ok(),
+                    _ => None,
+                });
+            return match option.name {
+                "create" => match self.api_keys.create(name, requests_per_minute) {
+                    Ok(key) => ephemeral(format!(
+                        "API key `{}` created, it isn't shown again:\n`{}`\nSend it in the `X-Api-Key` header.",
+                        name, key
+                    )),
+                    Err(e) => ephemeral(format!("Failed to create the key: {}", e)),
+                },
+                "revoke" => match self.api_keys.revoke(name) {
+                    Ok(true) => ephemeral(format!("API key `{}` revoked", name)),
+                    Ok(false) => ephemeral(format!("No API key named `{}`", name)),
+                    Err(e) => ephemeral(format!("Failed to revoke the key: {}", e)),
+                },
+                _ => {
+                    let names = self.api_keys.names();
+                    match names.is_empty() {
+                        true => ephemeral("No API keys".to_string()),
+                        false => ephemeral(format!("API keys: {}", names.join(", "))),
+                    }
+                }
+            };
+        }
+        ephemeral("Unknown subcommand".to_string())
+    }
+
+    /// `/report compare first:<YYYY-MM> second:<YYYY-MM> [server]`
+    fn report(&self, command: &CommandInteraction) -> CreateInteractionResponseMessage {
+        let history = match self.history {
+            Some(ref history) => history,
+            None => return ephemeral("History isn't being recorded".to_string()),
+        };
+        let mut months = vec![];
+        let mut name = None;
+        for option in command.data.options() {
+            if let ResolvedValue::SubCommand(options) = option.value {
+                for option in options {
+                    match (option.name, option.value) {
+                        ("server", ResolvedValue::String(server)) => name = Some(server),
+                        (_, ResolvedValue::String(month)) => months.push(month.to_string()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let server = match self.find_server(name) {
+            Some(server) => server,
+            None => return ephemeral("Server not found, try again in a minute".to_string()),
+        };
+        let mut embed = CreateEmbed::new().title(format!("{} - comparison", server.name));
+        for month in months {
+            let stats = match history::month_range(&month) {
+                Some((from, to)) => history.period_stats(Some(server.id), from, to),
+                None => return ephemeral(format!("`{}` isn't a month like 2024-05", month)),
+            };
+            let value = match stats {
+                Ok(stats) if stats.samples > 0 => {
+                    let top_maps: Vec<String> = stats
+                        .top_maps
+                        .iter()
+                        .map(|(map, samples)| {
+                            format!("{} ({}%)", map_name(map), samples * 100 / stats.samples)
+                        })
+                        .collect();
+                    let mut value = format!(
+                        "Average players: {:.1}\nPeak: {}\n",
+                        stats.average_players, stats.peak_players
+                    );
+                    // the sessions are only recorded with `track_players`
+                    if self.track_players {
+                        value += &format!("Unique players: {}\n", stats.unique_players);
+                    }
+                    value + &format!("Top maps:\n{}", top_maps.join("\n"))
+                }
+                Ok(_) => "No history for this month".to_string(),
+                Err(e) => {
+                    log::error!("Failed to read history: {}", e);
+                    "Failed to read history".to_string()
+                }
+            };
+            embed = embed.field(month, value, true);
+        }
+        CreateInteractionResponseMessage::new().embed(embed)
+    }
+}
+
+/// The round currently played on the monitored server
+pub struct Round {
+    pub map: String,
+    pub game_mode: String,
+    pub started: chrono::DateTime<Utc>,
+    pub peak_players: i64,
+    pub max_players: i64,
+}
+
+#[serenity::async_trait]
+impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        let user = ctx.cache.current_user().clone();
+        log::info!("Logged in as {:#?}", user.name);
+
+        let mut cfg = config::reload();
+        if let Some(index) = self.bot {
+            // a bot of `bots` only monitors its own server, the first one also posts in the
+            // configured channels
+            let primary = self.primary.read().unwrap().clone();
+            cfg.server_name = primary.name;
+            cfg.server_id = primary.id;
+            cfg.servers = vec![];
+            cfg.groups = vec![];
+            if index > 0 {
+                cfg.status_embed.channel_id = None;
+                cfg.channel_counter.channel_id = None;
+                cfg.digest.channel_id = None;
+                cfg.log_channel_id = None;
+                cfg.summary_channel_id = None;
+                cfg.check_for_updates = false;
+            }
+        }
+        let guilds: Vec<GuildId> = ready.guilds.iter().map(|guild| guild.id).collect();
+        register_commands(&ctx.http, cfg.command_scope, &guilds).await;
+
+        // serenity sends `ready` again after a reconnect, the loop and tasks are already running
+        *self.context.write().unwrap() = Some(ctx.clone());
+        if self.started.swap(true, atomic::Ordering::Relaxed) {
+            log::info!("Reconnected, the monitor loop keeps running");
+            return;
+        }
+
+        let targets = cfg.targets();
+        for target in &targets {
+            if let Some(ref server_name) = target.name {
+                log::info!("Started monitoring server with name: {}", server_name);
+            } else if let Some(server_id) = target.id {
+                log::info!("Started monitoring server with id: {}", server_id);
+            }
+        }
+        if targets.is_empty() {
+            log::error!("No server name or id set!");
+        }
+
+        // the summary covers all bots, the first one posts it
+        if self.bot.unwrap_or_default() == 0 {
+            let ctx = ctx.clone();
+            let cfg = cfg.clone();
+            let report = self.build_info.report();
+            let budget = Arc::clone(&self.budget);
+            tokio::spawn(async move { summary::startup(&ctx, &cfg, &report, &budget).await });
+        }
+        if let Some(channel_id) = cfg.log_channel_id {
+            tokio::spawn(log_channel::watch(
+                ctx.clone(),
+                channel_id,
+                Arc::clone(&self.budget),
+            ));
+        }
+        if cfg.check_for_updates {
+            tokio::spawn(update::watch(Arc::clone(&ctx.http), cfg.owner_channel_id));
+        }
+        if let (Some(_), Some(history)) = (cfg.digest.channel_id, &self.history) {
+            tokio::spawn(digest::watch(
+                ctx.clone(),
+                cfg.digest.clone(),
+                Arc::clone(history),
+                Arc::clone(&self.budget),
+            ));
+        }
+
+        tokio::spawn(whenplay::watch(
+            ctx.clone(),
+            cfg.whenplay.clone(),
+            Arc::clone(&self.polls),
+            Arc::clone(&self.events),
+            Arc::clone(&self.budget),
+            cfg.server_name.clone().unwrap_or("Marne".into()),
+        ));
+
+        // loop in seperate async
+        let scrim = Arc::clone(&self.scrim);
+        let privacy = Arc::clone(&self.privacy);
+        let history = self.history.clone();
+        let watchdog = Arc::clone(&self.watchdog);
+        let latest = Arc::clone(&self.latest);
+        let event_bus = Arc::clone(&self.events);
+        let budget = Arc::clone(&self.budget);
+        let server_metrics = Arc::clone(&self.server_metrics);
+        let primary = Arc::clone(&self.primary);
+        let last_status = Arc::clone(&self.last_status);
+        let watchlist = Arc::clone(&self.watchlist);
+        let redactor = Arc::clone(&self.redactor);
+        let last_update = Arc::clone(&self.last_update);
+        let context = Arc::clone(&self.context);
+        let game = self.game.clone();
+        let game_detection = Arc::clone(&self.game_detection);
+        tokio::spawn(
+            async move {
+                let mut ctx = ctx;
+                let mut avatar = AvatarState::new(&cfg);
+                // current round per server id
+                let mut rounds: HashMap<i64, Round> = HashMap::new();
+                let mut rotation: usize = 0;
+                // last overflow suggestion per group
+                let mut overflow_suggested = HashMap::new();
+                // last seeding announcement per server id
+                let mut seeding_announced = HashMap::new();
+                // rank in the previous update and last milestone announcement per server id
+                let mut ranks = HashMap::new();
+                let mut rank_announced = HashMap::new();
+                let mut availability = events::Availability::default();
+                let mut status_message_id = cfg.status_embed.message_id;
+                let mut counter = channel_counter::CounterState::default();
+                // nickname set per guild
+                let mut nicknames = HashMap::new();
+                let mut backoff = marne::Backoff::default();
+                // players of the previous update, for the sessions of `/recent` and the watchlist
+                let mut player_tracker = sessions::PlayerTracker::default();
+                let track_players = cfg.track_players && history.is_some();
+                let webhooks = webhooks::Webhooks::new(cfg.webhooks.clone());
+                // `/banner.png` is only served with the HTTP server
+                let draw_banner = cfg.health_addr().is_some();
+                let status_page = cfg.status_page && draw_banner;
+                let mut cycles: u64 = 0;
+                // `/setserver` changed the first monitored server since the last fetched update
+                let mut retargeted = false;
+                loop {
+                    if let Some(latest) = context.read().unwrap().clone() {
+                        ctx = latest;
+                    }
+                    let scrim_enabled = scrim.load(atomic::Ordering::Relaxed);
+                    {
+                        let primary = primary.read().unwrap();
+                        retargeted |=
+                            cfg.server_name != primary.name || cfg.server_id != primary.id;
+                        cfg.server_name = primary.name.clone();
+                        cfg.server_id = primary.id;
+                    }
+                    // the presence, avatar, status message and events are updated independently,
+                    // a failure of one doesn't stop the others
+                    let cycle = tracing::info_span!("poll", cycle = cycles);
+                    error_reporting::tag("cycle", cycles);
+                    cycles += 1;
+                    let fetch_players = track_players || status_page || !watchlist.is_empty();
+                    summary::STATS.polls.fetch_add(1, atomic::Ordering::Relaxed);
+                    async {
+                        match marne::fetch_servers(&marne::Marne, &cfg, &game_detection)
+                            .instrument(tracing::info_span!("fetch"))
+                            .await
+                        {
+                            Ok(servers) => {
+                                backoff.success();
+                                if !servers.is_empty() {
+                                    let server = shown_server(&cfg, &servers, rotation);
+                                    error_reporting::tag("server_id", server.id);
+                                    error_reporting::tag("map", internal_map(&server.map_name));
+                                }
+                                server_metrics.update(&servers);
+                                fill_rate::FILL_RATES.record(&servers);
+                                let (mod_changes, players) = update_last_status(
+                                    &game_detection.game(&game),
+                                    &last_status,
+                                    &servers,
+                                    cfg.alerts.mod_changes,
+                                    fetch_players,
+                                )
+                                .await;
+                                // the status page is public, it follows scrim mode, the
+                                // dashboard privacy and the redactions like the other surfaces
+                                if status_page
+                                    && shows_names(&scrim, &privacy, None, Surface::Dashboard)
+                                {
+                                    for (server_id, players) in &players {
+                                        last_status.set_players(
+                                            *server_id,
+                                            players
+                                                .iter()
+                                                .filter_map(|player| redactor.apply(&player.name))
+                                                .collect(),
+                                        );
+                                    }
+                                }
+                                let player_changes = match fetch_players {
+                                    true => {
+                                        let mut changes: Vec<(i64, sessions::PlayerChanges)> =
+                                            players
+                                                .iter()
+                                                .map(|(id, players)| {
+                                                    (*id, player_tracker.update(*id, players))
+                                                })
+                                                .collect();
+                                        changes.extend(player_tracker.forget_missing(&servers));
+                                        changes
+                                    }
+                                    // start over once the lists are fetched again
+                                    false => {
+                                        player_tracker = sessions::PlayerTracker::default();
+                                        vec![]
+                                    }
+                                };
+                                watchdog
+                                    .last_marne_ok
+                                    .store(Utc::now().timestamp(), atomic::Ordering::Relaxed);
+                                // the server monitored before isn't offline, it isn't monitored
+                                // anymore
+                                if retargeted {
+                                    availability.forget_missing(&servers);
+                                    latest.write().unwrap().servers.retain(|before| {
+                                        servers.iter().any(|server| server.id == before.id)
+                                    });
+                                    retargeted = false;
+                                }
+                                let availability_changes =
+                                    availability.update(&servers, cfg.offline_after_updates);
+                                // keep the presence of a server that is briefly missing
+                                if cfg.update_presence
+                                    && !(servers.is_empty() && availability.blip())
+                                {
+                                    update_presence(
+                                        &ctx,
+                                        &cfg,
+                                        &servers,
+                                        rotation,
+                                        availability.any_offline(),
+                                    );
+                                    if !servers.is_empty() {
+                                        let server = shown_server(&cfg, &servers, rotation);
+                                        if let Err(e) = update_avatar(
+                                            &ctx,
+                                            &cfg,
+                                            &budget,
+                                            &last_status,
+                                            server,
+                                            &mut avatar,
+                                        )
+                                        .instrument(tracing::info_span!("image"))
+                                        .await
+                                        {
+                                            log::error!("Failed to update avatar: {}", e);
+                                        }
+                                    }
+                                }
+                                if draw_banner {
+                                    if let Err(e) =
+                                        update_banner(&cfg, &last_status, servers.first())
+                                            .instrument(tracing::info_span!("image"))
+                                            .await
+                                    {
+                                        log::error!("Failed to draw the banner: {}", e);
+                                    }
+                                }
+                                if let Some(ref history) = history {
+                                    let states = availability.states(&servers);
+                                    if let Err(e) = history.record_availability(Utc::now(), &states)
+                                    {
+                                        log::error!("Failed to record availability: {}", e);
+                                    }
+                                    for server in &servers {
+                                        match history.uptimes(server.id) {
+                                            Ok(uptime) => last_status.set_uptime(server.id, uptime),
+                                            Err(e) => log::error!("Failed to get uptime: {}", e),
+                                        }
+                                        if status_page {
+                                            let to = Utc::now();
+                                            let from = to - chrono::Duration::hours(24);
+                                            match history.players(server.id, from, to) {
+                                                Ok(samples) => last_status.set_sparkline(
+                                                    server.id,
+                                                    status_page::sparkline(
+                                                        &samples,
+                                                        from.timestamp(),
+                                                        to.timestamp(),
+                                                    ),
+                                                ),
+                                                Err(e) => {
+                                                    log::error!("Failed to read history: {}", e)
+                                                }
+                                            }
+                                        }
+                                    }
+                                    if track_players {
+                                        for (server_id, changes) in &player_changes {
+                                            if let Err(e) = history.record_sessions(
+                                                *server_id,
+                                                changes,
+                                                Utc::now(),
+                                            ) {
+                                                log::error!(
+                                                    "Failed to record player sessions: {}",
+                                                    e
+                                                );
+                                            }
+                                        }
+                                    }
+                                }
+                                notify_watchers(
+                                    &ctx,
+                                    &budget,
+                                    &watchlist,
+                                    &servers,
+                                    &player_changes,
+                                )
+                                .instrument(tracing::info_span!("discord"))
+                                .await;
+                                for server in &servers {
+                                    if let Some(ref history) = history {
+                                        let entry = history::Entry {
+                                            timestamp: Utc::now(),
+                                            server_id: Some(server.id),
+                                            server_name: server.name.clone(),
+                                            players: server.current_players,
+                                            max_players: Some(server.max_players),
+                                            map: Some(internal_map(&server.map_name).to_string()),
+                                            game_mode: Some(server.game_mode.clone()),
+                                            source: "marne-bot".into(),
+                                        };
+                                        if let Err(e) = history.record(&entry) {
+                                            log::error!("Failed to record history: {}", e);
+                                        }
+                                    }
+                                    let round = rounds.remove(&server.id);
+                                    if let Some(round) = track_round(
+                                        &ctx,
+                                        &cfg,
+                                        &budget,
+                                        scrim_enabled,
+                                        round,
+                                        server,
+                                    )
+                                    .await
+                                    {
+                                        rounds.insert(server.id, round);
+                                    }
+                                }
+                                let changes = {
+                                    let mut latest = latest.write().unwrap();
+                                    let mut changes = events::diff(&latest.servers, &servers);
+                                    changes.extend(availability_changes);
+                                    changes.extend(mod_changes);
+                                    changes.extend(cfg.overflow.check(
+                                        &cfg.groups,
+                                        &servers,
+                                        &mut overflow_suggested,
+                                    ));
+                                    changes.extend(cfg.seeding.check(
+                                        &latest.servers,
+                                        &servers,
+                                        &cfg.artwork,
+                                        &mut seeding_announced,
+                                    ));
+                                    changes.extend(cfg.ranking.check(
+                                        &game_detection.game(&game),
+                                        &servers,
+                                        &mut ranks,
+                                        &mut rank_announced,
+                                    ));
+                                    webhooks.check_thresholds(&latest.servers, &servers);
+                                    latest.shown = match servers.is_empty() {
+                                        true => None,
+                                        false => Some(shown_server(&cfg, &servers, rotation).id),
+                                    };
+                                    latest.servers = servers;
+                                    changes
+                                };
+                                let discord = tracing::info_span!("discord");
+                                if let Some(channel_id) = cfg.status_embed.channel_id {
+                                    let servers = latest.read().unwrap().servers.clone();
+                                    if let Err(e) = status_embed::update(
+                                        &ctx,
+                                        &cfg,
+                                        &game_detection.game(&game),
+                                        &budget,
+                                        channel_id,
+                                        &mut status_message_id,
+                                        &servers,
+                                    )
+                                    .instrument(discord.clone())
+                                    .await
+                                    {
+                                        log::error!("Failed to update status message: {}", e);
+                                    }
+                                }
+                                let servers = latest.read().unwrap().servers.clone();
+                                if let Err(e) = channel_counter::update(
+                                    &ctx,
+                                    &cfg.channel_counter,
+                                    &budget,
+                                    &mut counter,
+                                    &servers,
+                                )
+                                .instrument(discord.clone())
+                                .await
+                                {
+                                    log::error!("Failed to rename the channel counter: {}", e);
+                                }
+                                nickname::update(
+                                    &ctx,
+                                    &cfg.nickname,
+                                    &budget,
+                                    &mut nicknames,
+                                    &servers,
+                                )
+                                .instrument(discord.clone())
+                                .await;
+                                for event in changes {
+                                    webhooks.send_event(&event);
+                                    if let Err(e) = event_bus
+                                        .publish(&ctx, &event, false)
+                                        .instrument(discord.clone())
+                                        .await
+                                    {
+                                        log::error!("Failed to publish event: {:#?}", e);
+                                    }
+                                }
+                                if cfg.archive.due(cycles) {
+                                    let server_ids =
+                                        servers.iter().map(|server| server.id).collect();
+                                    tokio::spawn(archive::snapshot(
+                                        cfg.archive.clone(),
+                                        game_detection.game(&game),
+                                        server_ids,
+                                    ));
+                                }
+                                health::publish_status();
+                                rotation = rotation.wrapping_add(1);
+                            }
+                            Err(e) => {
+                                summary::STATS
+                                    .failed_polls
+                                    .fetch_add(1, atomic::Ordering::Relaxed);
+                                if cfg.update_presence {
+                                    let server_info = "¯\\_(ツ)_/¯ server not found";
+                                    ctx.set_activity(Some(cfg.activity_type.activity(server_info)));
+                                }
+                                match backoff.failure() {
+                                    true => log::error!("cant get new stats: {}", e),
+                                    false => log::debug!("cant get new stats: {}", e),
+                                }
+                            }
+                        }
+                    }
+                    .instrument(cycle)
+                    .await;
+                    last_update.store(Utc::now().timestamp() / 60, atomic::Ordering::Relaxed);
+                    // poll faster during scrims, otherwise wait the update interval before redo
+                    let interval = match scrim_enabled {
+                        true => time::Duration::from_secs(cfg.scrim.poll_interval_secs.max(5)),
+                        false => cfg.update_interval(),
+                    };
+                    tokio::time::sleep(backoff.interval(interval)).await;
+                }
+            }
+            // the tags of the loop stay out of the errors of the other tasks
+            .bind_hub(sentry::Hub::new_from_top(sentry::Hub::current())),
+        );
+    }
+
+    /// Guilds joined after startup get the admin commands of the guild scope
+    async fn guild_create(&self, ctx: Context, guild: Guild, is_new: Option<bool>) {
+        let cfg = config::reload();
+        if is_new == Some(true) && cfg.command_scope == CommandScope::Guild {
+            if let Err(e) = sync_commands(&ctx.http, Some(guild.id), admin_commands()).await {
+                log::error!(
+                    "Failed to register slash commands in guild {}: {:#?}",
+                    guild.id,
+                    e
+                );
+            }
+        }
+    }
+
+    async fn shard_stage_update(&self, _: Context, event: ShardStageUpdateEvent) {
+        let down_since = &self.watchdog.discord_down_since;
+        if event.new == ConnectionStage::Connected {
+            down_since.store(0, atomic::Ordering::Relaxed);
+        } else if down_since.load(atomic::Ordering::Relaxed) == 0 {
+            down_since.store(Utc::now().timestamp(), atomic::Ordering::Relaxed);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Component(ref component) = interaction {
+            self.component(&ctx, component).await;
+        }
+        if let Interaction::Command(command) = interaction {
+            let message = match command.data.name.as_str() {
+                "scrim" => ephemeral({
+                    let enabled = command
+                        .data
+                        .options
+                        .first()
+                        .and_then(|option| option.value.as_bool())
+                        .unwrap_or_default();
+                    self.scrim.store(enabled, atomic::Ordering::Relaxed);
+                    // persist the toggle so it survives restarts
+                    if let Err(e) = config::update(|cfg| cfg.scrim.enabled = enabled) {
+                        log::error!("Failed to save scrim mode: {}", e);
+                    }
+                    match enabled {
+                        true => "Scrim mode enabled: player names are hidden from public outputs and round summaries go to the staff channel.".to_string(),
+                        false => "Scrim mode disabled.".to_string(),
+                    }
+                }),
+                "crosspost" => ephemeral({
+                    let guild_id = command.guild_id.unwrap_or_default().to_string();
+                    let enabled = command
+                        .data
+                        .options
+                        .first()
+                        .and_then(|option| option.value.as_bool())
+                        .unwrap_or_default();
+                    self.crosspost
+                        .write()
+                        .unwrap()
+                        .insert(guild_id.clone(), enabled);
+                    if let Err(e) = config::update(|cfg| {
+                        cfg.crosspost.insert(guild_id, enabled);
+                    }) {
+                        log::error!("Failed to save crosspost setting: {}", e);
+                    }
+                    match enabled {
+                        true => "Events in announcement channels are now published to the following servers.".to_string(),
+                        false => "Events are no longer published.".to_string(),
+                    }
+                }),
+                "privacy" => ephemeral({
+                    let guild_id = command.guild_id.unwrap_or_default().to_string();
+                    let mut privacy = self
+                        .privacy
+                        .read()
+                        .unwrap()
+                        .get(&guild_id)
+                        .cloned()
+                        .unwrap_or_default();
+                    for option in &command.data.options {
+                        let value = option.value.as_bool().unwrap_or_default();
+                        match option.name.as_str() {
+                            "roster" => privacy.roster = value,
+                            "join_leave_log" => privacy.join_leave_log = value,
+                            "dashboard" => privacy.dashboard = value,
+                            "anonymized" => privacy.anonymized = value,
+                            _ => {}
+                        }
+                    }
+                    if !command.data.options.is_empty() {
+                        self.privacy
+                            .write()
+                            .unwrap()
+                            .insert(guild_id.clone(), privacy.clone());
+                        if let Err(e) = config::update(|cfg| {
+                            cfg.privacy.insert(guild_id, privacy);
+                        }) {
+                            log::error!("Failed to save privacy settings: {}", e);
+                        }
+                    }
+                    let shown = |surface| match self.shows_names(command.guild_id, surface) {
+                        true => "names shown",
+                        false => "counts and team sizes only",
+                    };
+                    format!(
+                        "Roster: {}\nJoin/leave log: {}\nDashboard: {}",
+                        shown(Surface::Roster),
+                        shown(Surface::JoinLeaveLog),
+                        shown(Surface::Dashboard),
+                    )
+                }),
+                "redaction" => ephemeral({
+                    let name = command
+                        .data
+                        .options
+                        .first()
+                        .and_then(|option| option.value.as_str())
+                        .unwrap_or_default();
+                    match self.public_name(command.guild_id, Surface::Roster, name) {
+                        Some(shown) => format!("`{}` is shown as `{}`", name, shown),
+                        None => format!("`{}` is hidden", name),
+                    }
+                }),
+                "report" => self.report(&command),
+                "rotationstats" => self.rotation_stats(&command),
+                "history" => self.history(&command).await,
+                "peak" => self.peak(&command).await,
+                "population" => self.population(&command),
+                "uptime" => self.uptime(&command),
+                "recent" => self.recent(&command),
+                "watch" => self.watch(&command),
+                "servers" => self.servers(&command).await,
+                "status" => self.status(&command).await,
+                "simulate" => self.simulate(&ctx, &command).await,
+                "apikey" => self.api_key(&ctx, &command).await,
+                "players" => self.players(&command).await,
+                "mods" => self.mods(&command).await,
+                "online" => self.online(&command).await,
+                "link" => self.link(&command),
+                "unlink" => self.unlink(&command),
+                "setserver" => self.set_server(&command).await,
+                "whenplay" => self.when_play(&ctx, &command).await,
+                "debug" => self.debug(),
+                "version" => ephemeral({
+                    let report = self.build_info.report();
+                    format!(
+                        "Version: {}\nCommit: {}\nBuilt: {}\nFeatures: {}\nUptime: {}",
+                        report.version,
+                        report.commit,
+                        report.build_date,
+                        match report.features.is_empty() {
+                            true => "none".to_string(),
+                            false => report.features.join(", "),
+                        },
+                        report.uptime(),
+                    )
+                }),
+                _ => ephemeral("Unknown command".to_string()),
+            };
+            let response = CreateInteractionResponse::Message(message);
+            if let Err(e) = command.create_response(&ctx.http, response).await {
+                log::error!("Failed to respond to /{}: {:#?}", command.data.name, e);
+            }
+        }
+    }
+}
+
+/// What a player needs to join a server, `None` if it is open to everyone. `mods` is the amount
+/// of mods of the server when its details are known
+pub fn join_requirements(server: &MarneServerInfo, mods: Option<usize>) -> Option<String> {
+    let mut requirements = vec![];
+    if server.password != 0 {
+        requirements.push("🔒 Password protected".to_string());
+    }
+    let strictness = match (server.need_same_mods != 0, server.allow_more_mods != 0) {
+        (true, _) => ", exactly these versions",
+        (false, true) => ", other mods are allowed",
+        (false, false) => "",
+    };
+    match mods {
+        Some(0) => {}
+        Some(1) => requirements.push(format!("🧩 Requires 1 mod{}", strictness)),
+        Some(mods) => requirements.push(format!("🧩 Requires {} mods{}", mods, strictness)),
+        None if server.need_same_mods != 0 => requirements.push("🧩 Requires mods".to_string()),
+        None => {}
+    }
+    match requirements.is_empty() {
+        true => None,
+        false => Some(requirements.join("\n")),
+    }
+}
+
+/// Embed with the map, mode, players, region and tick rate of a server. `mods` is the amount of
+/// mods of the server when its details are known, see `join_requirements`
+pub fn server_embed(
+    server: &MarneServerInfo,
+    artwork: &Artwork,
+    mods: Option<usize>,
+) -> CreateEmbed {
+    let internal_map = internal_map(&server.map_name);
+    let mut embed = CreateEmbed::new()
+        .title(&server.name)
+        .field(
+            "Map",
+            match maps::min_players(internal_map) {
+                Some(min_players) if server.current_players < min_players => format!(
+                    "{}\n_plays best with {}+ players_",
+                    map_name(internal_map),
+                    min_players
+                ),
+                _ => map_name(internal_map),
+            },
+            true,
+        )
+        .field("Mode", mode_name(&server.game_mode), true)
+        .field(
+            "Players",
+            match fill_rate::eta_text(server) {
+                Some(eta) => format!("{}/{}, {}", server.current_players, server.max_players, eta),
+                None => format!("{}/{}", server.current_players, server.max_players),
+            },
+            true,
+        )
+        .field(
+            "Region",
+            format!("{} ({})", server.region, server.country),
+            true,
+        )
+        .field("Tick rate", server.tick_rate.to_string(), true)
+        .timestamp(serenity::model::Timestamp::now());
+    if let Some(rank) = marne::rank(server.id) {
+        embed = embed.field(
+            "Rank",
+            format!(
+                "#{} of {} servers, #{} of {} in {}",
+                rank.overall, rank.servers, rank.region, rank.region_servers, server.region
+            ),
+            true,
+        );
+    }
+    if let Some(requirements) = join_requirements(server, mods) {
+        embed = embed.field("To join", requirements, false);
+    }
+    if let Some(image) = artwork.image(internal_map) {
+        embed = embed.image(image);
+    }
+    if let Some(attribution) = artwork.footer(internal_map) {
+        embed = embed.footer(CreateEmbedFooter::new(attribution));
+    }
+    embed
+}
+
+/// Refusal for commands of the owner of the bot, `None` if the user is the owner
+async fn owner_only(
+    ctx: &Context,
+    command: &CommandInteraction,
+    action: &str,
+) -> Option<CreateInteractionResponseMessage> {
+    match ctx.http.get_current_application_info().await {
+        Ok(info) if info.owner.as_ref().map(|owner| owner.id) == Some(command.user.id) => None,
+        Ok(_) => Some(ephemeral(format!(
+            "Only the owner of the bot can {}",
+            action
+        ))),
+        Err(e) => {
+            log::error!("Failed to get the owner of the bot: {:#?}", e);
+            Some(ephemeral(
+                "Failed to check the owner of the bot".to_string(),
+            ))
+        }
+    }
+}
+
+fn ephemeral(content: String) -> CreateInteractionResponseMessage {
+    CreateInteractionResponseMessage::new()
+        .content(content)
+        .ephemeral(true)
+}
+
+/// A slash command with its translations from `locales`
+fn command(name: &str, description: &str) -> CreateCommand {
+    let mut command = CreateCommand::new(name).description(description);
+    for (locale, translation) in locales::translations(name) {
+        if let Some(ref name) = translation.name {
+            command = command.name_localized(locale, name);
+        }
+        if let Some(ref description) = translation.description {
+            command = command.description_localized(locale, description);
+        }
+    }
+    command
+}
+
+/// An option of a slash command with its translations, `path` is like `apikey.create.name`
+fn command_option(kind: CommandOptionType, path: &str, description: &str) -> CreateCommandOption {
+    let name = path.rsplit('.').next().unwrap_or(path);
+    let mut option = CreateCommandOption::new(kind, name, description);
+    for (locale, translation) in locales::translations(path) {
+        if let Some(ref name) = translation.name {
+            option = option.name_localized(locale, name);
+        }
+        if let Some(ref description) = translation.description {
+            option = option.description_localized(locale, description);
+        }
+    }
+    option
+}
+
+/// Slash commands for everyone
+pub fn public_commands() -> Vec<CreateCommand> {
+    vec![
+        command(
+            "status",
+            "Show the current map, mode and players of the monitored servers",
+        )
+        .add_option(command_option(
+            CommandOptionType::String,
+            "status.group",
+            "Only show the servers of this group",
+        )),
+        command(
+            "version",
+            "Show the version, build info and uptime of the bot",
+        ),
+        command("players", "Show the players of a monitored server")
+            .add_option(command_option(
+                CommandOptionType::String,
+                "players.server",
+                "Server name, the first server if not set",
+            ))
+            .add_option(command_option(
+                CommandOptionType::String,
+                "players.filter",
+                "Only players with this in their name",
+            ))
+            .add_option(
+                command_option(
+                    CommandOptionType::String,
+                    "players.sort",
+                    "Order of the players",
+                )
+                .add_string_choice("By team", "team")
+                .add_string_choice("By name", "name"),
+            ),
+        command("mods", "Show the mods needed to join a monitored server").add_option(
+            command_option(
+                CommandOptionType::String,
+                "mods.server",
+                "Server name, the first server if not set",
+            ),
+        ),
+        command("report", "Server population reports").add_option(
+            command_option(
+                CommandOptionType::SubCommand,
+                "report.compare",
+                "Compare two months side by side",
+            )
+            .add_sub_option(
+                command_option(
+                    CommandOptionType::String,
+                    "report.compare.first",
+                    "First month, like 2024-04",
+                )
+                .required(true),
+            )
+            .add_sub_option(
+                command_option(
+                    CommandOptionType::String,
+                    "report.compare.second",
+                    "Second month, like 2024-05",
+                )
+                .required(true),
+            )
+            .add_sub_option(command_option(
+                CommandOptionType::String,
+                "report.compare.server",
+                "Server name, the first server if not set",
+            )),
+        ),
+        command("servers", "Browse the Marne servers")
+            .add_option(
+                command_option(
+                    CommandOptionType::String,
+                    "servers.game",
+                    "Game of the servers",
+                )
+                .add_string_choice("Battlefield 1", "bf1")
+                .add_string_choice("Battlefield V", "bfv"),
+            )
+            .add_option(command_option(
+                CommandOptionType::String,
+                "servers.region",
+                "Region like EU",
+            ))
+            .add_option(
+                command_option(
+                    CommandOptionType::Integer,
+                    "servers.min_players",
+                    "Only servers with at least this many players",
+                )
+                .min_int_value(0),
+            )
+            .add_option(command_option(
+                CommandOptionType::Boolean,
+                "servers.password",
+                "Only servers with (true) or without (false) a password",
+            )),
+        command("uptime", "Show how reliably a monitored server was up").add_option(
+            command_option(
+                CommandOptionType::String,
+                "uptime.server",
+                "Server name, the first server if not set",
+            ),
+        ),
+        command(
+            "recent",
+            "Show who joined a monitored server in the last hour",
+        )
+        .add_option(command_option(
+            CommandOptionType::String,
+            "recent.server",
+            "Server name, the first server if not set",
+        )),
+        command(
+            "online",
+            "Show the linked members playing on the monitored servers",
+        ),
+        command("link", "Link your in-game name to show up in /online").add_option(
+            command_option(
+                CommandOptionType::String,
+                "link.player",
+                "Your in-game name",
+            )
+            .required(true),
+        ),
+        command("unlink", "Stop showing up in /online"),
+        command("watch", "Get a DM when players join a monitored server")
+            .add_option(
+                command_option(CommandOptionType::SubCommand, "watch.add", "Watch a player")
+                    .add_sub_option(
+                        command_option(
+                            CommandOptionType::String,
+                            "watch.add.player",
+                            "In-game name of the player",
+                        )
+                        .required(true),
+                    ),
+            )
+            .add_option(
+                command_option(
+                    CommandOptionType::SubCommand,
+                    "watch.remove",
+                    "Stop watching a player",
+                )
+                .add_sub_option(command_option(
+                    CommandOptionType::String,
+                    "watch.remove.player",
+                    "In-game name of the player, all of them if not set",
+                )),
+            )
+            .add_option(command_option(
+                CommandOptionType::SubCommand,
+                "watch.list",
+                "Show the players you watch",
+            )),
+        command("peak", "Best time to find a full monitored server")
+            .add_option(command_option(
+                CommandOptionType::String,
+                "peak.server",
+                "Server name, the first server if not set",
+            ))
+            .add_option(
+                command_option(
+                    CommandOptionType::Integer,
+                    "peak.weeks",
+                    "Weeks of history to average, 4 if not set",
+                )
+                .min_int_value(1)
+                .max_int_value(52),
+            ),
+        command("population", "Players on all Marne servers of the game").add_option(
+            command_option(
+                CommandOptionType::Boolean,
+                "population.by_region",
+                "Also show the players per region",
+            ),
+        ),
+        command("history", "Player count chart of a monitored server")
+            .add_option(
+                command_option(
+                    CommandOptionType::String,
+                    "history.period",
+                    "Period to show",
+                )
+                .add_string_choice("Last 24 hours", "24h")
+                .add_string_choice("Last 7 days", "7d"),
+            )
+            .add_option(command_option(
+                CommandOptionType::String,
+                "history.server",
+                "Server name, the first server if not set",
+            )),
+    ]
+}
+
+/// Slash commands for admins (and the owner of the bot), hidden from other members by default
+pub fn admin_commands() -> Vec<CreateCommand> {
+    let privacy_option = |name: &str, description| {
+        command_option(
+            CommandOptionType::Boolean,
+            &format!("privacy.{}", name),
+            description,
+        )
+    };
+    vec![
+        command("scrim", "Toggle tournament/scrim mode")
+            .default_member_permissions(Permissions::ADMINISTRATOR)
+            .dm_permission(false)
+            .add_option(
+                command_option(
+                    CommandOptionType::Boolean,
+                    "scrim.enabled",
+                    "Turn scrim mode on or off",
+                )
+                .required(true),
+            ),
+        command(
+            "crosspost",
+            "Publish the alerts of an announcement channel to following servers",
+        )
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(
+            command_option(
+                CommandOptionType::Boolean,
+                "crosspost.enabled",
+                "Turn publishing on or off",
+            )
+            .required(true),
+        ),
+        command(
+            "privacy",
+            "Show or change where player names are shown publicly",
+        )
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(privacy_option("roster", "Show player names in the roster"))
+        .add_option(privacy_option(
+            "join_leave_log",
+            "Show player names in the join/leave log",
+        ))
+        .add_option(privacy_option(
+            "dashboard",
+            "Show player names on the dashboard",
+        ))
+        .add_option(privacy_option(
+            "anonymized",
+            "Only show player counts and team sizes",
+        )),
+        command("redaction", "Preview how a player name is shown publicly")
+            .default_member_permissions(Permissions::ADMINISTRATOR)
+            .dm_permission(false)
+            .add_option(
+                command_option(CommandOptionType::String, "redaction.name", "Player name")
+                    .required(true),
+            ),
+        command("setserver", "Change the monitored server")
+            .default_member_permissions(Permissions::ADMINISTRATOR)
+            .dm_permission(false)
+            .add_option(
+                command_option(
+                    CommandOptionType::String,
+                    "setserver.server",
+                    "Name or id of the server",
+                )
+                .required(true),
+            ),
+        command(
+            "whenplay",
+            "Post a poll for the time to play, the winner gets a seeding alert",
+        )
+        .default_member_permissions(Permissions::MANAGE_EVENTS)
+        .dm_permission(false)
+        .add_option(command_option(
+            CommandOptionType::String,
+            "whenplay.slots",
+            "Times (UTC) to vote on like 19:00, 20:00, the configured ones if not set",
+        )),
+        command("debug", "Debug info of the bot")
+            .default_member_permissions(Permissions::ADMINISTRATOR)
+            .dm_permission(false)
+            .add_option(command_option(
+                CommandOptionType::SubCommand,
+                "debug.budgets",
+                "Discord actions of the last hour against their budget",
+            )),
+        command(
+            "simulate",
+            "Send a synthetic event to test the alert channel",
+        )
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(
+            command_option(CommandOptionType::String, "simulate.event", "Event to send")
+                .required(true)
+                .add_string_choice("Server full", "server_full")
+                .add_string_choice("Offline", "offline")
+                .add_string_choice("Map change", "map_change"),
+        ),
+        command(
+            "apikey",
+            "Manage the keys of the HTTP API, owner of the bot only",
+        )
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(
+            command_option(
+                CommandOptionType::SubCommand,
+                "apikey.create",
+                "Create a key",
+            )
+            .add_sub_option(
+                command_option(
+                    CommandOptionType::String,
+                    "apikey.create.name",
+                    "Who the key is for",
+                )
+                .required(true),
+            )
+            .add_sub_option(
+                command_option(
+                    CommandOptionType::Integer,
+                    "apikey.create.requests_per_minute",
+                    "Rate limit of the key",
+                )
+                .min_int_value(1),
+            ),
+        )
+        .add_option(
+            command_option(
+                CommandOptionType::SubCommand,
+                "apikey.revoke",
+                "Revoke a key",
+            )
+            .add_sub_option(
+                command_option(
+                    CommandOptionType::String,
+                    "apikey.revoke.name",
+                    "Name of the key",
+                )
+                .required(true),
+            ),
+        )
+        .add_option(command_option(
+            CommandOptionType::SubCommand,
+            "apikey.list",
+            "List the keys",
+        )),
+        command(
+            "rotationstats",
+            "How evenly the maps of the rotation are played",
+        )
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(
+            command_option(
+                CommandOptionType::Integer,
+                "rotationstats.days",
+                "Days to look back, 7 by default",
+            )
+            .min_int_value(1)
+            .max_int_value(365),
+        )
+        .add_option(command_option(
+            CommandOptionType::String,
+            "rotationstats.server",
+            "Server name, the first server if not set",
+        )),
+    ]
+}
+
+/// Registers the slash commands, replacing the ones of earlier versions. With the guild scope the
+/// admin commands are registered per guild, which Discord shows right away
+async fn register_commands(http: &Http, scope: CommandScope, guilds: &[GuildId]) {
+    let (global, guild) = match scope {
+        CommandScope::Global => ([public_commands(), admin_commands()].concat(), vec![]),
+        CommandScope::Guild => (public_commands(), admin_commands()),
+    };
+    if let Err(e) = sync_commands(http, None, global).await {
+        log::error!("Failed to register slash commands: {:#?}", e);
+    }
+    // with the global scope this removes the commands left from the guild scope
+    for guild_id in guilds {
+        if let Err(e) = sync_commands(http, Some(*guild_id), guild.clone()).await {
+            log::error!(
+                "Failed to register slash commands in guild {}: {:#?}",
+                guild_id,
+                e
+            );
+        }
+    }
+}
+
+/// The parts of a command that show in Discord, to compare registered and new commands
+fn command_signature(command: &impl serde::Serialize) -> serde_json::Value {
+    fn strip(value: &serde_json::Value) -> serde_json::Value {
+        let mut signature = serde_json::Map::new();
+        for key in [
+            "name",
+            "description",
+            "type",
+            "required",
+            "choices",
+            "default_member_permissions",
+            "name_localizations",
+            "description_localizations",
+        ] {
+            match value.get(key) {
+                // a missing option, `false` and no translations are the same to Discord
+                Some(serde_json::Value::Null) | Some(serde_json::Value::Bool(false)) | None => {}
+                Some(serde_json::Value::Object(map)) if map.is_empty() => {}
+                Some(field) => {
+                    signature.insert(key.to_string(), field.clone());
+                }
+            }
+        }
+        if let Some(serde_json::Value::Array(options)) = value.get("options") {
+            if !options.is_empty() {
+                signature.insert(
+                    "options".to_string(),
+                    options.iter().map(strip).collect::<Vec<_>>().into(),
+                );
+            }
+        }
+        signature.into()
+    }
+    strip(&serde_json::to_value(command).unwrap_or_default())
+}
+
+/// Replaces the registered commands of the bot (or of a guild) when they changed. Renamed and
+/// removed commands are deleted by the overwrite, so they don't linger next to the new ones
+pub async fn sync_commands(
+    http: &Http,
+    guild_id: Option<GuildId>,
+    commands: Vec<CreateCommand>,
+) -> Result<()> {
+    let registered = match guild_id {
+        Some(guild_id) => http.get_guild_commands_with_localizations(guild_id).await?,
+        None => http.get_global_commands_with_localizations().await?,
+    };
+    let by_name = |signatures: &mut Vec<serde_json::Value>| {
+        signatures.sort_by_key(|signature| signature["name"].to_string())
+    };
+    let mut current: Vec<serde_json::Value> = registered.iter().map(command_signature).collect();
+    let mut wanted: Vec<serde_json::Value> = commands.iter().map(command_signature).collect();
+    by_name(&mut current);
+    by_name(&mut wanted);
+    if current == wanted {
+        return Ok(());
+    }
+
+    let outdated: Vec<&str> = registered
+        .iter()
+        .map(|command| command.name.as_str())
+        .filter(|name| !wanted.iter().any(|signature| signature["name"] == *name))
+        .collect();
+    let place = match guild_id {
+        Some(guild_id) => format!("guild {}", guild_id),
+        None => "global".to_string(),
+    };
+    if !outdated.is_empty() {
+        log::info!(
+            "Removing outdated slash commands ({}): /{}",
+            place,
+            outdated.join(", /")
+        );
+    }
+    log::info!("Updating {} slash commands ({})", commands.len(), place);
+    match guild_id {
+        Some(guild_id) => guild_id.set_commands(http, commands).await?,
+        None => Command::set_global_commands(http, commands).await?,
+    };
+    Ok(())
+}
+
+/// Keeps track of the current round and posts a summary to the staff channel
+/// when a round ends while scrim mode is enabled.
+async fn track_round(
+    ctx: &Context,
+    statics: &Static,
+    budget: &budget::RateBudget,
+    scrim_enabled: bool,
+    round: Option<Round>,
+    server: &MarneServerInfo,
+) -> Option<Round> {
+    let map = map_name(internal_map(&server.map_name));
+    match round {
+        Some(mut round) if round.map == map && round.game_mode == server.game_mode => {
+            round.peak_players = round.peak_players.max(server.current_players);
+            Some(round)
+        }
+        previous => {
+            if let (Some(previous), Some(channel_id), true) = (
+                previous,
+                config::channel_id(statics.scrim.staff_channel_id),
+                scrim_enabled,
+            ) {
+                let minutes = (Utc::now() - previous.started).num_minutes();
+                let summary = format!(
+                    "**Round finished** on {}: {} ({})\nDuration: {} min\nPeak players: {}/{}\nNext: {} ({})",
+                    server.name,
+                    previous.map,
+                    mode_name(&previous.game_mode),
+                    minutes,
+                    previous.peak_players,
+                    previous.max_players,
+                    map,
+                    mode_name(&server.game_mode),
+                );
+                match channel_id
+                    .send_message(&ctx.http, CreateMessage::new().content(summary))
+                    .await
+                {
+                    Ok(_) => budget.record_channel(&ctx.cache, channel_id, budget::Action::Message),
+                    Err(e) => log::error!("Failed to post round summary: {:#?}", e),
+                }
+            }
+            Some(Round {
+                map,
+                game_mode: server.game_mode.clone(),
+                started: Utc::now(),
+                peak_players: server.current_players,
+                max_players: server.max_players,
+            })
+        }
+    }
+}
+
+/// Stores the servers for `/status.json`. Their mods are only fetched again when the map
+/// changes, or every update with `mod_changes` alerts, which are returned
+async fn update_last_status(
+    game: &str,
+    last_status: &health::LastStatus,
+    servers: &[MarneServerInfo],
+    mod_changes: bool,
+    fetch_players: bool,
+) -> (Vec<events::Event>, HashMap<i64, Vec<Player>>) {
+    let mut statuses = vec![];
+    let mut changes = vec![];
+    let mut players = HashMap::new();
+    for server in servers {
+        let previous = last_status.mods(server.id);
+        let mods = match previous {
+            Some((ref map, ref mods))
+                if !mod_changes && !fetch_players && *map == server.map_name =>
+            {
+                Some(mods.clone())
+            }
+            _ => match marne::server_detail(game, server.id).await {
+                Ok(detail) => {
+                    players.insert(server.id, detail.players.players().to_vec());
+                    Some(detail.mods.mods().to_vec())
+                }
+                Err(e) => {
+                    log::warn!("Failed to get the details of {}: {}", server.name, e);
+                    None
+                }
+            },
+        };
+        if let (true, Some((_, before)), Some(after)) = (mod_changes, &previous, &mods) {
+            changes.extend(events::mod_changes(server, before, after));
+        }
+        statuses.push(health::ServerStatus {
+            info: server.clone(),
+            map: map_name(internal_map(&server.map_name)),
+            mode: mode_name(&server.game_mode),
+            mods,
+            uptime: None,
+            players: None,
+            sparkline: vec![],
+        });
+    }
+    last_status.update(statuses);
+    (changes, players)
+}
+
+/// DMs the members watching the players that joined a monitored server. Players that were
+/// already on when the bot started aren't announced
+async fn notify_watchers(
+    ctx: &Context,
+    budget: &budget::RateBudget,
+    watchlist: &Watchlist,
+    servers: &[MarneServerInfo],
+    changes: &[(i64, sessions::PlayerChanges)],
+) {
+    for (server_id, changes) in changes {
+        if changes.baseline {
+            continue;
+        }
+        let server = match servers.iter().find(|server| server.id == *server_id) {
+            Some(server) => server,
+            None => continue,
+        };
+        for player in &changes.joined {
+            for user_id in watchlist.watchers(player) {
+                if !budget.allows(None, budget::Action::Message) {
+                    return;
+                }
+                let message = CreateMessage::new().content(format!(
+                    "`{}` joined **{}** ({}/{}, {})",
+                    player,
+                    server.name,
+                    server.current_players,
+                    server.max_players,
+                    map_name(internal_map(&server.map_name))
+                ));
+                match UserId::new(user_id)
+                    .direct_message(&ctx.http, message)
+                    .await
+                {
+                    Ok(_) => budget.record(None, budget::Action::Message),
+                    Err(e) => log::warn!("Failed to DM watcher {}: {}", user_id, e),
+                }
+            }
+        }
+    }
+}
+
+/// The presence of a server from `presence_format`. `{players}`, `{max}`, `{map}`, `{mode}`,
+/// `{name}`, `{region}` and `{tickrate}` are filled in, `{password_lock}` is a lock for servers
+/// with a password. Without `{password_lock}` the lock is added at the end
+pub fn presence_text(format: &str, server: &MarneServerInfo) -> String {
+    let format = match format.contains("{password_lock}") {
+        true => format.to_string(),
+        false => format!("{} {{password_lock}}", format),
+    };
+    format
+        .replace("{players}", &server.current_players.to_string())
+        .replace("{max}", &server.max_players.to_string())
+        .replace("{map}", &map_name(internal_map(&server.map_name)))
+        .replace("{mode}", &mode_name(&server.game_mode))
+        .replace("{name}", &server.name)
+        .replace("{region}", &server.region)
+        .replace("{tickrate}", &server.tick_rate.to_string())
+        .replace(
+            "{password_lock}",
+            match server.password != 0 {
+                true => "🔒",
+                false => "",
+            },
+        )
+        .trim()
+        .to_string()
+}
+
+/// Shows the players and map of the shown server, or the combined population, in the presence
+pub fn update_presence(
+    ctx: &Context,
+    statics: &Static,
+    servers: &[MarneServerInfo],
+    rotation: usize,
+    offline: bool,
+) {
+    if servers.is_empty() {
+        // marne.io works, the servers just aren't online
+        match offline {
+            true => ctx.set_activity(Some(statics.activity_type.activity("Server offline"))),
+            false => ctx.set_activity(Some(
+                statics
+                    .activity_type
+                    .activity("¯\\_(ツ)_/¯ server not found"),
+            )),
+        }
+        return;
+    }
+    let server = shown_server(statics, servers, rotation);
+    let server_info = match (statics.presence, servers.len()) {
+        (PresenceMode::Sum, count) if count > 1 => population(&servers.iter().collect::<Vec<_>>()),
+        (PresenceMode::Group, _) if !statics.groups.is_empty() => {
+            let group = &statics.groups[rotation % statics.groups.len()];
+            format!("{}: {}", group.name, population(&group.members(servers)))
+        }
+        _ => presence_text(&statics.presence_format, server),
+    };
+    // change game activity
+    ctx.set_activity(Some(statics.activity_type.activity(server_info)));
+}
+
+/// What is on the avatar and when it may be changed again
+pub struct AvatarState {
+    /// time of the last change, or of the discord timeout after a failed change
+    pub changed: chrono::DateTime<Utc>,
+    /// map, mode and player count (if drawn) currently on the avatar
+    pub shows: Option<(String, String, String)>,
+}
+
+impl AvatarState {
+    pub fn new(statics: &Static) -> Self {
+        Self {
+            // set to mins_between_avatar_change ago to allow changing on startup
+            changed: chrono::Utc::now()
+                - chrono::Duration::minutes(statics.mins_between_avatar_change.into()),
+            shows: None,
+        }
+    }
+}
+
+/// Changes the avatar (and banner) to the map and mode of the server. Discord rate limits
+/// avatar changes, so it is only changed if the map, mode or drawn player count did
+async fn update_avatar(
+    ctx: &Context,
+    statics: &Static,
+    budget: &budget::RateBudget,
+    last_status: &health::LastStatus,
+    server: &MarneServerInfo,
+    avatar: &mut AvatarState,
+) -> Result<()> {
+    let internal_map = internal_map(&server.map_name);
+    let players = match statics.avatar_players.enabled {
+        true => format!("{}/{}", server.current_players, server.max_players),
+        false => String::new(),
+    };
+    let shows = (
+        internal_map.to_string(),
+        server.game_mode.clone(),
+        players.clone(),
+    );
+    if avatar.shows.as_ref() == Some(&shows)
+        || avatar.changed.add(chrono::Duration::minutes(
+            statics.mins_between_avatar_change.into(),
+        )) > chrono::Utc::now()
+    {
+        return Ok(());
+    }
+    // map changes go first, a new player count waits for room in the avatar budget
+    let only_players = avatar
+        .shows
+        .as_ref()
+        .is_some_and(|(map, mode, _)| *map == shows.0 && *mode == shows.1);
+    if only_players && !budget.allows(None, budget::Action::Avatar) {
+        return Ok(());
+    }
+
+    let (image, banner) = gen_img(
+        &small_mode(&server.game_mode),
+        statics.artwork.image(internal_map).as_deref(),
+        &map_name(internal_map),
+        &statics.avatar_steps,
+        &statics.avatar_theme,
+        Some((&statics.avatar_players, &players)),
+    )
+    .await?;
+
+    // change avatar
+    let attachment = CreateAttachment::bytes(image.clone(), "map_mode.jpg");
+    let mut user = ctx.cache.current_user().clone();
+    let mut new_profile = EditProfile::new().avatar(&attachment);
+    if statics.set_banner_image {
+        let banner = CreateAttachment::bytes(banner, "info_image.jpg");
+        new_profile = new_profile.banner(&banner);
+    }
+    budget.record(None, budget::Action::Avatar);
+    if let Err(e) = user.edit(ctx.clone(), new_profile).await {
+        // add official avatar timeout if discord avatar timeout is reached
+        avatar.changed = chrono::Utc::now().add(chrono::Duration::minutes(5));
+        anyhow::bail!(
+            "Failed to set new avatar: {:?}\n adding timeout before retrying",
+            e
+        );
+    }
+    avatar.changed = chrono::Utc::now();
+    avatar.shows = Some(shows);
+    last_status.set_avatar(image);
+    Ok(())
+}
+
+/// Draws the banner of `/banner.png` for the first monitored server, or an offline banner with
+/// the configured name when it isn't in the server list
+async fn update_banner(
+    statics: &Static,
+    last_status: &health::LastStatus,
+    server: Option<&MarneServerInfo>,
+) -> Result<()> {
+    let banner = match server {
+        Some(server) => {
+            let internal_map = internal_map(&server.map_name);
+            images::server_banner(
+                statics.artwork.image(internal_map).as_deref(),
+                &server.name,
+                &format!(
+                    "{}/{} players │ {} │ {}",
+                    server.current_players,
+                    server.max_players,
+                    mode_name(&server.game_mode),
+                    map_name(internal_map)
+                ),
+            )
+            .await?
+        }
+        None => {
+            images::server_banner(
+                None,
+                statics.server_name.as_deref().unwrap_or("Server"),
+                "Offline",
+            )
+            .await?
+        }
+    };
+    last_status.set_banner(banner);
+    Ok(())
+}
+
+/// The server shown in the presence and avatar this update
+pub fn shown_server<'a>(
+    statics: &Static,
+    servers: &'a [MarneServerInfo],
+    rotation: usize,
+) -> &'a MarneServerInfo {
+    match statics.presence {
+        PresenceMode::Rotate => &servers[rotation % servers.len()],
+        PresenceMode::Sum => &servers[0],
+        // the first server of the group shown this update
+        PresenceMode::Group => statics
+            .groups
+            .get(rotation % statics.groups.len().max(1))
+            .and_then(|group| group.members(servers).first().copied())
+            .unwrap_or(&servers[0]),
+    }
+}
+
+/// Registers or updates the slash commands of the bot (or every bot of `bots`) without
+/// connecting to the gateway
+pub async fn register(cfg: &Static) -> Result<()> {
+    let tokens: Vec<&str> = match cfg.bots.is_empty() {
+        true => vec![cfg.token.as_str()],
+        false => cfg.bots.iter().map(|bot| bot.token.as_str()).collect(),
+    };
+    for token in tokens {
+        let http = Http::new(token);
+        let application = http.get_current_application_info().await?;
+        http.set_application_id(application.id);
+        let guilds: Vec<GuildId> = http
+            .get_guilds(None, None)
+            .await?
+            .into_iter()
+            .map(|guild| guild.id)
+            .collect();
+        register_commands(&http, cfg.command_scope, &guilds).await;
+        log::info!(
+            "Registered the commands of {} in {} Discord servers",
+            application.name,
+            guilds.len()
+        );
+    }
+    Ok(())
+}
+
+/// Waits for ctrl-c, or for SIGTERM on unix like `docker stop` and systemd send. `false` if the
+/// signals can't be listened for
+async fn shutdown_signal() -> bool {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                return tokio::select! {
+                    ctrl_c = tokio::signal::ctrl_c() => ctrl_c.is_ok(),
+                    _ = terminate.recv() => true,
+                };
+            }
+            Err(e) => log::warn!("Can't listen for SIGTERM: {}", e),
+        }
+    }
+    tokio::signal::ctrl_c().await.is_ok()
+}
+
+/// Runs the bot until the Discord client stops
+pub async fn run(cfg: Static) -> Result<()> {
+    http_client::init(&cfg.http_client);
+    images::load_fonts(&cfg.fonts);
+    summary::start();
+    let summary_channel_id = cfg.summary_channel_id;
+    marne::set_api_urls(&cfg.marne_urls);
+    let history = match history::History::open(&cfg.history_db) {
+        Ok(history) => Some(Arc::new(history)),
+        Err(e) => {
+            log::error!(
+                "Failed to open history database, not recording history: {}",
+                e
+            );
+            None
+        }
+    };
+
+    let mut features = vec![];
+    if history.is_some() {
+        features.push("history".to_string());
+    }
+    if cfg.hard_fail_after_mins.is_some() {
+        features.push("hard-fail".to_string());
+    }
+    if cfg.check_for_updates {
+        features.push("update-check".to_string());
+    }
+    if cfg.scrim.enabled {
+        features.push("scrim".to_string());
+    }
+    if !cfg.redactions.is_empty() {
+        features.push("redactions".to_string());
+    }
+    if cfg.alerts.mod_changes {
+        features.push("mod-changes".to_string());
+    }
+    if cfg.seeding.threshold.is_some() {
+        features.push("seeding".to_string());
+    }
+    if !cfg.ranking.milestones.is_empty() {
+        features.push("ranking".to_string());
+    }
+    if cfg.archive.enabled {
+        features.push("archive".to_string());
+    }
+    if cfg.nickname.enabled {
+        features.push("nickname".to_string());
+    }
+    if cfg.sentry_dsn.is_some() {
+        features.push("sentry".to_string());
+    }
+
+    let budget = Arc::new(budget::RateBudget::new(cfg.budgets.clone()));
+    let crosspost = Arc::new(RwLock::new(cfg.crosspost.clone()));
+
+    tokio::spawn(maps::watch());
+
+    let handler = Handler {
+        scrim: Arc::new(atomic::AtomicBool::new(cfg.scrim.enabled)),
+        privacy: Arc::new(RwLock::new(cfg.privacy.clone())),
+        redactor: Arc::new(Redactor::new(&cfg.redactions)),
+        history,
+        watchdog: Arc::new(Watchdog::default()),
+        latest: Arc::new(RwLock::new(Latest::default())),
+        build_info: Arc::new(version::BuildInfo::new(features)),
+        events: Arc::new(events::EventBus::new(
+            cfg.alerts.clone(),
+            cfg.seeding.clone(),
+            Arc::clone(&budget),
+            Arc::clone(&crosspost),
+        )),
+        crosspost,
+        game: cfg.game.clone(),
+        game_detection: Arc::new(marne::GameDetection::default()),
+        budget,
+        server_metrics: Arc::new(health::ServerMetrics::default()),
+        last_status: Arc::new(health::LastStatus::default()),
+        api_keys: Arc::new(ApiKeys::new(cfg.api.clone())),
+        groups: cfg.groups.clone(),
+        artwork: cfg.artwork.clone(),
+        primary: Arc::new(RwLock::new(ServerTarget {
+            name: cfg.server_name.clone(),
+            id: cfg.server_id,
+        })),
+        polls: Arc::new(RwLock::new(cfg.whenplay.pending.clone())),
+        links: Arc::new(Links::new(cfg.links.clone())),
+        track_players: cfg.track_players,
+        watchlist: Arc::new(Watchlist::new(cfg.watches.clone())),
+        last_update: Arc::new(atomic::AtomicI64::new(0)),
+        bot: None,
+        started: Arc::new(atomic::AtomicBool::new(false)),
+        context: Arc::new(RwLock::new(None)),
+    };
+    // the supervisor mode runs a bot per entry of `bots` instead of the one of `token`
+    let bots: Vec<(String, Handler)> = match cfg.bots.is_empty() {
+        true => vec![(cfg.token.clone(), handler)],
+        false => {
+            log::info!("Supervisor mode, starting {} bots", cfg.bots.len());
+            // the loops poll at the same interval, one list per interval is enough
+            let interval = cfg
+                .update_interval_secs
+                .max(config::MIN_UPDATE_INTERVAL_SECS);
+            marne::share_lists(i64::try_from(interval).unwrap_or(i64::MAX) - 5);
+            cfg.bots
+                .iter()
+                .enumerate()
+                .map(|(index, bot)| (bot.token.clone(), handler.for_bot(&cfg, index, bot)))
+                .collect()
+        }
+    };
+    // every bot has its own connection to watch
+    if let Some(hard_fail_after_mins) = cfg.hard_fail_after_mins {
+        for (_, handler) in &bots {
+            let watchdog = Arc::clone(&handler.watchdog);
+            tokio::spawn(async move { watchdog.run(hard_fail_after_mins).await });
+        }
+    }
+
+    // one HTTP server for all bots
+    if let Some(addr) = cfg.health_addr() {
+        let handler = &bots[0].1;
+        tokio::spawn(health::serve(
+            addr,
+            cfg.health_stale_minutes,
+            Arc::clone(&handler.last_update),
+            Arc::clone(&handler.build_info),
+            Arc::clone(&handler.budget),
+            bots.iter()
+                .map(|(_, handler)| Arc::clone(&handler.server_metrics))
+                .collect(),
+            Arc::clone(&handler.api_keys),
+            Arc::new(health::Allowlist::new(&cfg.health_allowlist)),
+            bots.iter()
+                .map(|(_, handler)| Arc::clone(&handler.last_status))
+                .collect(),
+            Arc::new(cfg.artwork.clone()),
+            cfg.status_page,
+            Arc::new(cfg.http_auth.clone()),
+        ));
+    }
+
+    let intents = GatewayIntents::non_privileged();
+    let mut clients = vec![];
+    for (token, handler) in bots {
+        let client = Client::builder(token, intents)
+            .event_handler(handler)
+            .await
+            .map_err(|e| anyhow::anyhow!("Error creating client: {}", e))?;
+        clients.push(client);
+    }
+
+    // post the shutdown summary on ctrl-c or SIGTERM before the shards go down
+    let shard_managers: Vec<_> = clients
+        .iter()
+        .map(|client| Arc::clone(&client.shard_manager))
+        .collect();
+    let http = Arc::clone(&clients[0].http);
+    tokio::spawn(async move {
+        if shutdown_signal().await {
+            summary::shutdown(http, summary_channel_id).await;
+            for shard_manager in shard_managers {
+                shard_manager.shutdown_all().await;
+            }
+        }
+    });
+
+    // start listening for events by starting a single shard per bot
+    let mut running = tokio::task::JoinSet::new();
+    for mut client in clients {
+        running.spawn(async move {
+            if let Err(why) = client.start().await {
+                log::error!("Client error: {:?}", why);
+            }
+        });
+    }
+    while running.join_next().await.is_some() {}
+    Ok(())
+}
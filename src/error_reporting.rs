@@ -0,0 +1,30 @@
+use crate::config::Static;
+use sentry::ClientInitGuard;
+
+/// Starts reporting panics and the errors that are logged to Sentry (or a Sentry compatible
+/// service) when `sentry_dsn` is set in config.txt. Read before the rest of the config like the
+/// logging settings. Reports are sent until the returned guard is dropped
+pub fn init() -> Option<ClientInitGuard> {
+    let dsn = confy::load_path::<Static>("config.txt").ok()?.sentry_dsn?;
+    let guard = sentry::init((
+        dsn,
+        sentry::ClientOptions {
+            release: sentry::release_name!(),
+            attach_stacktrace: true,
+            ..Default::default()
+        },
+    ));
+    match guard.is_enabled() {
+        true => Some(guard),
+        false => {
+            eprintln!("Invalid sentry_dsn, not reporting errors");
+            None
+        }
+    }
+}
+
+/// Adds a tag to the errors reported from the current task, like the poll cycle or the server
+/// it was about
+pub fn tag(key: &str, value: impl ToString) {
+    sentry::configure_scope(|scope| scope.set_tag(key, value.to_string()));
+}
@@ -0,0 +1,702 @@
+use crate::{
+    batcher::{Batch, Batcher},
+    budget::{self, RateBudget},
+    config::{self, Artwork, ServerGroup},
+    fill_rate,
+    marne::{self, internal_map, map_name, mode_name, MarneServerInfo, Mod},
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateAllowedMentions, CreateEmbed, CreateMessage, EditMessage},
+    client::Context,
+    model::{
+        channel::{ChannelType, Message},
+        id::{ChannelId, RoleId},
+    },
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+/// State changes of a monitored server
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    ServerFull {
+        server: String,
+        players: i64,
+        max_players: i64,
+    },
+    Offline {
+        server: String,
+    },
+    /// an offline server is back in the server list
+    Online {
+        server: String,
+    },
+    MapChange {
+        server: String,
+        previous_map: String,
+        map: String,
+        game_mode: String,
+    },
+    /// a server of a group is full while another one has room
+    Overflow {
+        group: String,
+        full: String,
+        other: String,
+        players: i64,
+        max_players: i64,
+        /// the configured message with the placeholders filled in
+        text: String,
+    },
+    /// a server got enough players to start seeding
+    Seeding {
+        server: String,
+        players: i64,
+        max_players: i64,
+        map: String,
+        /// map image shown with the announcement
+        image: Option<String>,
+        /// the configured message with the placeholders filled in
+        text: String,
+    },
+    /// the required mods of a server changed
+    ModsChanged {
+        server: String,
+        added: Vec<Mod>,
+        removed: Vec<Mod>,
+        /// the previous version and the updated mod
+        updated: Vec<(String, Mod)>,
+    },
+    /// a server went up to a top spot of the server list
+    RankMilestone {
+        server: String,
+        rank: usize,
+        /// the configured message with the placeholders filled in
+        text: String,
+    },
+    /// the time picked with `/whenplay` is about to start
+    PlannedSeeding {
+        /// unix time
+        time: i64,
+        votes: u64,
+        /// the configured message with the placeholders filled in
+        text: String,
+    },
+}
+
+impl Event {
+    pub fn message(&self) -> String {
+        match self {
+            Event::ServerFull {
+                server,
+                players,
+                max_players,
+            } => format!("**{}** is full ({}/{})", server, players, max_players),
+            Event::Offline { server } => format!("**{}** went offline", server),
+            Event::Online { server } => format!("**{}** is back online", server),
+            Event::MapChange {
+                server,
+                previous_map,
+                map,
+                game_mode,
+            } => format!(
+                "**{}** changed map from {} to {} ({})",
+                server,
+                previous_map,
+                map,
+                mode_name(game_mode)
+            ),
+            Event::ModsChanged {
+                server,
+                added,
+                removed,
+                updated,
+            } => {
+                let mut lines = vec![format!(
+                    "**{}** changed its mods, update them before joining:",
+                    server
+                )];
+                lines.extend(
+                    added.iter().map(|item| {
+                        format!("Added [{} {}]({})", item.name, item.version, item.link)
+                    }),
+                );
+                lines.extend(updated.iter().map(|(previous_version, item)| {
+                    format!(
+                        "Updated [{} {} to {}]({})",
+                        item.name, previous_version, item.version, item.link
+                    )
+                }));
+                lines.extend(
+                    removed
+                        .iter()
+                        .map(|item| format!("Removed {} {}", item.name, item.version)),
+                );
+                let message = lines.join("\n");
+                // discord allows up to 2000 characters per message
+                match message.chars().count() > 2000 {
+                    true => format!("{}...", message.chars().take(1990).collect::<String>()),
+                    false => message,
+                }
+            }
+            Event::Overflow { text, .. }
+            | Event::Seeding { text, .. }
+            | Event::RankMilestone { text, .. }
+            | Event::PlannedSeeding { text, .. } => text.clone(),
+        }
+    }
+
+    /// If the event may be added to the message of an earlier one. Seeding announcements and mod
+    /// changes are sent on their own so their ping and image aren't lost
+    pub fn batchable(&self) -> bool {
+        !matches!(
+            self,
+            Event::Seeding { .. } | Event::PlannedSeeding { .. } | Event::ModsChanged { .. }
+        )
+    }
+
+    /// Synthetic event for `/simulate`, based on the current info of a server
+    pub fn simulated(name: &str, server: &MarneServerInfo) -> Option<Self> {
+        let map = map_name(internal_map(&server.map_name));
+        match name {
+            "server_full" => Some(Event::ServerFull {
+                server: server.name.clone(),
+                players: server.max_players,
+                max_players: server.max_players,
+            }),
+            "offline" => Some(Event::Offline {
+                server: server.name.clone(),
+            }),
+            "map_change" => Some(Event::MapChange {
+                server: server.name.clone(),
+                previous_map: map.clone(),
+                map,
+                game_mode: server.game_mode.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Events between two updates of the monitored servers, going offline is left to `Availability`
+pub fn diff(previous: &[MarneServerInfo], current: &[MarneServerInfo]) -> Vec<Event> {
+    let mut events = vec![];
+    for before in previous {
+        let after = match current.iter().find(|server| server.id == before.id) {
+            Some(after) => after,
+            None => continue,
+        };
+        if before.map_name != after.map_name || before.game_mode != after.game_mode {
+            events.push(Event::MapChange {
+                server: after.name.clone(),
+                previous_map: map_name(internal_map(&before.map_name)),
+                map: map_name(internal_map(&after.map_name)),
+                game_mode: after.game_mode.clone(),
+            });
+        }
+        if before.current_players < before.max_players && after.current_players >= after.max_players
+        {
+            events.push(Event::ServerFull {
+                server: after.name.clone(),
+                players: after.current_players,
+                max_players: after.max_players,
+            });
+        }
+    }
+    events
+}
+
+/// Tracks servers missing from the server list, a server only counts as offline after missing a
+/// number of updates in a row so a brief marne.io blip doesn't announce it
+#[derive(Default)]
+pub struct Availability {
+    /// name of every server seen since the start
+    seen: HashMap<i64, String>,
+    /// consecutive updates a seen server was missing
+    missed: HashMap<i64, u32>,
+    offline: HashSet<i64>,
+}
+
+impl Availability {
+    /// Offline and back online events of an update
+    pub fn update(&mut self, servers: &[MarneServerInfo], offline_after: u32) -> Vec<Event> {
+        let mut events = vec![];
+        for (id, name) in &self.seen {
+            if servers.iter().any(|server| server.id == *id) {
+                self.missed.remove(id);
+                if self.offline.remove(id) {
+                    events.push(Event::Online {
+                        server: name.clone(),
+                    });
+                }
+                continue;
+            }
+            let missed = self.missed.entry(*id).or_default();
+            *missed += 1;
+            if *missed >= offline_after && self.offline.insert(*id) {
+                events.push(Event::Offline {
+                    server: name.clone(),
+                });
+            }
+        }
+        for server in servers {
+            self.seen.insert(server.id, server.name.clone());
+        }
+        events
+    }
+
+    /// Forgets the servers missing from an update, for when the monitored servers changed and the
+    /// ones that aren't monitored anymore shouldn't count as offline
+    pub fn forget_missing(&mut self, servers: &[MarneServerInfo]) {
+        self.seen
+            .retain(|id, _| servers.iter().any(|server| server.id == *id));
+        self.missed
+            .retain(|id, _| servers.iter().any(|server| server.id == *id));
+        self.offline
+            .retain(|id| servers.iter().any(|server| server.id == *id));
+    }
+
+    /// Every server seen since the start with if it is in the servers of this update
+    pub fn states(&self, servers: &[MarneServerInfo]) -> Vec<(i64, bool)> {
+        self.seen
+            .keys()
+            .map(|id| (*id, servers.iter().any(|server| server.id == *id)))
+            .collect()
+    }
+
+    /// If a server is offline
+    pub fn any_offline(&self) -> bool {
+        !self.offline.is_empty()
+    }
+
+    /// If a server is missing, but not for long enough to count as offline
+    pub fn blip(&self) -> bool {
+        self.missed.keys().any(|id| !self.offline.contains(id))
+    }
+}
+
+/// Suggesting players to join another server of a group when one is full
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Overflow {
+    pub enabled: bool,
+    /// a server counts as low below this percentage of its max players
+    pub low_percent: i64,
+    pub cooldown_mins: i64,
+    /// `{full}`, `{other}`, `{players}` and `{max_players}` are filled in
+    pub message: String,
+}
+
+impl ::std::default::Default for Overflow {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            low_percent: 50,
+            cooldown_mins: 30,
+            message: "{full} is full — {other} is at {players}/{max_players}, hop in!".into(),
+        }
+    }
+}
+
+impl Overflow {
+    /// Overflow suggestions for the groups, at most one per group per cooldown.
+    /// `suggested` keeps the time of the last suggestion per group
+    pub fn check(
+        &self,
+        groups: &[ServerGroup],
+        servers: &[MarneServerInfo],
+        suggested: &mut HashMap<String, DateTime<Utc>>,
+    ) -> Vec<Event> {
+        let mut events = vec![];
+        if !self.enabled {
+            return events;
+        }
+        for group in groups {
+            if suggested.get(&group.name).is_some_and(|time| {
+                Utc::now() - *time < chrono::Duration::minutes(self.cooldown_mins)
+            }) {
+                continue;
+            }
+            let members = group.members(servers);
+            let full = members
+                .iter()
+                .find(|server| server.current_players >= server.max_players);
+            let low = members
+                .iter()
+                .filter(|server| {
+                    server.current_players * 100 < server.max_players * self.low_percent
+                })
+                .min_by_key(|server| server.current_players);
+            if let (Some(full), Some(low)) = (full, low) {
+                let text = self
+                    .message
+                    .replace("{full}", &full.name)
+                    .replace("{other}", &low.name)
+                    .replace("{players}", &low.current_players.to_string())
+                    .replace("{max_players}", &low.max_players.to_string());
+                events.push(Event::Overflow {
+                    group: group.name.clone(),
+                    full: full.name.clone(),
+                    other: low.name.clone(),
+                    players: low.current_players,
+                    max_players: low.max_players,
+                    text,
+                });
+                suggested.insert(group.name.clone(), Utc::now());
+            }
+        }
+        events
+    }
+}
+
+/// Announcing a server that starts seeding, so players can help fill it up
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Seeding {
+    /// announce when a server goes up to this many players, disabled if not set
+    pub threshold: Option<i64>,
+    /// (optional) channel of the announcement, the alert channel if not set
+    pub channel_id: Option<u64>,
+    /// (optional) role pinged with the announcement, the alert role if not set
+    pub role_id: Option<u64>,
+    /// minutes before the same server is announced again
+    pub cooldown_mins: i64,
+    /// `{server}`, `{players}`, `{max_players}` and `{map}` are filled in
+    pub message: String,
+}
+
+impl ::std::default::Default for Seeding {
+    fn default() -> Self {
+        Self {
+            threshold: None,
+            channel_id: None,
+            role_id: None,
+            cooldown_mins: 60,
+            message: "**{server}** is seeding ({players}/{max_players} on {map}), join now!".into(),
+        }
+    }
+}
+
+impl Seeding {
+    /// Servers that went up to the threshold since the previous update, at most once per
+    /// server per cooldown. `announced` keeps the time of the last announcement per server id
+    pub fn check(
+        &self,
+        previous: &[MarneServerInfo],
+        servers: &[MarneServerInfo],
+        artwork: &Artwork,
+        announced: &mut HashMap<i64, DateTime<Utc>>,
+    ) -> Vec<Event> {
+        let mut events = vec![];
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return events,
+        };
+        for after in servers {
+            let crossed = previous.iter().any(|before| {
+                before.id == after.id
+                    && before.current_players < threshold
+                    && after.current_players >= threshold
+            });
+            if !crossed
+                || announced.get(&after.id).is_some_and(|time| {
+                    Utc::now() - *time < chrono::Duration::minutes(self.cooldown_mins)
+                })
+            {
+                continue;
+            }
+            let internal_map = internal_map(&after.map_name);
+            let map = map_name(internal_map);
+            let mut text = self
+                .message
+                .replace("{server}", &after.name)
+                .replace("{players}", &after.current_players.to_string())
+                .replace("{max_players}", &after.max_players.to_string())
+                .replace("{map}", &map);
+            if let Some(eta) = fill_rate::eta_text(after) {
+                text = format!("{}\nAt this rate it is {}.", text, eta);
+            }
+            events.push(Event::Seeding {
+                server: after.name.clone(),
+                players: after.current_players,
+                max_players: after.max_players,
+                map,
+                image: artwork.image(internal_map),
+                text,
+            });
+            announced.insert(after.id, Utc::now());
+        }
+        events
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Ranking {
+    /// announce when a server goes up to one of these places in the server list, like `[1, 3]`.
+    /// Disabled if empty
+    pub milestones: Vec<usize>,
+    /// minutes before the same server is announced again
+    pub cooldown_mins: i64,
+    /// `{server}`, `{top}`, `{rank}` and `{game}` are filled in
+    pub message: String,
+}
+
+impl ::std::default::Default for Ranking {
+    fn default() -> Self {
+        Self {
+            milestones: vec![],
+            cooldown_mins: 120,
+            message: "**{server}** is a top {top} most populated {game} Marne server right now!"
+                .into(),
+        }
+    }
+}
+
+impl Ranking {
+    /// Servers that went up to a milestone since the previous update, at most once per server
+    /// per cooldown. `previous` keeps the rank per server id of the previous update and
+    /// `announced` the time of the last announcement
+    pub fn check(
+        &self,
+        game: &str,
+        servers: &[MarneServerInfo],
+        previous: &mut HashMap<i64, usize>,
+        announced: &mut HashMap<i64, DateTime<Utc>>,
+    ) -> Vec<Event> {
+        let mut events = vec![];
+        if self.milestones.is_empty() {
+            return events;
+        }
+        for server in servers {
+            let rank = match marne::rank(server.id) {
+                // an empty server sharing the first place with the others isn't a milestone
+                Some(rank) if server.current_players > 0 => rank.overall,
+                _ => {
+                    previous.remove(&server.id);
+                    continue;
+                }
+            };
+            let before = previous.insert(server.id, rank);
+            // the best milestone that was reached this update
+            let top = self
+                .milestones
+                .iter()
+                .filter(|top| rank <= **top && before.is_some_and(|before| before > **top))
+                .min();
+            let top = match top {
+                Some(top) => *top,
+                None => continue,
+            };
+            if announced.get(&server.id).is_some_and(|time| {
+                Utc::now() - *time < chrono::Duration::minutes(self.cooldown_mins)
+            }) {
+                continue;
+            }
+            let text = self
+                .message
+                .replace("{server}", &server.name)
+                .replace("{top}", &top.to_string())
+                .replace("{rank}", &rank.to_string())
+                .replace("{game}", &game.to_uppercase());
+            events.push(Event::RankMilestone {
+                server: server.name.clone(),
+                rank,
+                text,
+            });
+            announced.insert(server.id, Utc::now());
+        }
+        events
+    }
+}
+
+/// Mods of a server that were added, removed or changed version, `None` if nothing changed
+pub fn mod_changes(server: &MarneServerInfo, before: &[Mod], after: &[Mod]) -> Option<Event> {
+    let added: Vec<Mod> = after
+        .iter()
+        .filter(|item| !before.iter().any(|old| old.name == item.name))
+        .cloned()
+        .collect();
+    let removed: Vec<Mod> = before
+        .iter()
+        .filter(|old| !after.iter().any(|item| item.name == old.name))
+        .cloned()
+        .collect();
+    let updated: Vec<(String, Mod)> = after
+        .iter()
+        .filter_map(|item| {
+            before
+                .iter()
+                .find(|old| old.name == item.name && old.version != item.version)
+                .map(|old| (old.version.clone(), item.clone()))
+        })
+        .collect();
+    if added.is_empty() && removed.is_empty() && updated.is_empty() {
+        return None;
+    }
+    Some(Event::ModsChanged {
+        server: server.name.clone(),
+        added,
+        removed,
+        updated,
+    })
+}
+
+/// Channel (and optional role to ping) that receives the events
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Alerts {
+    pub channel_id: Option<u64>,
+    pub role_id: Option<u64>,
+    /// fetch the mods of the servers every update and announce when they change
+    pub mod_changes: bool,
+    /// events within this many seconds of an alert are added to its message instead of sent
+    /// on their own, 0 to send them all
+    pub batch_window_secs: u64,
+    /// events shown in a batched message, the ones in between are counted
+    pub batch_max_lines: usize,
+}
+
+impl ::std::default::Default for Alerts {
+    fn default() -> Self {
+        Self {
+            channel_id: None,
+            role_id: None,
+            mod_changes: false,
+            batch_window_secs: 60,
+            batch_max_lines: 10,
+        }
+    }
+}
+
+/// Sends the events of the monitor loop (and `/simulate`) to the alert outputs
+pub struct EventBus {
+    alerts: Alerts,
+    seeding: Seeding,
+    budget: Arc<RateBudget>,
+    /// guilds that publish the events in announcement channels, shared with `/crosspost`
+    crosspost: Arc<RwLock<HashMap<String, bool>>>,
+    batcher: Batcher,
+}
+
+impl EventBus {
+    pub fn new(
+        alerts: Alerts,
+        seeding: Seeding,
+        budget: Arc<RateBudget>,
+        crosspost: Arc<RwLock<HashMap<String, bool>>>,
+    ) -> Self {
+        Self {
+            batcher: Batcher::new(alerts.batch_window_secs, alerts.batch_max_lines),
+            alerts,
+            seeding,
+            budget,
+            crosspost,
+        }
+    }
+
+    pub async fn publish(&self, ctx: &Context, event: &Event, simulated: bool) -> Result<()> {
+        log::info!("Event: {:?}", event);
+        let (channel_id, role_id) = match event {
+            Event::Seeding { .. } | Event::PlannedSeeding { .. } => (
+                self.seeding.channel_id.or(self.alerts.channel_id),
+                self.seeding.role_id.or(self.alerts.role_id),
+            ),
+            _ => (self.alerts.channel_id, self.alerts.role_id),
+        };
+        let channel_id = match config::channel_id(channel_id) {
+            Some(channel_id) => channel_id,
+            None => return Ok(()),
+        };
+        let mut content = event.message();
+        if simulated {
+            content = format!("[simulated] {}", content);
+        }
+        let batched = !simulated && event.batchable();
+        if batched {
+            if let Some(batch) = self.batcher.add(channel_id, content.clone()) {
+                return self.edit_batch(ctx, channel_id, &batch).await;
+            }
+        }
+        let mut message = CreateMessage::new();
+        if let Event::Seeding {
+            image: Some(image), ..
+        } = event
+        {
+            message = message.embed(CreateEmbed::new().image(image));
+        }
+        if let Some(role_id) = role_id.filter(|role_id| *role_id != 0) {
+            content = format!("<@&{}> {}", role_id, content);
+            message = message
+                .allowed_mentions(CreateAllowedMentions::new().roles(vec![RoleId::new(role_id)]));
+        }
+        let sent = channel_id
+            .send_message(&ctx.http, message.content(&content))
+            .await?;
+        self.budget
+            .record_channel(&ctx.cache, channel_id, budget::Action::Message);
+        if batched {
+            self.batcher.start(channel_id, sent.id, content);
+        }
+        if !simulated {
+            self.crosspost(ctx, &sent).await;
+        }
+        Ok(())
+    }
+
+    /// Updates the message of a batch with its new event, within the edit budget. Events over
+    /// the budget show up with the next edit of the batch
+    async fn edit_batch(&self, ctx: &Context, channel_id: ChannelId, batch: &Batch) -> Result<()> {
+        let guild_id = budget::guild_of(&ctx.cache, channel_id);
+        if !self.budget.allows(guild_id, budget::Action::Edit) {
+            return Ok(());
+        }
+        let edit = EditMessage::new()
+            .content(batch.content(self.batcher.max_lines()))
+            .allowed_mentions(CreateAllowedMentions::new());
+        channel_id
+            .edit_message(&ctx.http, batch.message_id, edit)
+            .await?;
+        self.budget.record(guild_id, budget::Action::Edit);
+        Ok(())
+    }
+
+    /// Publishes a message in an announcement channel to the following servers, if the guild
+    /// turned it on
+    async fn crosspost(&self, ctx: &Context, message: &Message) {
+        let guild_id = match message.guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+        let news = ctx.cache.guild(guild_id).is_some_and(|guild| {
+            guild
+                .channels
+                .get(&message.channel_id)
+                .is_some_and(|channel| channel.kind == ChannelType::News)
+        });
+        if !news {
+            return;
+        }
+        let enabled = self
+            .crosspost
+            .read()
+            .unwrap()
+            .get(&guild_id.to_string())
+            .copied()
+            .unwrap_or_default();
+        if !enabled {
+            return;
+        }
+        match message.crosspost(&ctx.http).await {
+            Ok(_) => self
+                .budget
+                .record(Some(guild_id), budget::Action::Crosspost),
+            Err(e) => log::error!("Failed to publish event: {:#?}", e),
+        }
+    }
+}
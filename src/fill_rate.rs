@@ -0,0 +1,68 @@
+use crate::marne::MarneServerInfo;
+use chrono::Utc;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+};
+
+/// Player counts of this many recent minutes give the join rate
+const WINDOW_SECS: i64 = 10 * 60;
+/// Shorter spans are too noisy for an estimate
+const MIN_SPAN_SECS: i64 = 3 * 60;
+/// Further estimates are guesses, they aren't shown
+const MAX_ETA_MINUTES: i64 = 90;
+
+/// Recent player counts per server id, to estimate when a filling server is full
+pub struct FillRates {
+    samples: Mutex<BTreeMap<i64, VecDeque<(i64, i64)>>>,
+}
+
+pub static FILL_RATES: FillRates = FillRates {
+    samples: Mutex::new(BTreeMap::new()),
+};
+
+impl FillRates {
+    /// Stores the player counts of an update, servers that are gone are forgotten
+    pub fn record(&self, servers: &[MarneServerInfo]) {
+        let now = Utc::now().timestamp();
+        let mut samples = self.samples.lock().unwrap();
+        samples.retain(|server_id, _| servers.iter().any(|server| server.id == *server_id));
+        for server in servers {
+            let counts = samples.entry(server.id).or_default();
+            counts.push_back((now, server.current_players));
+            while counts
+                .front()
+                .is_some_and(|(time, _)| now - time > WINDOW_SECS)
+            {
+                counts.pop_front();
+            }
+        }
+    }
+
+    /// Minutes until the server is likely full, rounded to 5. `None` if it isn't filling up
+    /// or it would take too long to say
+    pub fn eta_minutes(&self, server: &MarneServerInfo) -> Option<i64> {
+        let samples = self.samples.lock().unwrap();
+        let counts = samples.get(&server.id)?;
+        let (first_time, first_players) = *counts.front()?;
+        let (last_time, last_players) = *counts.back()?;
+        let span = last_time - first_time;
+        let missing = server.max_players - server.current_players;
+        if span < MIN_SPAN_SECS || last_players <= first_players || missing <= 0 {
+            return None;
+        }
+        let players_per_minute = (last_players - first_players) as f64 * 60.0 / span as f64;
+        let minutes = (missing as f64 / players_per_minute).ceil() as i64;
+        match minutes <= MAX_ETA_MINUTES {
+            true => Some(((minutes + 4) / 5 * 5).max(5)),
+            false => None,
+        }
+    }
+}
+
+/// Like `likely full in ~20 min`, `None` if the server isn't filling up
+pub fn eta_text(server: &MarneServerInfo) -> Option<String> {
+    FILL_RATES
+        .eta_minutes(server)
+        .map(|minutes| format!("likely full in ~{} min", minutes))
+}
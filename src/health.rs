@@ -0,0 +1,695 @@
+use crate::{
+    anomalies::ANOMALIES,
+    apikeys::ApiKeys,
+    budget::RateBudget,
+    config::Artwork,
+    history::Uptime,
+    http_auth::{HttpAuth, Route},
+    images,
+    marne::{self, MarneServerInfo, Mod},
+    status_page,
+    version::BuildInfo,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::{atomic, Arc, Mutex, OnceLock},
+    time,
+};
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use warp::{
+    http::StatusCode,
+    reply::{Html, Json, Reply, WithStatus},
+    Filter,
+};
+
+/// Exit code used when marne.io failed for longer than `hard_fail_after_mins`
+pub const EXIT_MARNE_UNREACHABLE: i32 = 3;
+/// Exit code used when Discord was disconnected for longer than `hard_fail_after_mins`
+pub const EXIT_DISCORD_DISCONNECTED: i32 = 4;
+
+/// Timestamps (in seconds) checked by the optional hard-fail mode
+pub struct Watchdog {
+    pub last_marne_ok: atomic::AtomicI64,
+    /// 0 while connected to Discord
+    pub discord_down_since: atomic::AtomicI64,
+}
+
+impl ::std::default::Default for Watchdog {
+    fn default() -> Self {
+        let now = Utc::now().timestamp();
+        Self {
+            last_marne_ok: atomic::AtomicI64::new(now),
+            discord_down_since: atomic::AtomicI64::new(now),
+        }
+    }
+}
+
+impl Watchdog {
+    /// Exits the process with a distinct exit code if marne.io or Discord keep failing,
+    /// so orchestrators like Docker restart the bot instead of it running degraded forever
+    pub async fn run(&self, hard_fail_after_mins: i64) {
+        let limit = hard_fail_after_mins * 60;
+        loop {
+            tokio::time::sleep(time::Duration::from_secs(30)).await;
+            let now = Utc::now().timestamp();
+            let discord_down_since = self.discord_down_since.load(atomic::Ordering::Relaxed);
+            if now - self.last_marne_ok.load(atomic::Ordering::Relaxed) > limit {
+                log::error!(
+                    "marne.io failed for over {} minutes, exiting",
+                    hard_fail_after_mins
+                );
+                std::process::exit(EXIT_MARNE_UNREACHABLE);
+            }
+            if discord_down_since != 0 && now - discord_down_since > limit {
+                log::error!(
+                    "Discord disconnected for over {} minutes, exiting",
+                    hard_fail_after_mins
+                );
+                std::process::exit(EXIT_DISCORD_DISCONNECTED);
+            }
+        }
+    }
+}
+
+/// Escapes a Prometheus label value
+pub fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Last known state of a monitored server
+#[derive(Clone)]
+struct ServerGauge {
+    name: String,
+    players: i64,
+    max_players: i64,
+    up: bool,
+}
+
+/// Player counts of the monitored servers, labeled per server for the metrics
+#[derive(Default)]
+pub struct ServerMetrics {
+    servers: Mutex<BTreeMap<i64, ServerGauge>>,
+}
+
+impl ServerMetrics {
+    /// Stores the servers of an update, servers seen before but missing now are reported down
+    pub fn update(&self, servers: &[MarneServerInfo]) {
+        let mut gauges = self.servers.lock().unwrap();
+        for gauge in gauges.values_mut() {
+            gauge.players = 0;
+            gauge.up = false;
+        }
+        for server in servers {
+            gauges.insert(
+                server.id,
+                ServerGauge {
+                    name: server.name.clone(),
+                    players: server.current_players,
+                    max_players: server.max_players,
+                    up: true,
+                },
+            );
+        }
+    }
+
+    /// Prometheus text format of the player counts of the servers of every bot
+    pub fn metrics(bots: &[Arc<Self>]) -> String {
+        let mut gauges = BTreeMap::new();
+        for bot in bots {
+            for (server_id, gauge) in bot.servers.lock().unwrap().iter() {
+                gauges.entry(*server_id).or_insert_with(|| gauge.clone());
+            }
+        }
+        gauge_metric(
+            &gauges,
+            "marne_bot_server_players",
+            "Players on the server",
+            |gauge| gauge.players,
+        ) + &gauge_metric(
+            &gauges,
+            "marne_bot_server_max_players",
+            "Player slots of the server",
+            |gauge| gauge.max_players,
+        ) + &gauge_metric(
+            &gauges,
+            "marne_bot_server_up",
+            "If the server is in the marne.io server list",
+            |gauge| i64::from(gauge.up),
+        )
+    }
+}
+
+fn gauge_metric(
+    gauges: &BTreeMap<i64, ServerGauge>,
+    metric: &str,
+    help: &str,
+    value: fn(&ServerGauge) -> i64,
+) -> String {
+    let mut metrics = format!("# HELP {} {}\n# TYPE {} gauge\n", metric, help, metric);
+    for (server_id, gauge) in gauges {
+        metrics += &format!(
+            "{}{{server_id=\"{}\",server_name=\"{}\"}} {}\n",
+            metric,
+            server_id,
+            label(&gauge.name),
+            value(gauge)
+        );
+    }
+    metrics
+}
+
+/// A monitored server as shown on `/status.json`
+#[derive(Serialize, Clone)]
+pub struct ServerStatus {
+    #[serde(flatten)]
+    pub info: MarneServerInfo,
+    /// Human readable map and mode
+    pub map: String,
+    pub mode: String,
+    /// `None` when the server details couldn't be fetched
+    pub mods: Option<Vec<Mod>>,
+    /// `None` when the history isn't recorded
+    pub uptime: Option<Uptime>,
+    /// names for the status page, `None` when they aren't fetched or are hidden
+    #[serde(skip)]
+    pub players: Option<Vec<String>>,
+    /// player counts of the last 24 hours for the status page
+    #[serde(skip)]
+    pub sparkline: Vec<Option<f64>>,
+}
+
+#[derive(Serialize, Default)]
+struct StatusSnapshot {
+    /// Unix timestamp of the last successful update
+    last_update: Option<i64>,
+    servers: Vec<ServerStatus>,
+}
+
+/// Wakes the `/events` streams when a monitor loop finished an update
+fn updates() -> &'static broadcast::Sender<()> {
+    static UPDATES: OnceLock<broadcast::Sender<()>> = OnceLock::new();
+    UPDATES.get_or_init(|| broadcast::channel(16).0)
+}
+
+/// Pushes the servers of the last update to the `/events` streams, called after every update
+pub fn publish_status() {
+    // fails without streams, nobody to push to
+    let _ = updates().send(());
+}
+
+/// Servers of the last update, so dashboards don't have to poll marne.io themselves
+#[derive(Default)]
+pub struct LastStatus {
+    snapshot: Mutex<StatusSnapshot>,
+    /// PNG of `/banner.png`, drawn every update
+    banner: Mutex<Option<Vec<u8>>>,
+    /// JPEG of the bot's avatar, attached to `/status`
+    avatar: Mutex<Option<Vec<u8>>>,
+}
+
+impl LastStatus {
+    /// Mods of the server from the last update with the map they were fetched on, `None` if
+    /// the server is new or the last fetch failed
+    pub fn mods(&self, server_id: i64) -> Option<(String, Vec<Mod>)> {
+        let snapshot = self.snapshot.lock().unwrap();
+        snapshot
+            .servers
+            .iter()
+            .find(|status| status.info.id == server_id)
+            .and_then(|status| Some((status.info.map_name.clone(), status.mods.clone()?)))
+    }
+
+    /// Sets the uptime of a server of the last update
+    pub fn set_uptime(&self, server_id: i64, uptime: Uptime) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        if let Some(status) = snapshot
+            .servers
+            .iter_mut()
+            .find(|status| status.info.id == server_id)
+        {
+            status.uptime = Some(uptime);
+        }
+    }
+
+    /// Sets the player names of a server of the last update for the status page
+    pub fn set_players(&self, server_id: i64, players: Vec<String>) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        if let Some(status) = snapshot
+            .servers
+            .iter_mut()
+            .find(|status| status.info.id == server_id)
+        {
+            status.players = Some(players);
+        }
+    }
+
+    /// Sets the sparkline of a server of the last update for the status page
+    pub fn set_sparkline(&self, server_id: i64, sparkline: Vec<Option<f64>>) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        if let Some(status) = snapshot
+            .servers
+            .iter_mut()
+            .find(|status| status.info.id == server_id)
+        {
+            status.sparkline = sparkline;
+        }
+    }
+
+    pub fn update(&self, servers: Vec<ServerStatus>) {
+        *self.snapshot.lock().unwrap() = StatusSnapshot {
+            last_update: Some(Utc::now().timestamp()),
+            servers,
+        };
+    }
+
+    pub fn set_banner(&self, banner: Vec<u8>) {
+        *self.banner.lock().unwrap() = Some(banner);
+    }
+
+    pub fn set_avatar(&self, avatar: Vec<u8>) {
+        *self.avatar.lock().unwrap() = Some(avatar);
+    }
+
+    /// The avatar the bot set last, `None` until the first change
+    pub fn avatar(&self) -> Option<Vec<u8>> {
+        self.avatar.lock().unwrap().clone()
+    }
+
+    /// The banner of the first bot that drew one
+    fn banner(bots: &[Arc<Self>]) -> Option<Vec<u8>> {
+        bots.iter()
+            .find_map(|bot| bot.banner.lock().unwrap().clone())
+    }
+
+    /// The servers of every bot, a server monitored by several bots is listed once
+    fn merged(bots: &[Arc<Self>]) -> StatusSnapshot {
+        let mut merged = StatusSnapshot::default();
+        for bot in bots {
+            let snapshot = bot.snapshot.lock().unwrap();
+            merged.last_update = merged.last_update.max(snapshot.last_update);
+            for status in &snapshot.servers {
+                if !merged
+                    .servers
+                    .iter()
+                    .any(|merged| merged.info.id == status.info.id)
+                {
+                    merged.servers.push(status.clone());
+                }
+            }
+        }
+        merged
+    }
+
+    fn json(bots: &[Arc<Self>]) -> Json {
+        warp::reply::json(&Self::merged(bots))
+    }
+
+    fn page(bots: &[Arc<Self>]) -> Html<String> {
+        let merged = Self::merged(bots);
+        warp::reply::html(status_page::render(&merged.servers, merged.last_update))
+    }
+
+    /// A server of the last update in the endpoint schema of shields.io, the first one without
+    /// an id. Colored by how full the server is
+    fn badge(bots: &[Arc<Self>], server_id: Option<i64>) -> Json {
+        let merged = Self::merged(bots);
+        let status = merged
+            .servers
+            .iter()
+            .find(|status| server_id.is_none_or(|id| status.info.id == id));
+        let badge = match status {
+            Some(status) => {
+                let server = &status.info;
+                let fill = server.current_players as f64 / server.max_players.max(1) as f64;
+                let color = match fill {
+                    fill if fill >= 0.75 => "brightgreen",
+                    fill if fill >= 0.4 => "green",
+                    fill if fill >= 0.1 => "yellow",
+                    fill if fill > 0.0 => "orange",
+                    _ => "red",
+                };
+                Badge {
+                    schema_version: 1,
+                    label: server.name.clone(),
+                    message: format!(
+                        "{}/{} – {}",
+                        server.current_players, server.max_players, status.map
+                    ),
+                    color,
+                }
+            }
+            None => Badge {
+                schema_version: 1,
+                label: "server".into(),
+                message: "offline".into(),
+                color: "lightgrey",
+            },
+        };
+        warp::reply::json(&badge)
+    }
+}
+
+/// Endpoint badge of shields.io, see https://shields.io/badges/endpoint-badge
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Badge {
+    schema_version: u8,
+    label: String,
+    message: String,
+    color: &'static str,
+}
+
+/// Networks that may use the routes next to the health check, everyone if empty
+#[derive(Default)]
+pub struct Allowlist {
+    networks: Vec<(IpAddr, u32)>,
+}
+
+impl Allowlist {
+    /// Parses addresses and networks like `10.0.0.0/8`, invalid ones are logged and skipped
+    pub fn new(networks: &[String]) -> Self {
+        let networks = networks
+            .iter()
+            .filter_map(|network| {
+                let (ip, prefix) = match network.split_once('/') {
+                    Some((ip, prefix)) => (ip, prefix.parse().ok()),
+                    None => (network.as_str(), None),
+                };
+                let ip: IpAddr = match ip.trim().parse() {
+                    Ok(ip) => ip,
+                    Err(e) => {
+                        log::error!("Invalid network {} in the allowlist: {}", network, e);
+                        return None;
+                    }
+                };
+                let bits = match ip {
+                    IpAddr::V4(_) => 32,
+                    IpAddr::V6(_) => 128,
+                };
+                Some((ip, prefix.unwrap_or(bits).min(bits)))
+            })
+            .collect();
+        Self { networks }
+    }
+
+    pub fn allows(&self, addr: Option<SocketAddr>) -> bool {
+        if self.networks.is_empty() {
+            return true;
+        }
+        let ip = match addr.map(|addr| addr.ip()) {
+            // clients of a dual stack socket show up as IPv4-mapped IPv6
+            Some(IpAddr::V6(ip)) => ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4),
+            Some(ip) => ip,
+            None => return false,
+        };
+        self.networks
+            .iter()
+            .any(|(network, prefix)| match (network, ip) {
+                (IpAddr::V4(network), IpAddr::V4(ip)) => same_prefix(
+                    u32::from(*network).into(),
+                    u32::from(ip).into(),
+                    32,
+                    *prefix,
+                ),
+                (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                    same_prefix(u128::from(*network), u128::from(ip), 128, *prefix)
+                }
+                _ => false,
+            })
+    }
+}
+
+fn same_prefix(network: u128, ip: u128, bits: u32, prefix: u32) -> bool {
+    let shift = bits - prefix;
+    shift >= bits || network >> shift == ip >> shift
+}
+
+fn forbidden<T: Reply + 'static>(
+    allowlist: &Allowlist,
+    addr: Option<SocketAddr>,
+    reply: T,
+) -> Box<dyn Reply> {
+    match allowlist.allows(addr) {
+        true => Box::new(reply),
+        false => Box::new(warp::reply::with_status("Forbidden", StatusCode::FORBIDDEN)),
+    }
+}
+
+/// Server query of `/badge.json`
+#[derive(Deserialize)]
+struct BadgeServer {
+    server: Option<i64>,
+}
+
+/// Size query of `/image/map/`
+#[derive(Deserialize)]
+struct ImageSize {
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+/// The (replaced) image of an internal map name as JPEG
+async fn map_image(
+    artwork: Arc<Artwork>,
+    internal_map: String,
+    size: ImageSize,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let url = match artwork.image(&internal_map) {
+        Some(url) => url,
+        None => {
+            return Ok(Box::new(warp::reply::with_status(
+                "Unknown map",
+                StatusCode::NOT_FOUND,
+            )))
+        }
+    };
+    match images::served_map_image(&url, size.width, size.height).await {
+        Ok(bytes) => Ok(Box::new(warp::reply::with_header(
+            warp::reply::with_header(bytes, "content-type", "image/jpeg"),
+            "cache-control",
+            "public, max-age=86400",
+        ))),
+        Err(e) => {
+            log::warn!("Failed to serve the image of {}: {}", internal_map, e);
+            Ok(Box::new(warp::reply::with_status(
+                "Map image unavailable",
+                StatusCode::BAD_GATEWAY,
+            )))
+        }
+    }
+}
+
+/// JSON reply of an `/api/` route, if the key of the request is allowed
+fn api_reply<T: Serialize>(
+    api_keys: &ApiKeys,
+    key: Option<String>,
+    body: impl FnOnce() -> T,
+) -> WithStatus<Json> {
+    match api_keys.authorize(key.as_deref()) {
+        Ok(()) => warp::reply::with_status(warp::reply::json(&body()), StatusCode::OK),
+        Err(status) => warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "error": status.canonical_reason() })),
+            status,
+        ),
+    }
+}
+
+/// `true` if the `Authorization` header of the request allows a route
+fn authorized(
+    auth: &Arc<HttpAuth>,
+    route: Route,
+) -> impl Filter<Extract = (bool,), Error = warp::Rejection> + Clone {
+    let auth = Arc::clone(auth);
+    warp::header::optional::<String>("authorization")
+        .map(move |authorization: Option<String>| auth.allows(route, authorization.as_deref()))
+}
+
+/// Answer of a private route without valid credentials, browsers ask for the username and
+/// password
+fn unauthorized() -> Box<dyn Reply> {
+    Box::new(warp::reply::with_header(
+        warp::reply::with_status("Unauthorized", StatusCode::UNAUTHORIZED),
+        "www-authenticate",
+        "Basic realm=\"marne-bot\"",
+    ))
+}
+
+/// Serves the health check on `/`, the build info on `/api/version`, the metrics on `/metrics`
+/// the last update on `/status.json` and `/events`, the banner on `/banner.png`, a shields.io
+/// badge on `/badge.json`, the map images
+/// on `/image/map/<internal map name>` and the status page on `/` for browsers. The health check
+/// fails when the last update (in minutes) is over `stale_minutes` ago
+#[allow(clippy::too_many_arguments)]
+pub async fn serve(
+    addr: SocketAddr,
+    stale_minutes: i64,
+    last_update: Arc<atomic::AtomicI64>,
+    build_info: Arc<BuildInfo>,
+    budget: Arc<RateBudget>,
+    servers: Vec<Arc<ServerMetrics>>,
+    api_keys: Arc<ApiKeys>,
+    allowlist: Arc<Allowlist>,
+    status: Vec<Arc<LastStatus>>,
+    artwork: Arc<Artwork>,
+    status_page: bool,
+    auth: Arc<HttpAuth>,
+) {
+    let version_allowlist = Arc::clone(&allowlist);
+    let version = warp::path!("api" / "version")
+        .and(authorized(&auth, Route::Api))
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and(warp::addr::remote())
+        .map(move |authorized, key, addr| match authorized {
+            true => forbidden(
+                &version_allowlist,
+                addr,
+                api_reply(&api_keys, key, || build_info.report()),
+            ),
+            false => unauthorized(),
+        });
+    let hello = warp::any().and(authorized(&auth, Route::Health)).map(
+        move |authorized: bool| -> Box<dyn Reply> {
+            if !authorized {
+                return unauthorized();
+            }
+            let last_update_i64 = last_update.load(atomic::Ordering::Relaxed);
+            let now_minutes = Utc::now().timestamp() / 60;
+            if (now_minutes - last_update_i64) > stale_minutes {
+                Box::new(warp::reply::with_status(
+                    format!("{}", now_minutes - last_update_i64),
+                    warp::http::StatusCode::SERVICE_UNAVAILABLE,
+                ))
+            } else {
+                Box::new(warp::reply::with_status(
+                    format!("{}", now_minutes - last_update_i64),
+                    warp::http::StatusCode::OK,
+                ))
+            }
+        },
+    );
+    let metrics = warp::path!("metrics")
+        .and(authorized(&auth, Route::Metrics))
+        .and(warp::addr::remote())
+        .map(move |authorized, addr| match authorized {
+            true => forbidden(
+                &allowlist,
+                addr,
+                ServerMetrics::metrics(&servers)
+                    + &budget.metrics()
+                    + &ANOMALIES.metrics()
+                    + &marne::source_metrics()
+                    + &marne::population_metrics(),
+            ),
+            false => unauthorized(),
+        });
+    // browsers get the status page on `/`, the health check keeps answering everything else
+    let page_status = status.clone();
+    let page = warp::path::end()
+        .and(authorized(&auth, Route::Page))
+        .and(warp::header::optional::<String>("accept"))
+        .and_then(move |authorized, accept: Option<String>| {
+            let page =
+                match status_page && accept.is_some_and(|accept| accept.contains("text/html")) {
+                    true if authorized => {
+                        Ok(Box::new(LastStatus::page(&page_status)) as Box<dyn Reply>)
+                    }
+                    true => Ok(unauthorized()),
+                    false => Err(warp::reject::not_found()),
+                };
+            async move { page }
+        });
+    // the servers of `/status.json` on connect and after every update, without polling
+    let events_status = status.clone();
+    let events = warp::path!("events")
+        .and(authorized(&auth, Route::Events))
+        .map(move |authorized: bool| -> Box<dyn Reply> {
+            if !authorized {
+                return unauthorized();
+            }
+            let bots = events_status.clone();
+            // a stream that lagged behind gets the latest servers, like every other update
+            let updates = BroadcastStream::new(updates().subscribe()).map(|_| ());
+            let stream = tokio_stream::once(()).chain(updates).map(move |_| {
+                warp::sse::Event::default()
+                    .event("status")
+                    .json_data(LastStatus::merged(&bots))
+            });
+            Box::new(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+        })
+        .with(warp::cors().allow_any_origin().allow_method("GET"));
+    let banner_status = status.clone();
+    let banner = warp::path!("banner.png")
+        .and(authorized(&auth, Route::Banner))
+        .map(move |authorized: bool| -> Box<dyn Reply> {
+            if !authorized {
+                return unauthorized();
+            }
+            match LastStatus::banner(&banner_status) {
+                Some(png) => Box::new(warp::reply::with_header(
+                    warp::reply::with_header(png, "content-type", "image/png"),
+                    "cache-control",
+                    "no-cache",
+                )),
+                None => Box::new(warp::reply::with_status(
+                    "No banner yet",
+                    StatusCode::SERVICE_UNAVAILABLE,
+                )),
+            }
+        })
+        .with(warp::cors().allow_any_origin().allow_method("GET"));
+    let badge_status = status.clone();
+    let badge = warp::path!("badge.json")
+        .and(warp::query::<BadgeServer>())
+        .and(authorized(&auth, Route::Badge))
+        .map(move |query: BadgeServer, authorized| -> Box<dyn Reply> {
+            match authorized {
+                true => Box::new(LastStatus::badge(&badge_status, query.server)),
+                false => unauthorized(),
+            }
+        })
+        .with(warp::cors().allow_any_origin().allow_method("GET"));
+    // public like the Discord status, website widgets fetch it from other origins
+    let status = warp::path!("status.json")
+        .and(authorized(&auth, Route::Status))
+        .map(move |authorized: bool| -> Box<dyn Reply> {
+            match authorized {
+                true => Box::new(LastStatus::json(&status)),
+                false => unauthorized(),
+            }
+        })
+        .with(warp::cors().allow_any_origin().allow_method("GET"));
+    let image = warp::path!("image" / "map" / String)
+        .and(warp::query::<ImageSize>())
+        .and(authorized(&auth, Route::Image))
+        .and_then(move |internal_map, size, authorized| {
+            let artwork = Arc::clone(&artwork);
+            async move {
+                match authorized {
+                    true => map_image(artwork, internal_map, size).await,
+                    false => Ok(unauthorized()),
+                }
+            }
+        });
+    log::info!("Health check listening on {}", addr);
+    warp::serve(
+        version
+            .or(metrics)
+            .or(status)
+            .or(events)
+            .or(banner)
+            .or(badge)
+            .or(image)
+            .or(page)
+            .or(hello),
+    )
+    .run(addr)
+    .await;
+}
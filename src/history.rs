@@ -0,0 +1,597 @@
+use crate::sessions::PlayerChanges;
+use anyhow::Result;
+use chrono::{DateTime, Months, NaiveDate, NaiveDateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Player count history of the monitored servers, stored in SQLite
+pub struct History {
+    conn: Mutex<Connection>,
+}
+
+/// A single point in the player count history
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    pub server_id: Option<i64>,
+    pub server_name: String,
+    pub players: i64,
+    pub max_players: Option<i64>,
+    pub map: Option<String>,
+    pub game_mode: Option<String>,
+    /// where the entry came from, `marne-bot` or the import format
+    pub source: String,
+}
+
+impl History {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS server_history (
+                timestamp INTEGER NOT NULL,
+                server_id INTEGER,
+                server_name TEXT NOT NULL,
+                players INTEGER NOT NULL,
+                max_players INTEGER,
+                map TEXT,
+                game_mode TEXT,
+                source TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS server_history_timestamp ON server_history (timestamp);
+            CREATE TABLE IF NOT EXISTS server_availability (
+                timestamp INTEGER NOT NULL,
+                server_id INTEGER NOT NULL,
+                up INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS server_availability_server
+                ON server_availability (server_id, timestamp);
+            CREATE TABLE IF NOT EXISTS player_sessions (
+                server_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                joined INTEGER NOT NULL,
+                left INTEGER,
+                at_start INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS player_sessions_joined
+                ON player_sessions (server_id, joined);
+            -- sessions still open when the bot stopped end at its last update
+            UPDATE player_sessions
+                SET left = MAX(joined, COALESCE((SELECT MAX(timestamp) FROM server_history), 0))
+                WHERE left IS NULL;",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn record(&self, entry: &Entry) -> Result<()> {
+        self.insert_many(std::slice::from_ref(entry))?;
+        Ok(())
+    }
+
+    /// Inserts all entries in one transaction, returns the amount inserted
+    pub fn insert_many(&self, entries: &[Entry]) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO server_history
+                    (timestamp, server_id, server_name, players, max_players, map, game_mode, source)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for entry in entries {
+                stmt.execute(params![
+                    entry.timestamp.timestamp(),
+                    entry.server_id,
+                    entry.server_name,
+                    entry.players,
+                    entry.max_players,
+                    entry.map,
+                    entry.game_mode,
+                    entry.source,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(entries.len())
+    }
+
+    /// Stores if each monitored server was in the server list of an update
+    pub fn record_availability(
+        &self,
+        timestamp: DateTime<Utc>,
+        states: &[(i64, bool)],
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO server_availability (timestamp, server_id, up) VALUES (?1, ?2, ?3)",
+            )?;
+            for (server_id, up) in states {
+                stmt.execute(params![timestamp.timestamp(), server_id, up])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Percentage of the updates over a period a server was up, `None` without updates
+    pub fn uptime(
+        &self,
+        server_id: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Option<f64>> {
+        let conn = self.conn.lock().unwrap();
+        let uptime = conn.query_row(
+            "SELECT AVG(up) * 100 FROM server_availability
+            WHERE server_id = ?1 AND timestamp >= ?2 AND timestamp < ?3",
+            params![server_id, from.timestamp(), to.timestamp()],
+            |row| row.get(0),
+        )?;
+        Ok(uptime)
+    }
+
+    /// Uptime of a server over the last 24 hours, 7 days and 30 days
+    pub fn uptimes(&self, server_id: i64) -> Result<Uptime> {
+        let now = Utc::now();
+        let since = |days| self.uptime(server_id, now - chrono::Duration::days(days), now);
+        Ok(Uptime {
+            day: since(1)?,
+            week: since(7)?,
+            month: since(30)?,
+        })
+    }
+
+    /// Opens a session for the players that joined a server and ends the ones of the players
+    /// that left
+    pub fn record_sessions(
+        &self,
+        server_id: i64,
+        changes: &PlayerChanges,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut open = tx.prepare(
+                "INSERT INTO player_sessions (server_id, name, joined, left, at_start)
+                VALUES (?1, ?2, ?3, NULL, ?4)",
+            )?;
+            for name in &changes.joined {
+                open.execute(params![
+                    server_id,
+                    name,
+                    timestamp.timestamp(),
+                    changes.baseline
+                ])?;
+            }
+            let mut close = tx.prepare(
+                "UPDATE player_sessions SET left = ?3
+                WHERE server_id = ?1 AND name = ?2 AND left IS NULL",
+            )?;
+            for name in &changes.left {
+                close.execute(params![server_id, name, timestamp.timestamp()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Players that joined a server since a time, newest first. Players that were already on
+    /// when the bot started aren't included
+    pub fn recent_sessions(&self, server_id: i64, since: DateTime<Utc>) -> Result<Vec<Session>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT name, joined, left FROM player_sessions
+            WHERE server_id = ?1 AND joined >= ?2 AND at_start = 0
+            ORDER BY joined DESC",
+        )?;
+        let sessions = stmt
+            .query_map(params![server_id, since.timestamp()], |row| {
+                Ok(Session {
+                    name: row.get(0)?,
+                    joined: row.get(1)?,
+                    left: row.get(2)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(sessions)
+    }
+
+    /// Population statistics of a server over a period, of all servers if `server_id` is `None`
+    pub fn period_stats(
+        &self,
+        server_id: Option<i64>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<PeriodStats> {
+        let conn = self.conn.lock().unwrap();
+        let (samples, average_players, peak_players) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(AVG(players), 0), COALESCE(MAX(players), 0)
+            FROM server_history
+            WHERE timestamp >= ?1 AND timestamp < ?2 AND (?3 IS NULL OR server_id = ?3)",
+            params![from.timestamp(), to.timestamp(), server_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        // everyone that was on during the period, sessions are only kept with `track_players`
+        let unique_players = conn.query_row(
+            "SELECT COUNT(DISTINCT name) FROM player_sessions
+            WHERE joined < ?2 AND (left IS NULL OR left >= ?1) AND (?3 IS NULL OR server_id = ?3)",
+            params![from.timestamp(), to.timestamp(), server_id],
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare(
+            "SELECT map, COUNT(*) AS samples FROM server_history
+            WHERE timestamp >= ?1 AND timestamp < ?2 AND map IS NOT NULL
+                AND (?3 IS NULL OR server_id = ?3)
+            GROUP BY map ORDER BY samples DESC LIMIT 3",
+        )?;
+        let top_maps = stmt
+            .query_map(
+                params![from.timestamp(), to.timestamp(), server_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+            .collect::<Result<_, _>>()?;
+        Ok(PeriodStats {
+            samples,
+            average_players,
+            peak_players,
+            unique_players,
+            top_maps,
+        })
+    }
+
+    /// Player counts of a server over a period as unix timestamps, oldest first
+    pub fn players(
+        &self,
+        server_id: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<(i64, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, players FROM server_history
+            WHERE server_id = ?1 AND timestamp >= ?2 AND timestamp < ?3
+            ORDER BY timestamp",
+        )?;
+        let players = stmt
+            .query_map(
+                params![server_id, from.timestamp(), to.timestamp()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+            .collect::<Result<_, _>>()?;
+        Ok(players)
+    }
+
+    /// Average player count of a server per day of the week and hour of the day (UTC) since a
+    /// time
+    pub fn peak_hours(&self, server_id: i64, since: DateTime<Utc>) -> Result<PeakHours> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT CAST(strftime('%w', timestamp, 'unixepoch') AS INTEGER),
+                CAST(strftime('%H', timestamp, 'unixepoch') AS INTEGER),
+                AVG(players)
+            FROM server_history
+            WHERE server_id = ?1 AND timestamp >= ?2
+            GROUP BY 1, 2",
+        )?;
+        let mut peak = PeakHours::default();
+        let rows = stmt.query_map(params![server_id, since.timestamp()], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, f64>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (weekday, hour, players) = row?;
+            let (Ok(weekday), Ok(hour)) = (usize::try_from(weekday), usize::try_from(hour)) else {
+                continue;
+            };
+            // sqlite starts the week on sunday
+            if let Some(cell) = peak
+                .averages
+                .get_mut((weekday + 6) % 7)
+                .and_then(|day| day.get_mut(hour))
+            {
+                *cell = Some(players);
+            }
+        }
+        Ok(peak)
+    }
+
+    /// How each map did in the rotation of a server over a period, most played first
+    pub fn rotation_stats(
+        &self,
+        server_id: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<MapRotation>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, players, map FROM server_history
+            WHERE server_id = ?1 AND timestamp >= ?2 AND timestamp < ?3 AND map IS NOT NULL
+            ORDER BY timestamp",
+        )?;
+        let samples: Vec<(i64, i64, String)> = stmt
+            .query_map(
+                params![server_id, from.timestamp(), to.timestamp()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?
+            .collect::<Result<_, _>>()?;
+
+        let mut stats: HashMap<String, MapRotation> = HashMap::new();
+        let mut finish = |round: &Round, changed: bool| {
+            let map = stats
+                .entry(round.map.clone())
+                .or_insert_with(|| MapRotation {
+                    map: round.map.clone(),
+                    ..Default::default()
+                });
+            map.rounds += 1;
+            map.minutes += (round.end - round.start) / 60;
+            if changed && round.end - round.start < SKIP_MINUTES * 60 {
+                map.skips += 1;
+            }
+            if let (Some(before), Some(after)) = (round.players_before, round.players_after) {
+                map.measured_changes += 1;
+                map.players_lost += before - after;
+            }
+            if let Some(before) = round.players_before {
+                if before >= EMPTIED_MIN_PLAYERS && round.lowest_after_start * 4 <= before * 3 {
+                    map.emptied += 1;
+                }
+            }
+        };
+        let mut current: Option<Round> = None;
+        for (timestamp, players, map) in samples {
+            if let Some(round) = current.as_mut() {
+                let continues = timestamp - round.end <= GAP_MINUTES * 60;
+                if continues && round.map == map {
+                    round.end = timestamp;
+                    if timestamp - round.start <= DROP_WINDOW_MINUTES * 60 {
+                        round.lowest_after_start = round.lowest_after_start.min(players);
+                    }
+                    if round.players_after.is_none()
+                        && timestamp - round.start >= POPULATION_AFTER_MINUTES * 60
+                    {
+                        round.players_after = Some(players);
+                    }
+                    round.last_players = players;
+                    continue;
+                }
+                finish(round, continues);
+                let players_before = continues.then_some(round.last_players);
+                current = Some(Round::new(map, timestamp, players, players_before));
+                continue;
+            }
+            current = Some(Round::new(map, timestamp, players, None));
+        }
+        if let Some(round) = current {
+            finish(&round, false);
+        }
+
+        let mut stats: Vec<MapRotation> = stats.into_values().collect();
+        stats.sort_by(|a, b| b.minutes.cmp(&a.minutes).then(a.map.cmp(&b.map)));
+        Ok(stats)
+    }
+}
+
+/// Rounds left within this many minutes after a map change count as skipped
+pub const SKIP_MINUTES: i64 = 5;
+/// A drop in population within this many minutes after a map change is blamed on the map
+pub const DROP_WINDOW_MINUTES: i64 = 5;
+/// The population this many minutes after a map change is compared to the one before it
+pub const POPULATION_AFTER_MINUTES: i64 = 10;
+/// Samples further apart than this are separate rounds, the bot or server was down in between
+const GAP_MINUTES: i64 = 15;
+/// Smaller populations are too noisy to tell if a map empties the server
+const EMPTIED_MIN_PLAYERS: i64 = 8;
+
+/// A stretch of samples on the same map of one server
+struct Round {
+    map: String,
+    start: i64,
+    end: i64,
+    /// players on the server just before the map change, `None` without a sample right before it
+    players_before: Option<i64>,
+    /// lowest population within `DROP_WINDOW_MINUTES` after the map started
+    lowest_after_start: i64,
+    /// players `POPULATION_AFTER_MINUTES` after the map started, `None` for shorter rounds
+    players_after: Option<i64>,
+    last_players: i64,
+}
+
+impl Round {
+    fn new(map: String, timestamp: i64, players: i64, players_before: Option<i64>) -> Self {
+        Self {
+            map,
+            start: timestamp,
+            end: timestamp,
+            players_before,
+            lowest_after_start: players,
+            players_after: None,
+            last_players: players,
+        }
+    }
+}
+
+/// How a map did in the rotation over a period
+#[derive(Debug, Clone, Default)]
+pub struct MapRotation {
+    /// internal map name
+    pub map: String,
+    pub rounds: i64,
+    pub minutes: i64,
+    /// rounds changed within `SKIP_MINUTES`
+    pub skips: i64,
+    /// rounds that lost a quarter of the players right after the map started
+    pub emptied: i64,
+    /// map changes with a population before and `POPULATION_AFTER_MINUTES` after the change
+    pub measured_changes: i64,
+    /// players lost over those map changes, negative if the map gained players
+    pub players_lost: i64,
+}
+
+impl MapRotation {
+    /// Players lost on average `POPULATION_AFTER_MINUTES` after changing to this map
+    pub fn average_players_lost(&self) -> Option<f64> {
+        match self.measured_changes {
+            0 => None,
+            changes => Some(self.players_lost as f64 / changes as f64),
+        }
+    }
+}
+
+/// Time a player spent on a server
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub name: String,
+    /// unix time
+    pub joined: i64,
+    /// unix time, `None` while the player is on
+    pub left: Option<i64>,
+}
+
+/// Percentage of the updates a server was in the server list, `None` without updates
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Uptime {
+    pub day: Option<f64>,
+    pub week: Option<f64>,
+    pub month: Option<f64>,
+}
+
+/// Average player count per day of the week (monday first) and hour of the day in UTC, `None`
+/// for hours without history
+#[derive(Debug, Clone, Default)]
+pub struct PeakHours {
+    pub averages: [[Option<f64>; 24]; 7],
+}
+
+impl PeakHours {
+    /// The fullest hour as day of the week (monday is 0), hour and average player count
+    pub fn busiest(&self) -> Option<(usize, usize, f64)> {
+        self.averages
+            .iter()
+            .enumerate()
+            .flat_map(|(day, hours)| {
+                hours
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(hour, players)| players.map(|players| (day, hour, players)))
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+    }
+}
+
+/// Population statistics over a period
+#[derive(Debug, Clone)]
+pub struct PeriodStats {
+    pub samples: i64,
+    pub average_players: f64,
+    pub peak_players: i64,
+    /// players with a session in the period, 0 without `track_players`
+    pub unique_players: i64,
+    /// internal map names with the amount of samples they were played in, most played first
+    pub top_maps: Vec<(String, i64)>,
+}
+
+/// Start and end of a month like `2024-05`
+pub fn month_range(month: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let start = NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d").ok()?;
+    let end = start.checked_add_months(Months::new(1))?;
+    Some((
+        start.and_hms_opt(0, 0, 0)?.and_utc(),
+        end.and_hms_opt(0, 0, 0)?.and_utc(),
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ImportTimestamp {
+    Unix(i64),
+    Text(String),
+}
+
+/// A row of an export from another tracking tool
+#[derive(Deserialize, Debug)]
+struct ImportRow {
+    #[serde(alias = "time", alias = "Time", alias = "date", alias = "Timestamp")]
+    timestamp: ImportTimestamp,
+    #[serde(
+        alias = "value",
+        alias = "Value",
+        alias = "Players",
+        alias = "player_count"
+    )]
+    players: f64,
+    #[serde(default, alias = "maxPlayers", alias = "max")]
+    max_players: Option<i64>,
+    #[serde(default, alias = "mapName", alias = "Map")]
+    map: Option<String>,
+    #[serde(default, alias = "gameMode", alias = "mode")]
+    game_mode: Option<String>,
+}
+
+fn parse_timestamp(timestamp: &ImportTimestamp) -> Result<DateTime<Utc>> {
+    let text = match timestamp {
+        ImportTimestamp::Unix(secs) => return unix_timestamp(*secs),
+        ImportTimestamp::Text(text) => text.trim(),
+    };
+    if let Ok(secs) = text.parse::<i64>() {
+        return unix_timestamp(secs);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    match NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S") {
+        Ok(time) => Ok(time.and_utc()),
+        Err(_) => anyhow::bail!("unknown timestamp format: {}", text),
+    }
+}
+
+fn unix_timestamp(secs: i64) -> Result<DateTime<Utc>> {
+    match DateTime::from_timestamp(secs, 0) {
+        Some(time) => Ok(time),
+        None => anyhow::bail!("timestamp out of range: {}", secs),
+    }
+}
+
+/// Imports a `battlemetrics-csv` or `json` export for the given server into the history
+pub fn import(
+    history: &History,
+    format: &str,
+    path: &str,
+    server_id: i64,
+    server_name: &str,
+) -> Result<usize> {
+    let rows: Vec<ImportRow> = match format {
+        "battlemetrics-csv" => csv::Reader::from_path(path)?
+            .deserialize()
+            .collect::<Result<_, _>>()?,
+        "json" => serde_json::from_reader(std::fs::File::open(path)?)?,
+        _ => anyhow::bail!(
+            "unknown import format {}, use battlemetrics-csv or json",
+            format
+        ),
+    };
+    let entries = rows
+        .iter()
+        .map(|row| {
+            Ok(Entry {
+                timestamp: parse_timestamp(&row.timestamp)?,
+                server_id: Some(server_id),
+                server_name: server_name.to_string(),
+                players: row.players.round() as i64,
+                max_players: row.max_players,
+                map: row.map.clone(),
+                game_mode: row.game_mode.clone(),
+                source: format.to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    history.insert_many(&entries)
+}
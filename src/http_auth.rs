@@ -0,0 +1,95 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Routes of the HTTP server that can be made private
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Route {
+    /// the health check, every path without a route of its own
+    Health,
+    Metrics,
+    /// `/api/` routes, next to their API keys
+    Api,
+    /// `/status.json`
+    Status,
+    /// `/events`
+    Events,
+    /// `/banner.png`
+    Banner,
+    /// `/badge.json`
+    Badge,
+    /// `/image/map/`
+    Image,
+    /// the status page on `/`
+    Page,
+}
+
+/// Credentials of the private routes of the HTTP server, every route is public without them
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HttpAuth {
+    /// (optional) accepted as `Authorization: Bearer <token>`
+    pub bearer_token: Option<String>,
+    /// (optional) accepted as basic auth, which browsers ask for
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// routes that need the token or the username and password
+    pub private: Vec<Route>,
+}
+
+impl ::std::default::Default for HttpAuth {
+    fn default() -> Self {
+        Self {
+            bearer_token: None,
+            username: None,
+            password: None,
+            private: vec![Route::Metrics],
+        }
+    }
+}
+
+/// Compares the hashes, so the time it takes doesn't tell how much of a secret was right
+fn same_secret(given: &str, secret: &str) -> bool {
+    Sha256::digest(given.as_bytes()) == Sha256::digest(secret.as_bytes())
+}
+
+impl HttpAuth {
+    /// `true` when a token or a username and password are set
+    pub fn enabled(&self) -> bool {
+        self.bearer_token.is_some() || (self.username.is_some() && self.password.is_some())
+    }
+
+    /// Whether a request with the `Authorization` header may use a route
+    pub fn allows(&self, route: Route, authorization: Option<&str>) -> bool {
+        if !self.enabled() || !self.private.contains(&route) {
+            return true;
+        }
+        let authorization = match authorization {
+            Some(authorization) => authorization.trim(),
+            None => return false,
+        };
+        if let (Some(token), Some(given)) =
+            (&self.bearer_token, authorization.strip_prefix("Bearer "))
+        {
+            if same_secret(given.trim(), token) {
+                return true;
+            }
+        }
+        if let (Some(username), Some(password), Some(given)) = (
+            &self.username,
+            &self.password,
+            authorization.strip_prefix("Basic "),
+        ) {
+            let given = STANDARD
+                .decode(given.trim())
+                .ok()
+                .and_then(|given| String::from_utf8(given).ok());
+            if given.is_some_and(|given| same_secret(&given, &format!("{}:{}", username, password)))
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{sync::OnceLock, time::Duration};
+
+/// Settings of the client used for marne.io, map images and the update check
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HttpClient {
+    /// time a whole request may take
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    /// time between two reads of a response, so a hung connection fails before `timeout_secs`
+    pub read_timeout_secs: u64,
+    /// idle connections kept open per host
+    pub pool_max_idle_per_host: usize,
+    /// proxy for all requests, like `http://proxy:8080`
+    pub proxy: Option<String>,
+    /// defaults to `marne-bot/<version>`
+    pub user_agent: Option<String>,
+}
+
+impl ::std::default::Default for HttpClient {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 15,
+            connect_timeout_secs: 5,
+            read_timeout_secs: 10,
+            pool_max_idle_per_host: 4,
+            proxy: None,
+            user_agent: None,
+        }
+    }
+}
+
+impl HttpClient {
+    fn build(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(self.timeout_secs))
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            .read_timeout(Duration::from_secs(self.read_timeout_secs))
+            .gzip(true)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .user_agent(
+                self.user_agent
+                    .clone()
+                    .unwrap_or(concat!("marne-bot/", env!("CARGO_PKG_VERSION")).into()),
+            );
+        if let Some(ref proxy) = self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Creates the shared client from the config, falls back to the defaults if it is invalid.
+/// Only the first call has an effect
+pub fn init(settings: &HttpClient) {
+    CLIENT.get_or_init(|| match settings.build() {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("Invalid http_client settings, using the defaults: {}", e);
+            default_client()
+        }
+    });
+}
+
+fn default_client() -> reqwest::Client {
+    HttpClient::default()
+        .build()
+        .expect("default http client is invalid")
+}
+
+/// The shared client, its connections are reused between requests
+pub fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(default_client)
+}
@@ -0,0 +1,952 @@
+use crate::http_client;
+use ab_glyph::{Font, FontRef, PxScale};
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use image::{
+    io::Reader as ImageReader, DynamicImage, GenericImageView, ImageFormat, Rgb, RgbImage, Rgba,
+};
+use imageproc::{
+    drawing::{draw_filled_rect_mut, draw_line_segment_mut, draw_text_mut, text_size},
+    rect::Rect,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+/// Where generated avatars and banners are kept between restarts
+const AVATAR_CACHE_DIR: &str = "./cache/avatars";
+
+type Avatars = BTreeMap<String, (Vec<u8>, Vec<u8>)>;
+
+/// Generated avatar and banner JPEGs per map image (or name) and mode
+static AVATARS: Mutex<Avatars> = Mutex::new(BTreeMap::new());
+
+/// Replaces the characters that aren't safe in a file name
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect()
+}
+
+/// Where downloaded map images are kept for when the CDN is down
+fn map_cache_path(map_image: &str) -> PathBuf {
+    Path::new("./cache/maps").join(file_name(map_image.rsplit('/').next().unwrap_or_default()))
+}
+
+/// Runs CPU-bound image work off the async threads, so the gateway heartbeat and the web server
+/// keep running while a big map image is decoded or drawn
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Result<T> {
+    Ok(tokio::task::spawn_blocking(work).await?)
+}
+
+async fn download_map_image(map_image: &str) -> Result<DynamicImage> {
+    let img = http_client::client()
+        .get(map_image)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let cache_path = map_cache_path(map_image);
+    blocking(move || -> Result<DynamicImage> {
+        let decoded = ImageReader::new(Cursor::new(&img))
+            .with_guessed_format()?
+            .decode()?;
+        if let Err(e) =
+            std::fs::create_dir_all("./cache/maps").and_then(|_| std::fs::write(&cache_path, &img))
+        {
+            log::warn!("Failed to cache map image {}: {}", cache_path.display(), e);
+        }
+        Ok(decoded)
+    })
+    .await?
+}
+
+/// A map image downloaded earlier
+async fn open_cached_map_image(map_image: &str) -> Result<DynamicImage> {
+    let cache_path = map_cache_path(map_image);
+    Ok(blocking(move || image::open(cache_path)).await??)
+}
+
+/// Largest width or height `/image/map/` scales to
+const MAX_SERVED_SIZE: u32 = 1920;
+/// Scaled map images kept in memory, the cache is emptied when it is full
+const MAX_SERVED_CACHED: usize = 64;
+
+/// Scaled map images served on `/image/map/`, keyed by url, width and height
+static SERVED: Mutex<BTreeMap<(String, u32, u32), Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+/// A map image as JPEG for `/image/map/`, from the cache folder or else the CDN. It is scaled
+/// down to fit in `width` and `height` keeping its aspect ratio
+pub async fn served_map_image(
+    map_image: &str,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<Vec<u8>> {
+    let width = width.unwrap_or(MAX_SERVED_SIZE).clamp(1, MAX_SERVED_SIZE);
+    let height = height.unwrap_or(MAX_SERVED_SIZE).clamp(1, MAX_SERVED_SIZE);
+    let key = (map_image.to_string(), width, height);
+    if let Some(bytes) = SERVED.lock().unwrap().get(&key) {
+        return Ok(bytes.clone());
+    }
+    let img = match open_cached_map_image(map_image).await {
+        Ok(img) => img,
+        Err(_) => download_map_image(map_image).await?,
+    };
+    let bytes = blocking(move || {
+        let img = match img.width() > width || img.height() > height {
+            true => img.resize(width, height, image::imageops::FilterType::Triangle),
+            false => img,
+        };
+        jpeg(&img)
+    })
+    .await??;
+    let mut served = SERVED.lock().unwrap();
+    if served.len() >= MAX_SERVED_CACHED {
+        served.clear();
+    }
+    served.insert(key, bytes.clone());
+    Ok(bytes)
+}
+
+/// Size of `/banner.png`, the size of a wide forum signature
+const BANNER_WIDTH: u32 = 960;
+const BANNER_HEIGHT: u32 = 180;
+
+/// Darkened map image behind the banner of the last map, drawn again when the map changes
+static BANNER_BACKGROUND: Mutex<Option<(String, RgbImage)>> = Mutex::new(None);
+
+/// The map image scaled and cropped to the banner size and darkened so the text stays readable.
+/// A plain background without a map image
+async fn banner_background(map_image: Option<&str>) -> Result<RgbImage> {
+    let map_image = match map_image {
+        Some(map_image) => map_image,
+        None => {
+            return Ok(RgbImage::from_pixel(
+                BANNER_WIDTH,
+                BANNER_HEIGHT,
+                Rgb([30u8, 34u8, 40u8]),
+            ))
+        }
+    };
+    if let Some((url, background)) = BANNER_BACKGROUND.lock().unwrap().as_ref() {
+        if url == map_image {
+            return Ok(background.clone());
+        }
+    }
+    let img = match open_cached_map_image(map_image).await {
+        Ok(img) => img,
+        Err(_) => load_map_image(map_image).await.0,
+    };
+    let background = blocking(move || {
+        img.resize_to_fill(
+            BANNER_WIDTH,
+            BANNER_HEIGHT,
+            image::imageops::FilterType::Triangle,
+        )
+        .brighten(-70)
+        .to_rgb8()
+    })
+    .await?;
+    *BANNER_BACKGROUND.lock().unwrap() = Some((map_image.to_string(), background.clone()));
+    Ok(background)
+}
+
+/// Wide banner of a server as PNG for websites and forum signatures: the map image with the
+/// server name and below it `details`, like the player count and mode
+pub async fn server_banner(map_image: Option<&str>, name: &str, details: &str) -> Result<Vec<u8>> {
+    let mut img = banner_background(map_image).await?;
+    let (name, details) = (name.to_string(), details.to_string());
+    blocking(move || -> Result<Vec<u8>> {
+        // the banner is left without text without the font
+        let max_width = BANNER_WIDTH as f32 - 48.0;
+        for (text, height, y, color) in [
+            (&name, 56.0, 28, Rgb([255u8, 255u8, 255u8])),
+            (&details, 36.0, 108, Rgb([210u8, 210u8, 210u8])),
+        ] {
+            if let Some(font) = font_for(text) {
+                let mut scale = PxScale::from(height);
+                let (width, _) = text_size(scale, font, text);
+                if width as f32 > max_width {
+                    scale = PxScale::from(scale.y * max_width / width as f32);
+                }
+                draw_text_mut(&mut img, color, 24, y, scale, font, text);
+            }
+        }
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img).write_to(&mut png, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    })
+    .await?
+}
+
+/// The map image from the CDN, falls back to an earlier download and then to a plain background.
+/// `false` with the plain background
+async fn load_map_image(map_image: &str) -> (DynamicImage, bool) {
+    match download_map_image(map_image).await {
+        Ok(img) => return (img, true),
+        Err(e) => log::warn!("Failed to download map image {}: {}", map_image, e),
+    }
+    match open_cached_map_image(map_image).await {
+        Ok(img) => return (img, true),
+        Err(e) => log::warn!("No cached map image for {}: {}", map_image, e),
+    }
+    (background(), false)
+}
+
+fn background() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(1280, 720, Rgb([30u8, 34u8, 40u8])))
+}
+
+/// Characters of the text drawn on the avatar: the short modes and the player count
+const OVERLAY_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789/";
+
+/// The fonts of the images in order of preference, the built-in font last
+static FONTS: OnceLock<Vec<FontRef<'static>>> = OnceLock::new();
+
+/// The built-in font, `None` if it is broken
+fn builtin_font() -> Option<FontRef<'static>> {
+    match FontRef::try_from_slice(include_bytes!("Futura.ttf") as &[u8]) {
+        Ok(font) => Some(font),
+        Err(e) => {
+            log::error!("Failed to load the built-in font: {}", e);
+            None
+        }
+    }
+}
+
+/// The characters of a text the font has no glyph for
+fn missing_glyphs(font: &FontRef, text: &str) -> String {
+    let mut missing: Vec<char> = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && font.glyph_id(*c).0 == 0)
+        .collect();
+    missing.sort_unstable();
+    missing.dedup();
+    missing.into_iter().collect()
+}
+
+/// Reads a font file, it is kept for as long as the bot runs
+fn load_font(path: &str) -> Result<FontRef<'static>> {
+    let bytes = std::fs::read(path).with_context(|| format!("can't read {}", path))?;
+    let bytes: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+    let font = FontRef::try_from_slice(bytes)
+        .map_err(|e| anyhow::anyhow!("{} isn't a TrueType or OpenType font: {}", path, e))?;
+    let missing = missing_glyphs(&font, OVERLAY_CHARS);
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "{} has no glyphs for `{}`, which are drawn on the avatar",
+            path,
+            missing
+        );
+    }
+    Ok(font)
+}
+
+/// Loads the font files of `fonts` in front of the built-in font, on startup before any image is
+/// drawn. A font that can't be used is logged and left out
+pub fn load_fonts(paths: &[String]) {
+    let mut fonts = vec![];
+    for path in paths {
+        match load_font(path) {
+            Ok(font) => {
+                log::info!("Using the font {}", path);
+                fonts.push(font);
+            }
+            Err(e) => log::error!("Failed to load a font, falling back to the next one: {}", e),
+        }
+    }
+    fonts.extend(builtin_font());
+    if FONTS.set(fonts).is_err() {
+        log::warn!("The fonts were already loaded, restart to use other fonts");
+    }
+}
+
+/// The first font with glyphs for every character of the text, else the first font. `None`
+/// without a usable font, the images are drawn without text then
+fn font_for(text: &str) -> Option<&'static FontRef<'static>> {
+    let fonts = FONTS.get_or_init(|| builtin_font().into_iter().collect());
+    fonts
+        .iter()
+        .find(|font| missing_glyphs(font, text).is_empty())
+        .or(fonts.first())
+}
+
+/// The preferred font, for the text of the charts
+fn font() -> Option<&'static FontRef<'static>> {
+    font_for("")
+}
+
+/// Plain background with the map name at the bottom, for custom maps without an image
+fn placeholder_image(map_name: &str) -> DynamicImage {
+    let mut img = background();
+    let font = match font_for(map_name) {
+        Some(font) => font,
+        None => return img,
+    };
+    let mut scale = PxScale::from(img.height() as f32 / 8.0);
+    let max_width = img.width() as f32 * 0.9;
+    let (width, _) = text_size(scale, font, map_name);
+    if width as f32 > max_width {
+        scale = PxScale::from(scale.y * max_width / width as f32);
+    }
+    let (width, height) = text_size(scale, font, map_name);
+    let (w, h) = img.dimensions();
+    draw_text_mut(
+        &mut img,
+        Rgba([255u8, 255u8, 255u8, 255u8]),
+        (w.saturating_sub(width) / 2) as i32,
+        h.saturating_sub(height * 2) as i32,
+        scale,
+        font,
+        map_name,
+    );
+    img
+}
+
+/// Where the player count is drawn on the avatar. Discord shows the middle of the image in a
+/// circle, the positions are inside of it
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    TopLeft,
+    Top,
+    TopRight,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+    /// the middle of the image, where earlier versions drew the mode
+    Center,
+}
+
+impl Position {
+    /// Left and top of a text of `width` and `height` inside `area` (left, top, right, bottom)
+    fn place(self, area: (u32, u32, u32, u32), width: u32, height: u32) -> (u32, u32) {
+        let (left, top, right, bottom) = area;
+        let x = match self {
+            Position::TopLeft | Position::BottomLeft => left,
+            Position::Top | Position::Bottom | Position::Center => {
+                (left + right).saturating_sub(width) / 2
+            }
+            Position::TopRight | Position::BottomRight => right.saturating_sub(width),
+        };
+        let y = match self {
+            Position::TopLeft | Position::Top | Position::TopRight => top,
+            Position::Center => (top + bottom).saturating_sub(height) / 2,
+            _ => bottom.saturating_sub(height),
+        };
+        (x, y)
+    }
+}
+
+/// The square in the middle of the avatar that Discord shows, with a margin for the round
+/// corners
+fn avatar_area(img: &DynamicImage) -> (u32, u32, u32, u32) {
+    let side = img.width().min(img.height());
+    (
+        (img.width() - side) / 2 + side / 7,
+        (img.height() - side) / 2 + side / 10,
+        (img.width() + side) / 2 - side / 7,
+        (img.height() + side) / 2 - side / 10,
+    )
+}
+
+/// Width of the letters of the mode relative to their height
+const MODE_LETTER_WIDTH: f32 = 0.57;
+
+/// Built-in looks of the mode on the avatar
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemePreset {
+    /// the big white mode of earlier versions
+    Classic,
+    /// darkened map with an outlined white mode
+    Dark,
+    /// brightened map with a dark mode
+    Light,
+}
+
+/// Look of the short mode drawn by the `mode` step. The preset is the base, the other settings
+/// override it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AvatarTheme {
+    pub preset: ThemePreset,
+    /// brightens the map before the mode is drawn, or darkens with a negative value
+    pub brightness: Option<i32>,
+    /// hex color like `#ffffff`
+    pub text_color: Option<String>,
+    /// hex color of an outline around the mode, `none` for no outline
+    pub stroke_color: Option<String>,
+    /// dark shadow below the mode
+    pub shadow: Option<bool>,
+    pub position: Option<Position>,
+    /// height of the mode in percent of the image height
+    pub scale: Option<u32>,
+}
+
+impl ::std::default::Default for AvatarTheme {
+    fn default() -> Self {
+        Self {
+            preset: ThemePreset::Classic,
+            brightness: None,
+            text_color: None,
+            stroke_color: None,
+            shadow: None,
+            position: None,
+            scale: None,
+        }
+    }
+}
+
+/// A theme with the preset and the overrides combined
+struct Theme {
+    brightness: i32,
+    text: TextStyle,
+    position: Position,
+    scale: u32,
+}
+
+impl AvatarTheme {
+    fn resolve(&self) -> Theme {
+        let white = Rgba([255u8, 255u8, 255u8, 255u8]);
+        let preset = match self.preset {
+            // the shadow keeps the white mode readable on bright maps like Sinai
+            ThemePreset::Classic => Theme {
+                brightness: 0,
+                text: TextStyle {
+                    color: white,
+                    stroke: None,
+                    shadow: true,
+                },
+                position: Position::Center,
+                scale: 59,
+            },
+            ThemePreset::Dark => Theme {
+                brightness: -40,
+                text: TextStyle {
+                    color: white,
+                    stroke: Some(Rgba([0u8, 0u8, 0u8, 255u8])),
+                    shadow: false,
+                },
+                position: Position::Center,
+                scale: 50,
+            },
+            ThemePreset::Light => Theme {
+                brightness: 25,
+                text: TextStyle {
+                    color: Rgba([30u8, 34u8, 40u8, 255u8]),
+                    stroke: Some(white),
+                    shadow: false,
+                },
+                position: Position::Center,
+                scale: 50,
+            },
+        };
+        let color = |hex: &Option<String>, fallback: Rgba<u8>| match hex {
+            Some(hex) => parse_color(hex).unwrap_or_else(|| {
+                log::warn!("Invalid avatar_theme color {}, using the preset", hex);
+                fallback
+            }),
+            None => fallback,
+        };
+        Theme {
+            brightness: self.brightness.unwrap_or(preset.brightness),
+            text: TextStyle {
+                color: color(&self.text_color, preset.text.color),
+                stroke: match self.stroke_color.as_deref() {
+                    Some(hex) => stroke_color(hex, "avatar_theme stroke_color"),
+                    None => preset.text.stroke,
+                },
+                shadow: self.shadow.unwrap_or(preset.text.shadow),
+            },
+            position: self.position.unwrap_or(preset.position),
+            scale: self.scale.unwrap_or(preset.scale).clamp(5, 90),
+        }
+    }
+}
+
+/// Color of a text with what keeps it readable on bright and busy maps
+struct TextStyle {
+    color: Rgba<u8>,
+    /// outline around the letters
+    stroke: Option<Rgba<u8>>,
+    /// dark copy of the text below and right of it
+    shadow: bool,
+}
+
+/// Parses an outline color setting, `none` for no outline
+fn stroke_color(hex: &str, setting: &str) -> Option<Rgba<u8>> {
+    match hex {
+        "none" => None,
+        hex => parse_color(hex).or_else(|| {
+            log::warn!("Invalid {} {}, drawing no outline", setting, hex);
+            None
+        }),
+    }
+}
+
+/// Draws a text in a style. The outline is a ring of copies of the text moved by a few pixels in
+/// the stroke color, the shadow a black copy further down and right
+fn draw_styled_text(
+    img: &mut DynamicImage,
+    style: &TextStyle,
+    (x, y): (i32, i32),
+    scale: PxScale,
+    font: &FontRef,
+    text: &str,
+) {
+    let width = ((scale.y / 30.0).round() as i32).max(1);
+    if style.shadow {
+        let offset = width * 2;
+        let shadow = Rgba([0u8, 0u8, 0u8, 255u8]);
+        draw_text_mut(img, shadow, x + offset, y + offset, scale, font, text);
+    }
+    if let Some(stroke) = style.stroke {
+        for dx in -width..=width {
+            for dy in -width..=width {
+                if (dx, dy) != (0, 0) && dx * dx + dy * dy <= width * width {
+                    draw_text_mut(img, stroke, x + dx, y + dy, scale, font, text);
+                }
+            }
+        }
+    }
+    draw_text_mut(img, style.color, x, y, scale, font, text);
+}
+
+/// Current and max players drawn on the avatar, like `34/64`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AvatarPlayers {
+    pub enabled: bool,
+    pub position: Position,
+    /// hex color like `#ffffff`
+    pub color: String,
+    /// hex color of an outline around the text, `none` for no outline
+    pub stroke_color: String,
+    /// dark shadow below the text
+    pub shadow: bool,
+    /// height of the text in percent of the image height
+    pub font_size: u32,
+}
+
+impl ::std::default::Default for AvatarPlayers {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: Position::Bottom,
+            color: "#ffffff".into(),
+            stroke_color: "#000000".into(),
+            shadow: false,
+            font_size: 22,
+        }
+    }
+}
+
+impl AvatarPlayers {
+    fn color(&self) -> Rgba<u8> {
+        parse_color(&self.color).unwrap_or_else(|| {
+            log::warn!("Invalid avatar_players color {}, using white", self.color);
+            Rgba([255u8, 255u8, 255u8, 255u8])
+        })
+    }
+
+    /// Draws `text` on the avatar JPEG
+    fn draw(&self, avatar: &[u8], text: &str) -> Result<Vec<u8>> {
+        let font = match font_for(text) {
+            Some(font) => font,
+            None => return Ok(avatar.to_vec()),
+        };
+        let mut img = image::load_from_memory(avatar)?;
+        let scale = PxScale::from(img.height() as f32 * self.font_size.clamp(5, 50) as f32 / 100.0);
+        let (width, height) = text_size(scale, font, text);
+        let (x, y) = self.position.place(avatar_area(&img), width, height);
+        let style = TextStyle {
+            color: self.color(),
+            stroke: stroke_color(&self.stroke_color, "avatar_players stroke_color"),
+            shadow: self.shadow,
+        };
+        draw_styled_text(&mut img, &style, (x as i32, y as i32), scale, font, text);
+        jpeg(&img)
+    }
+}
+
+fn jpeg(img: &DynamicImage) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    DynamicImage::ImageRgb8(img.to_rgb8())
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg)?;
+    Ok(bytes)
+}
+
+/// Generates the avatar and banner JPEGs, maps without an image
+/// get a placeholder with their name. Both are cached per map and mode, in memory and in
+/// `cache/avatars`, so a map that comes around again isn't downloaded and drawn again.
+/// The `steps` draw the avatar from the map image with the mode in the look of `theme`,
+/// `players` is drawn over the cached avatar when the overlay is enabled
+pub async fn gen_img(
+    small_mode: &str,
+    map_image: Option<&str>,
+    map_name: &str,
+    steps: &[Step],
+    theme: &AvatarTheme,
+    players: Option<(&AvatarPlayers, &str)>,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let key = file_name(&format!(
+        "{}_{}_{}",
+        map_image.unwrap_or(map_name),
+        small_mode,
+        steps_key(steps, theme)
+    ));
+    let avatar_path = Path::new(AVATAR_CACHE_DIR).join(format!("{}_avatar.jpg", key));
+    let banner_path = Path::new(AVATAR_CACHE_DIR).join(format!("{}_banner.jpg", key));
+    let cached = AVATARS.lock().unwrap().get(&key).cloned();
+    let cached = cached.or_else(|| {
+        Some((
+            std::fs::read(&avatar_path).ok()?,
+            std::fs::read(&banner_path).ok()?,
+        ))
+    });
+    let (avatar, banner) = match cached {
+        Some(images) => images,
+        None => {
+            let (avatar, banner, complete) =
+                render(small_mode, map_image, map_name, steps, theme).await?;
+            // a missing map image is tried again next time
+            if complete {
+                if let Err(e) = std::fs::create_dir_all(AVATAR_CACHE_DIR)
+                    .and_then(|_| std::fs::write(&avatar_path, &avatar))
+                    .and_then(|_| std::fs::write(&banner_path, &banner))
+                {
+                    log::warn!("Failed to cache the avatar of {}: {}", map_name, e);
+                }
+                AVATARS
+                    .lock()
+                    .unwrap()
+                    .insert(key, (avatar.clone(), banner.clone()));
+            }
+            (avatar, banner)
+        }
+    };
+    let avatar = match players {
+        Some((overlay, text)) if overlay.enabled => {
+            let (overlay, text) = (overlay.clone(), text.to_string());
+            blocking(move || overlay.draw(&avatar, &text)).await??
+        }
+        _ => avatar,
+    };
+    Ok((avatar, banner))
+}
+
+/// Avatar and banner JPEGs, `false` if the map image couldn't be loaded
+async fn render(
+    small_mode: &str,
+    map_image: Option<&str>,
+    map_name: &str,
+    steps: &[Step],
+    theme: &AvatarTheme,
+) -> Result<(Vec<u8>, Vec<u8>, bool)> {
+    let (img, complete) = match map_image {
+        Some(map_image) => load_map_image(map_image).await,
+        None => {
+            let map_name = map_name.to_string();
+            (blocking(move || placeholder_image(&map_name)).await?, true)
+        }
+    };
+    let (small_mode, steps, theme) = (small_mode.to_string(), steps.to_vec(), theme.resolve());
+    let (avatar, banner) = blocking(move || -> Result<(Vec<u8>, Vec<u8>)> {
+        let banner = jpeg(&img)?;
+        let img = steps
+            .iter()
+            .fold(img, |img, step| step.apply(img, &small_mode, &theme));
+        Ok((jpeg(&img)?, banner))
+    })
+    .await??;
+    Ok((avatar, banner, complete))
+}
+
+/// A step between loading the map image and encoding the avatar, applied in the configured order
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    /// brightens, or darkens with a negative value
+    Brighten { value: i32 },
+    /// scales the image to fit in the size, keeping its aspect ratio
+    Resize { width: u32, height: u32 },
+    /// mixes a hex color like `#203040` into the image
+    Tint { color: String, percent: u32 },
+    /// draws the short mode, like `CQ`, in the look of `avatar_theme`
+    Mode,
+}
+
+/// The avatar of earlier versions: the map with the short mode
+pub fn default_steps() -> Vec<Step> {
+    vec![Step::Mode]
+}
+
+impl Step {
+    fn apply(&self, mut img: DynamicImage, small_mode: &str, theme: &Theme) -> DynamicImage {
+        match self {
+            Step::Brighten { value } => img.brighten(*value),
+            Step::Resize { width, height } => img.resize(
+                (*width).max(1),
+                (*height).max(1),
+                image::imageops::FilterType::Triangle,
+            ),
+            Step::Tint { color, percent } => {
+                let color = parse_color(color).unwrap_or_else(|| {
+                    log::warn!("Invalid tint color {}, using black", color);
+                    Rgba([0u8, 0u8, 0u8, 255u8])
+                });
+                let percent = (*percent).min(100);
+                let mut rgb = img.to_rgb8();
+                for pixel in rgb.pixels_mut() {
+                    for (value, tint) in pixel.0.iter_mut().zip(color.0) {
+                        *value =
+                            ((*value as u32 * (100 - percent) + tint as u32 * percent) / 100) as u8;
+                    }
+                }
+                DynamicImage::ImageRgb8(rgb)
+            }
+            Step::Mode => {
+                if theme.brightness != 0 {
+                    img = img.brighten(theme.brightness);
+                }
+                // unknown modes have no short mode, leave the map without an overlay
+                if let (false, Some(font)) = (small_mode.is_empty(), font_for(small_mode)) {
+                    let height = img.height() as f32 * theme.scale as f32 / 100.0;
+                    // the narrow letters of earlier versions
+                    let scale = PxScale {
+                        x: height * MODE_LETTER_WIDTH,
+                        y: height,
+                    };
+                    let (width, text_height) = text_size(scale, font, small_mode);
+                    let (x, y) = theme.position.place(avatar_area(&img), width, text_height);
+                    draw_styled_text(
+                        &mut img,
+                        &theme.text,
+                        (x as i32, y as i32),
+                        scale,
+                        font,
+                        small_mode,
+                    );
+                }
+                img
+            }
+        }
+    }
+}
+
+/// Short name of the steps and theme for the cache key, so other steps draw a new avatar
+fn steps_key(steps: &[Step], theme: &AvatarTheme) -> String {
+    let digest = Sha256::digest(format!("{:?}{:?}", steps, theme).as_bytes());
+    digest[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn parse_color(hex: &str) -> Option<Rgba<u8>> {
+    let hex = hex.trim_start_matches('#');
+    match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            Some(Rgba([r, g, b, 255]))
+        }
+        _ => None,
+    }
+}
+
+/// Player count chart of a period as PNG. The samples are averaged into `buckets` points, periods
+/// without samples are left as a gap. `time_format` is the chrono format of the time axis labels
+pub async fn history_chart(
+    samples: &[(i64, i64)],
+    from: i64,
+    to: i64,
+    max_players: i64,
+    buckets: usize,
+    time_format: &str,
+) -> Result<Vec<u8>> {
+    let (samples, time_format) = (samples.to_vec(), time_format.to_string());
+    blocking(move || -> Result<Vec<u8>> {
+        const WIDTH: u32 = 1000;
+        const HEIGHT: u32 = 400;
+        // space for the axis labels
+        const LEFT: f32 = 60.0;
+        const BOTTOM: f32 = 40.0;
+        const TOP: f32 = 20.0;
+        const RIGHT: f32 = 20.0;
+
+        let buckets = buckets.max(1);
+        let mut sums = vec![(0i64, 0i64); buckets];
+        let bucket_secs = ((to - from) / buckets as i64).max(1);
+        for (timestamp, players) in samples {
+            let bucket = ((timestamp - from) / bucket_secs) as usize;
+            if let Some((sum, count)) = sums.get_mut(bucket) {
+                *sum += players;
+                *count += 1;
+            }
+        }
+
+        let mut img = RgbImage::from_pixel(WIDTH, HEIGHT, Rgb([30u8, 34u8, 40u8]));
+        // the axis labels are left out without the font
+        let font = font();
+        let scale = PxScale::from(16.0);
+        let grid = Rgb([70u8, 74u8, 80u8]);
+        let text = Rgb([200u8, 200u8, 200u8]);
+        let plot_width = WIDTH as f32 - LEFT - RIGHT;
+        let plot_height = HEIGHT as f32 - TOP - BOTTOM;
+        let max_players = max_players.max(1) as f32;
+        let y = |players: f32| TOP + plot_height * (1.0 - players / max_players);
+        let x = |bucket: f32| LEFT + plot_width * bucket / buckets as f32;
+
+        for step in 0..=4 {
+            let players = max_players * step as f32 / 4.0;
+            draw_line_segment_mut(
+                &mut img,
+                (LEFT, y(players)),
+                (WIDTH as f32 - RIGHT, y(players)),
+                grid,
+            );
+            if let Some(font) = font {
+                draw_text_mut(
+                    &mut img,
+                    text,
+                    10,
+                    y(players) as i32 - 8,
+                    scale,
+                    font,
+                    &format!("{:.0}", players),
+                );
+            }
+        }
+        for step in 0..=4 {
+            let font = match font {
+                Some(font) => font,
+                None => break,
+            };
+            let timestamp = from + (to - from) * step / 4;
+            let label = DateTime::from_timestamp(timestamp, 0)
+                .map(|time| time.format(&time_format).to_string())
+                .unwrap_or_default();
+            let (label_width, _) = text_size(scale, font, &label);
+            let position = x(buckets as f32 * step as f32 / 4.0) - label_width as f32 / 2.0;
+            draw_text_mut(
+                &mut img,
+                text,
+                position.clamp(0.0, WIDTH.saturating_sub(label_width) as f32) as i32,
+                (HEIGHT as f32 - BOTTOM + 12.0) as i32,
+                scale,
+                font,
+                &label,
+            );
+        }
+
+        let line = Rgb([88u8, 166u8, 255u8]);
+        let mut previous: Option<(f32, f32)> = None;
+        for (bucket, (sum, count)) in sums.iter().enumerate() {
+            if *count == 0 {
+                previous = None;
+                continue;
+            }
+            let point = (x(bucket as f32 + 0.5), y(*sum as f32 / *count as f32));
+            match previous {
+                Some(previous) => draw_line_segment_mut(&mut img, previous, point, line),
+                None => draw_filled_rect_mut(
+                    &mut img,
+                    Rect::at(point.0 as i32 - 1, point.1 as i32 - 1).of_size(3, 3),
+                    line,
+                ),
+            }
+            previous = Some(point);
+        }
+
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img).write_to(&mut png, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    })
+    .await?
+}
+
+/// Heatmap of the average player count per day of the week (rows, monday first) and hour of the
+/// day (columns) as PNG, hours without history are left dark
+pub async fn peak_heatmap(averages: &[[Option<f64>; 24]; 7], max_players: i64) -> Result<Vec<u8>> {
+    let averages = *averages;
+    blocking(move || -> Result<Vec<u8>> {
+        const CELL: u32 = 36;
+        // space for the axis labels
+        const LEFT: u32 = 60;
+        const TOP: u32 = 30;
+        const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+        let width = LEFT + CELL * 24 + 10;
+        let height = TOP + CELL * 7 + 10;
+        let mut img = RgbImage::from_pixel(width, height, Rgb([30u8, 34u8, 40u8]));
+        // the axis labels are left out without the font
+        let font = font();
+        let scale = PxScale::from(16.0);
+        let text = Rgb([200u8, 200u8, 200u8]);
+        let max_players = max_players.max(1) as f32;
+
+        if let Some(font) = font {
+            for hour in (0..24).step_by(3) {
+                draw_text_mut(
+                    &mut img,
+                    text,
+                    (LEFT + CELL * hour) as i32 + 4,
+                    6,
+                    scale,
+                    font,
+                    &format!("{:02}", hour),
+                );
+            }
+            for (day, name) in DAYS.iter().enumerate() {
+                draw_text_mut(
+                    &mut img,
+                    text,
+                    10,
+                    (TOP + CELL * day as u32 + CELL / 2) as i32 - 8,
+                    scale,
+                    font,
+                    name,
+                );
+            }
+        }
+        for (day, hours) in averages.iter().enumerate() {
+            for (hour, players) in hours.iter().enumerate() {
+                // from the background color to blue for a full server
+                let color = match players {
+                    Some(players) => {
+                        let fill = (*players as f32 / max_players).clamp(0.0, 1.0);
+                        let mix = |empty: f32, full: f32| (empty + (full - empty) * fill) as u8;
+                        Rgb([mix(50.0, 88.0), mix(56.0, 166.0), mix(64.0, 255.0)])
+                    }
+                    None => Rgb([36u8, 40u8, 46u8]),
+                };
+                draw_filled_rect_mut(
+                    &mut img,
+                    Rect::at(
+                        (LEFT + CELL * hour as u32) as i32 + 1,
+                        (TOP + CELL * day as u32) as i32 + 1,
+                    )
+                    .of_size(CELL - 2, CELL - 2),
+                    color,
+                );
+            }
+        }
+
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img).write_to(&mut png, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    })
+    .await?
+}
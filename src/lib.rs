@@ -0,0 +1,37 @@
+pub mod anomalies;
+pub mod apikeys;
+pub mod archive;
+pub mod batcher;
+pub mod budget;
+pub mod channel_counter;
+pub mod config;
+pub mod digest;
+pub mod discord;
+pub mod error_reporting;
+pub mod events;
+pub mod fill_rate;
+pub mod health;
+pub mod history;
+pub mod http_auth;
+pub mod http_client;
+pub mod images;
+pub mod links;
+pub mod locales;
+pub mod log_channel;
+pub mod logging;
+pub mod maps;
+pub mod marne;
+#[cfg(feature = "mock-marne")]
+pub mod mock_marne;
+pub mod nickname;
+pub mod server_browser;
+pub mod service;
+pub mod sessions;
+pub mod status_embed;
+pub mod status_page;
+pub mod summary;
+pub mod update;
+pub mod version;
+pub mod watchlist;
+pub mod webhooks;
+pub mod whenplay;
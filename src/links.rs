@@ -0,0 +1,67 @@
+use crate::config;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+/// A Discord member and their in-game name, set with `/link`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Link {
+    pub user_id: u64,
+    pub player: String,
+}
+
+/// The linked members, kept in config.txt so they survive a restart
+pub struct Links {
+    links: RwLock<Vec<Link>>,
+}
+
+impl Links {
+    pub fn new(links: Vec<Link>) -> Self {
+        Self {
+            links: RwLock::new(links),
+        }
+    }
+
+    /// Links a member to a player name, replaces their previous link. Fails when another member
+    /// already linked the name
+    pub fn set(&self, user_id: u64, player: &str) -> Result<()> {
+        let mut links = self.links.write().unwrap();
+        if links
+            .iter()
+            .any(|link| link.user_id != user_id && link.player.eq_ignore_ascii_case(player))
+        {
+            anyhow::bail!("`{}` is already linked to another member", player);
+        }
+        links.retain(|link| link.user_id != user_id);
+        links.push(Link {
+            user_id,
+            player: player.to_string(),
+        });
+        store(&links)
+    }
+
+    /// Removes the link of a member, returns if there was one
+    pub fn remove(&self, user_id: u64) -> Result<bool> {
+        let mut links = self.links.write().unwrap();
+        let before = links.len();
+        links.retain(|link| link.user_id != user_id);
+        if links.len() == before {
+            return Ok(false);
+        }
+        store(&links)?;
+        Ok(true)
+    }
+
+    /// The member linked to a player name, names are matched ignoring case
+    pub fn member(&self, player: &str) -> Option<u64> {
+        let links = self.links.read().unwrap();
+        links
+            .iter()
+            .find(|link| link.player.eq_ignore_ascii_case(player))
+            .map(|link| link.user_id)
+    }
+}
+
+fn store(links: &[Link]) -> Result<()> {
+    config::update(|cfg| cfg.links = links.to_vec())
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, sync::OnceLock};
+
+/// Translations of the slash commands shipped with the bot
+const BUNDLED: &str = include_str!("locales.json");
+/// Optional file next to config.txt, its entries are merged over the bundled ones
+pub const OVERRIDE_PATH: &str = "locales.json";
+
+/// Translated name and description of a command or option, unset ones stay English
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Translation {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Translations per Discord locale (`de`, `fr`) and command or option path (`players.server`)
+type Locales = HashMap<String, HashMap<String, Translation>>;
+
+static LOCALES: OnceLock<Locales> = OnceLock::new();
+
+fn locales() -> &'static Locales {
+    LOCALES.get_or_init(|| {
+        let mut locales: Locales =
+            serde_json::from_str(BUNDLED).expect("bundled locales.json is invalid");
+        match read_override() {
+            Ok(Some(overrides)) => {
+                for (locale, translations) in overrides {
+                    let entries = locales.entry(locale).or_default();
+                    for (path, translation) in translations {
+                        let entry = entries.entry(path).or_default();
+                        if translation.name.is_some() {
+                            entry.name = translation.name;
+                        }
+                        if translation.description.is_some() {
+                            entry.description = translation.description;
+                        }
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("error in {}: {}", OVERRIDE_PATH, e),
+        }
+        locales
+    })
+}
+
+/// The user's locales.json, `None` if there isn't one
+fn read_override() -> Result<Option<Locales>> {
+    match fs::read_to_string(OVERRIDE_PATH) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Translations of a command or option in each locale that has one
+pub fn translations(path: &str) -> Vec<(&'static str, &'static Translation)> {
+    let mut translations: Vec<(&'static str, &'static Translation)> = locales()
+        .iter()
+        .filter_map(|(locale, entries)| Some((locale.as_str(), entries.get(path)?)))
+        .collect();
+    // the same order on every start, so unchanged commands aren't registered again
+    translations.sort_by_key(|(locale, _)| *locale);
+    translations
+}
@@ -0,0 +1,121 @@
+use crate::{
+    budget::{self, RateBudget},
+    config,
+};
+use serenity::{builder::CreateMessage, client::Context};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer, registry::LookupSpan, Layer};
+
+/// Messages waiting to be posted, the oldest are dropped when Discord can't keep up
+const MAX_PENDING: usize = 50;
+/// Longest message Discord accepts
+const MAX_MESSAGE_LENGTH: usize = 2000;
+/// Time between posts, messages of the same few updates are posted together
+const POST_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Set once the channel is known, until then nothing is kept
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PENDING: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Keeps the warnings and errors logged during an update of the monitor loop (its `poll` span)
+/// for `log_channel_id`
+pub struct LogChannelLayer;
+
+impl<S> Layer<S> for LogChannelLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) {
+        let level = *event.metadata().level();
+        if !ENABLED.load(Ordering::Relaxed) || level > Level::WARN {
+            return;
+        }
+        let in_poll = ctx
+            .event_scope(event)
+            .is_some_and(|mut scope| scope.any(|span| span.name() == "poll"));
+        if !in_poll {
+            return;
+        }
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+        let mut pending = PENDING.lock().unwrap();
+        if pending.len() >= MAX_PENDING {
+            pending.pop_front();
+        }
+        pending.push_back(format!("**{}** {}", level, message.0));
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+/// The pending messages joined into as few Discord messages as possible
+fn take_messages() -> Vec<String> {
+    let mut messages: Vec<String> = vec![];
+    for line in PENDING.lock().unwrap().drain(..) {
+        let line: String = line.chars().take(MAX_MESSAGE_LENGTH).collect();
+        match messages.last_mut() {
+            Some(message) if message.len() + line.len() < MAX_MESSAGE_LENGTH => {
+                message.push('\n');
+                message.push_str(&line);
+            }
+            _ => messages.push(line),
+        }
+    }
+    messages
+}
+
+/// Posts the kept warnings and errors in the channel every 30 seconds, within the message
+/// budget. Messages over the budget wait for the next post
+pub async fn watch(ctx: Context, channel_id: u64, budget: Arc<RateBudget>) {
+    let channel_id = match config::channel_id(Some(channel_id)) {
+        Some(channel_id) => channel_id,
+        None => return,
+    };
+    if ENABLED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    loop {
+        tokio::time::sleep(POST_INTERVAL).await;
+        let guild_id = budget::guild_of(&ctx.cache, channel_id);
+        let mut messages = take_messages().into_iter();
+        while let Some(message) = messages.next() {
+            if !budget.allows(guild_id, budget::Action::Message) {
+                // the rest is posted later
+                let mut pending = PENDING.lock().unwrap();
+                for message in std::iter::once(message).chain(messages).rev() {
+                    pending.push_front(message);
+                }
+                pending.truncate(MAX_PENDING);
+                break;
+            }
+            match channel_id
+                .send_message(&ctx.http, CreateMessage::new().content(&message))
+                .await
+            {
+                Ok(_) => budget.record(guild_id, budget::Action::Message),
+                Err(e) => log::warn!("Failed to post to the log channel: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{config::Static, log_channel::LogChannelLayer, summary::ErrorCountLayer};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+/// Used when `level` isn't a valid filter
+const DEFAULT_LEVEL: &str = "warn,discord_bot=info";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// a line of text per message
+    Text,
+    /// a JSON object per line, with the poll cycle it belongs to, for log aggregation
+    Json,
+}
+
+/// Log output of the bot
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Logging {
+    /// filter like `warn,discord_bot=debug`, `RUST_LOG` wins over it
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl ::std::default::Default for Logging {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_LEVEL.into(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+impl Logging {
+    /// The settings of config.txt, read before the rest of it so its errors are logged
+    pub fn from_config() -> Self {
+        confy::load_path::<Static>("config.txt")
+            .map(|cfg| cfg.logging)
+            .unwrap_or_default()
+    }
+}
+
+/// Starts logging to stdout, or to a daily file in `logs` for services that have no console.
+/// The `log` messages of the bot and its dependencies go through it as well, warnings and errors
+/// of the monitor loop are also kept for `log_channel_id`, errors are counted for the shutdown
+/// summary and reported to Sentry when it is set up. Logs are written
+/// until the returned guard is dropped
+pub fn init(settings: &Logging, to_file: bool) -> Result<WorkerGuard> {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&settings.level))
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "Invalid log level {}, using {}: {}",
+                settings.level, DEFAULT_LEVEL, e
+            );
+            EnvFilter::new(DEFAULT_LEVEL)
+        });
+    let (writer, guard) = match to_file {
+        true => tracing_appender::non_blocking(tracing_appender::rolling::daily(
+            "logs",
+            "discord_bot.log",
+        )),
+        false => tracing_appender::non_blocking(std::io::stdout()),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(!to_file);
+    let result = match settings.format {
+        LogFormat::Text => builder
+            .finish()
+            .with(LogChannelLayer)
+            .with(ErrorCountLayer)
+            .with(sentry_tracing::layer())
+            .try_init(),
+        LogFormat::Json => builder
+            .json()
+            .finish()
+            .with(LogChannelLayer)
+            .with(ErrorCountLayer)
+            .with(sentry_tracing::layer())
+            .try_init(),
+    };
+    if let Err(e) = result {
+        anyhow::bail!("Logger initialization failed with {}", e);
+    }
+    Ok(guard)
+}
@@ -0,0 +1,179 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{OnceLock, RwLock},
+    time::{Duration, SystemTime},
+};
+
+/// Map names, map images and modes shipped with the bot
+const BUNDLED: &str = include_str!("maps.json");
+/// Optional file next to config.txt, its entries are merged over the bundled ones
+pub const OVERRIDE_PATH: &str = "maps.json";
+/// How often the override file is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MapInfo {
+    /// Human readable name, like `St Quentin Scar`
+    pub name: Option<String>,
+    /// Landscape image used for the avatar and embeds
+    pub image: Option<String>,
+    /// Players the map needs to play well, the status embed notes when there are fewer
+    pub min_players: Option<i64>,
+    /// Factions of team 1 and team 2, like `British Empire` and `German Empire`
+    pub teams: Option<Vec<String>>,
+    /// Factions for a game mode where they differ from `teams`, keyed by game mode
+    pub mode_teams: HashMap<String, Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ModeInfo {
+    /// Full name shown in embeds, like `Frontlines`
+    pub name: Option<String>,
+    /// Two letter mode drawn on the avatar, like `CQ`
+    pub short: Option<String>,
+}
+
+/// Contents of maps.json, keyed by internal map name (`MP_Amiens`) and game mode (`Conquest0`)
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MapData {
+    pub maps: HashMap<String, MapInfo>,
+    pub modes: HashMap<String, ModeInfo>,
+}
+
+impl MapData {
+    /// Merges the entries of `other` over these, only the fields it sets are replaced
+    fn merge(&mut self, other: MapData) {
+        for (key, map) in other.maps {
+            let entry = self.maps.entry(key).or_default();
+            if map.name.is_some() {
+                entry.name = map.name;
+            }
+            if map.image.is_some() {
+                entry.image = map.image;
+            }
+            if map.min_players.is_some() {
+                entry.min_players = map.min_players;
+            }
+            if map.teams.is_some() {
+                entry.teams = map.teams;
+            }
+            entry.mode_teams.extend(map.mode_teams);
+        }
+        for (key, mode) in other.modes {
+            let entry = self.modes.entry(key).or_default();
+            if mode.name.is_some() {
+                entry.name = mode.name;
+            }
+            if mode.short.is_some() {
+                entry.short = mode.short;
+            }
+        }
+    }
+}
+
+static DATA: OnceLock<RwLock<MapData>> = OnceLock::new();
+
+fn data() -> &'static RwLock<MapData> {
+    DATA.get_or_init(|| {
+        let mut data = bundled();
+        match read_override() {
+            Ok(Some(overrides)) => data.merge(overrides),
+            Ok(None) => {}
+            Err(e) => log::error!("error in {}: {}", OVERRIDE_PATH, e),
+        }
+        RwLock::new(data)
+    })
+}
+
+fn bundled() -> MapData {
+    serde_json::from_str(BUNDLED).expect("bundled maps.json is invalid")
+}
+
+/// The user's maps.json, `None` if there isn't one
+fn read_override() -> Result<Option<MapData>> {
+    match fs::read_to_string(OVERRIDE_PATH) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn modified() -> Option<SystemTime> {
+    fs::metadata(OVERRIDE_PATH)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+pub fn map_name(internal_map: &str) -> Option<String> {
+    let data = data().read().unwrap();
+    data.maps.get(internal_map).and_then(|map| map.name.clone())
+}
+
+pub fn map_image(internal_map: &str) -> Option<String> {
+    let data = data().read().unwrap();
+    data.maps
+        .get(internal_map)
+        .and_then(|map| map.image.clone())
+}
+
+pub fn min_players(internal_map: &str) -> Option<i64> {
+    let data = data().read().unwrap();
+    data.maps.get(internal_map).and_then(|map| map.min_players)
+}
+
+/// Faction name of a team on a map in a game mode, `Team 2` when it isn't known
+pub fn team_name(internal_map: &str, game_mode: &str, team: i64) -> String {
+    let data = data().read().unwrap();
+    data.maps
+        .get(internal_map)
+        .and_then(|map| map.mode_teams.get(game_mode).or(map.teams.as_ref()))
+        .and_then(|teams| usize::try_from(team - 1).ok().and_then(|i| teams.get(i)))
+        .cloned()
+        .unwrap_or_else(|| format!("Team {}", team))
+}
+
+pub fn mode_name(game_mode: &str) -> Option<String> {
+    let data = data().read().unwrap();
+    data.modes.get(game_mode).and_then(|mode| mode.name.clone())
+}
+
+pub fn small_mode(game_mode: &str) -> Option<String> {
+    let data = data().read().unwrap();
+    data.modes
+        .get(game_mode)
+        .and_then(|mode| mode.short.clone())
+}
+
+/// Reloads maps.json when it changes, a broken file keeps the previous data
+pub async fn watch() {
+    let mut last_modified = modified();
+    loop {
+        tokio::time::sleep(RELOAD_INTERVAL).await;
+        let current = modified();
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+        let mut reloaded = bundled();
+        match read_override() {
+            Ok(Some(overrides)) => reloaded.merge(overrides),
+            Ok(None) => {}
+            Err(e) => {
+                log::error!(
+                    "error in {}, keeping the previous maps: {}",
+                    OVERRIDE_PATH,
+                    e
+                );
+                continue;
+            }
+        }
+        *data().write().unwrap() = reloaded;
+        log::info!("Reloaded {}", OVERRIDE_PATH);
+    }
+}
@@ -0,0 +1,833 @@
+use crate::{
+    anomalies::{self, ANOMALIES},
+    config::Static,
+    health::label,
+    http_client, maps,
+};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+/// Tries per request before giving up until the next update
+pub const MAX_ATTEMPTS: u32 = 3;
+/// Consecutive failed updates before the polling interval is lengthened
+pub const BREAKER_THRESHOLD: u32 = 3;
+/// Longest polling interval while marne.io is down
+pub const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// Combined population like `87/128 across 2 servers`
+pub fn population(servers: &[&MarneServerInfo]) -> String {
+    format!(
+        "{}/{} across {} servers",
+        servers
+            .iter()
+            .map(|server| server.current_players)
+            .sum::<i64>(),
+        servers.iter().map(|server| server.max_players).sum::<i64>(),
+        servers.len()
+    )
+}
+
+/// A number marne.io may send as a string like `"64"`, missing or `null` is 0
+fn number_or_string<'de, D>(deserializer: D) -> std::result::Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Integer(i64),
+        Float(f64),
+        String(String),
+        Null(()),
+    }
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Integer(number) => Ok(number),
+        NumberOrString::Float(number) => Ok(number as i64),
+        NumberOrString::String(text) => {
+            let text = text.trim();
+            if text.is_empty() {
+                return Ok(0);
+            }
+            text.parse()
+                .or_else(|_| text.parse::<f64>().map(|number| number as i64))
+                .map_err(|_| serde::de::Error::custom(format!("`{}` isn't a number", text)))
+        }
+        NumberOrString::Null(_) => Ok(0),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Player {
+    pub name: String,
+    #[serde(deserialize_with = "number_or_string")]
+    pub team: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Mod {
+    pub category: String,
+    pub file_name: String,
+    pub link: String,
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ModType {
+    Vec(Vec<Mod>),
+    String(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum PlayerType {
+    Vec(Vec<Player>),
+    String(String),
+}
+
+impl ::std::default::Default for ModType {
+    fn default() -> Self {
+        ModType::Vec(vec![])
+    }
+}
+
+impl ::std::default::Default for PlayerType {
+    fn default() -> Self {
+        PlayerType::Vec(vec![])
+    }
+}
+
+impl ModType {
+    /// The mods, marne returns a string instead of a list if there are none
+    pub fn mods(&self) -> &[Mod] {
+        match self {
+            ModType::Vec(mods) => mods,
+            ModType::String(_) => &[],
+        }
+    }
+}
+
+impl PlayerType {
+    /// The players, marne returns a string instead of a list if there are none
+    pub fn players(&self) -> &[Player] {
+        match self {
+            PlayerType::Vec(players) => players,
+            PlayerType::String(_) => &[],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarneServerList {
+    #[serde(default, deserialize_with = "tolerant_servers")]
+    pub servers: Vec<MarneServerInfo>,
+}
+
+/// The servers of a list, the ones that don't parse are left out so they don't take the others
+/// down with them
+fn tolerant_servers<'de, D>(deserializer: D) -> std::result::Result<Vec<MarneServerInfo>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    let mut servers = Vec::with_capacity(values.len());
+    for value in values {
+        match MarneServerInfo::deserialize(&value) {
+            Ok(server) => servers.push(server),
+            Err(e) => ANOMALIES.count_server(
+                anomalies::DROPPED_SERVER,
+                value
+                    .get("id")
+                    .and_then(|id| id.as_i64())
+                    .unwrap_or_default(),
+                value
+                    .get("name")
+                    .and_then(|name| name.as_str())
+                    .unwrap_or_default(),
+                "server left out of the server list",
+                &format!("{} ({})", value.get("name").unwrap_or(&value), e),
+            ),
+        }
+    }
+    Ok(servers)
+}
+
+/// A server of the server list. Only `id` and `name` are required, other missing fields are
+/// empty or 0 and numbers may come as strings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarneServerInfo {
+    #[serde(deserialize_with = "number_or_string")]
+    pub id: i64,
+    pub name: String,
+    #[serde(rename = "mapName", default)]
+    pub map_name: String,
+    #[serde(rename = "gameMode", default)]
+    pub game_mode: String,
+    #[serde(rename = "maxPlayers", default, deserialize_with = "number_or_string")]
+    pub max_players: i64,
+    #[serde(rename = "tickRate", default, deserialize_with = "number_or_string")]
+    pub tick_rate: i64,
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub password: i64,
+    #[serde(
+        rename = "needSameMods",
+        default,
+        deserialize_with = "number_or_string"
+    )]
+    pub need_same_mods: i64,
+    #[serde(
+        rename = "allowMoreMods",
+        default,
+        deserialize_with = "number_or_string"
+    )]
+    pub allow_more_mods: i64,
+    #[serde(
+        rename = "currentPlayers",
+        default,
+        deserialize_with = "number_or_string"
+    )]
+    pub current_players: i64,
+    #[serde(default)]
+    pub region: String,
+    #[serde(default)]
+    pub country: String,
+}
+
+/// Place of a server in the server list by current players, servers with as many players share
+/// a place
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Rank {
+    pub overall: usize,
+    /// servers in the list
+    pub servers: usize,
+    pub region: usize,
+    /// servers of the region in the list
+    pub region_servers: usize,
+}
+
+/// Ranks of the monitored servers in the last server list they were in, by server id. The bots
+/// of the supervisor mode only replace the ranks of their own servers
+static RANKS: Mutex<BTreeMap<i64, Rank>> = Mutex::new(BTreeMap::new());
+
+/// The rank of a monitored server in the last server list it was in
+pub fn rank(server_id: i64) -> Option<Rank> {
+    RANKS.lock().unwrap().get(&server_id).cloned()
+}
+
+/// Ranks the found servers in the list they were found in
+fn record_ranks(list: &[MarneServerInfo], found: &[Option<MarneServerInfo>]) {
+    let mut ranks = RANKS.lock().unwrap();
+    for server in found.iter().flatten() {
+        ranks.insert(server.id, rank_in(list, server));
+    }
+}
+
+fn rank_in(list: &[MarneServerInfo], server: &MarneServerInfo) -> Rank {
+    let region: Vec<&MarneServerInfo> = list
+        .iter()
+        .filter(|other| other.region == server.region)
+        .collect();
+    let place = |servers: &[&MarneServerInfo]| {
+        servers
+            .iter()
+            .filter(|other| other.current_players > server.current_players)
+            .count()
+            + 1
+    };
+    let all: Vec<&MarneServerInfo> = list.iter().collect();
+    Rank {
+        overall: place(&all),
+        servers: all.len(),
+        region: place(&region),
+        region_servers: region.len(),
+    }
+}
+
+/// Players on all servers of the server list of a game, next to the monitored ones
+#[derive(Clone, Debug, Default)]
+pub struct Population {
+    pub game: String,
+    pub players: i64,
+    pub servers: usize,
+    /// players and servers per region
+    pub regions: BTreeMap<String, (i64, usize)>,
+}
+
+/// Population of the last server list of the monitored game
+static POPULATION: Mutex<Option<Population>> = Mutex::new(None);
+
+/// Population of the last server list, `None` before the first update
+pub fn community_population() -> Option<Population> {
+    POPULATION.lock().unwrap().clone()
+}
+
+fn population_of(game: &str, list: &[MarneServerInfo]) -> Population {
+    let mut population = Population {
+        game: game.to_string(),
+        ..Default::default()
+    };
+    for server in list {
+        population.players += server.current_players;
+        population.servers += 1;
+        let region = population.regions.entry(server.region.clone()).or_default();
+        region.0 += server.current_players;
+        region.1 += 1;
+    }
+    population
+}
+
+/// Prometheus text format of the players on all servers of the game, and per region
+pub fn population_metrics() -> String {
+    let mut metrics = String::from(
+        "# HELP marne_bot_community_players Players on all servers of the game\n\
+        # TYPE marne_bot_community_players gauge\n",
+    );
+    let population = match community_population() {
+        Some(population) => population,
+        None => return metrics,
+    };
+    let game = label(&population.game);
+    metrics += &format!(
+        "marne_bot_community_players{{game=\"{}\"}} {}\n",
+        game, population.players
+    );
+    metrics += "# HELP marne_bot_community_servers Servers in the server list of the game\n\
+        # TYPE marne_bot_community_servers gauge\n";
+    metrics += &format!(
+        "marne_bot_community_servers{{game=\"{}\"}} {}\n",
+        game, population.servers
+    );
+    metrics += "# HELP marne_bot_community_region_players Players on the servers of a region\n\
+        # TYPE marne_bot_community_region_players gauge\n";
+    for (region, (players, _)) in &population.regions {
+        metrics += &format!(
+            "marne_bot_community_region_players{{game=\"{}\",region=\"{}\"}} {}\n",
+            game,
+            label(region),
+            players
+        );
+    }
+    metrics
+}
+
+/// Games with a server list on marne.io
+pub const GAMES: [&str; 2] = ["bf1", "bfv"];
+/// Least time between looking for missing servers in the list of the other game
+const DETECTION_INTERVAL_SECS: i64 = 10 * 60;
+
+/// Game whose server list had the monitored servers of a bot when they weren't in the
+/// configured one
+#[derive(Default)]
+pub struct GameDetection {
+    detected: Mutex<Option<String>>,
+    /// Unix time of the last look in the list of the other game
+    last: Mutex<i64>,
+}
+
+impl GameDetection {
+    /// Game of the monitored servers: the detected one, else `game` from the config, else bf1
+    pub fn game(&self, configured: &Option<String>) -> String {
+        self.detected
+            .lock()
+            .unwrap()
+            .clone()
+            .or(configured.clone())
+            .unwrap_or("bf1".into())
+    }
+}
+
+/// Targets that matched several servers, so the warning about it is only logged once per target
+static AMBIGUOUS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// The monitored servers in a server list, `None` for the ones that aren't in it
+fn find_targets(statics: &Static, status: &MarneServerList) -> Vec<Option<MarneServerInfo>> {
+    statics
+        .targets()
+        .iter()
+        .map(|target| {
+            let mut candidates: Vec<(usize, &MarneServerInfo)> = status
+                .servers
+                .iter()
+                .filter_map(|server| {
+                    target
+                        .distance(server, statics.name_matching, statics.fuzzy_max_distance)
+                        .map(|distance| (distance, server))
+                })
+                .collect();
+            candidates.sort_by_key(|(distance, _)| *distance);
+            if candidates.len() > 1 {
+                let level = match AMBIGUOUS.lock().unwrap().insert(format!("{:?}", target)) {
+                    true => log::Level::Warn,
+                    false => log::Level::Debug,
+                };
+                log::log!(
+                    level,
+                    "{} servers match {:?}, using the closest: {}",
+                    candidates.len(),
+                    target,
+                    candidates[0].1.name
+                );
+            }
+            candidates.first().map(|(_, server)| (*server).clone())
+        })
+        .collect()
+}
+
+/// Looks for the missing servers in the lists of the other games, at most every 10 minutes.
+/// A game that has more of the monitored servers is used from then on, its list is returned
+/// with the servers found in it
+async fn detect_game(
+    client: &impl MarneClient,
+    statics: &Static,
+    detection: &GameDetection,
+    game: &str,
+    found: &[Option<MarneServerInfo>],
+) -> Option<(Vec<Option<MarneServerInfo>>, MarneServerList)> {
+    let now = chrono::Utc::now().timestamp();
+    {
+        let mut last = detection.last.lock().unwrap();
+        if now - *last < DETECTION_INTERVAL_SECS {
+            return None;
+        }
+        *last = now;
+    }
+    let count = |servers: &[Option<MarneServerInfo>]| servers.iter().flatten().count();
+    let mut best = None;
+    let mut most = count(found);
+    for other in GAMES.iter().filter(|other| **other != game) {
+        let status = match client.list_servers(other).await {
+            Ok(status) => status,
+            Err(e) => {
+                log::warn!("Failed to get the {} server list: {}", other, e);
+                continue;
+            }
+        };
+        let servers = find_targets(statics, &status);
+        if count(&servers) > most {
+            log::info!(
+                "Found the monitored servers in the {} server list instead of {}, set `game` \
+                 in config.txt to skip the detection",
+                other,
+                game
+            );
+            *detection.detected.lock().unwrap() = Some(other.to_string());
+            most = count(&servers);
+            best = Some((servers, status));
+        }
+    }
+    best
+}
+
+/// Finds the monitored servers in the server list of marne.io, in the order of the config.
+/// Servers missing from the list of the configured game are looked for in the other games, a
+/// game found there is kept in `detection`
+pub async fn fetch_servers(
+    client: &impl MarneClient,
+    statics: &Static,
+    detection: &GameDetection,
+) -> Result<Vec<MarneServerInfo>> {
+    let game = detection.game(&statics.game);
+    let status = match client.list_servers(&game).await {
+        Ok(status) => status,
+        Err(e) => anyhow::bail!(format!("Failed to get new serverinfo: {}", e)),
+    };
+
+    let mut found = find_targets(statics, &status);
+    *POPULATION.lock().unwrap() = Some(population_of(&game, &status.servers));
+    let mut list = status;
+    if found.iter().any(Option::is_none) {
+        if let Some((better, other)) = detect_game(client, statics, detection, &game, &found).await
+        {
+            found = better;
+            list = other;
+        }
+    }
+    record_ranks(&list.servers, &found);
+    let mut servers = vec![];
+    for (target, server) in statics.targets().iter().zip(found) {
+        match server {
+            Some(server) => {
+                let internal_map = internal_map(&server.map_name);
+                if map_name(internal_map) == internal_map {
+                    ANOMALIES.count_server(
+                        anomalies::UNKNOWN_MAP,
+                        server.id,
+                        &server.name,
+                        "unknown map",
+                        internal_map,
+                    );
+                }
+                if small_mode(&server.game_mode).is_empty() {
+                    ANOMALIES.count_server(
+                        anomalies::UNKNOWN_GAME_MODE,
+                        server.id,
+                        &server.name,
+                        "unknown game mode",
+                        &server.game_mode,
+                    );
+                }
+                servers.push(server);
+            }
+            None => log::warn!("Couldn't find server {:?} in serverlist", target),
+        }
+    }
+    Ok(servers)
+}
+
+/// The short mode shown on the avatar, like `CQ` for `Conquest0`, empty if unknown
+pub fn small_mode(game_mode: &str) -> String {
+    maps::small_mode(game_mode).unwrap_or_default()
+}
+
+/// Full name of a game mode for embeds, like `Frontlines` for `TugOfWar0`. Unknown modes are
+/// shown without their trailing digits split into title-cased words
+pub fn mode_name(game_mode: &str) -> String {
+    if let Some(name) = maps::mode_name(game_mode) {
+        return name;
+    }
+    let mut spaced = String::new();
+    let mut previous = ' ';
+    for c in game_mode
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .chars()
+    {
+        let c = match c {
+            '_' | '-' => ' ',
+            c => c,
+        };
+        if c.is_uppercase() && previous.is_lowercase() {
+            spaced.push(' ');
+        }
+        spaced.push(c);
+        previous = c;
+    }
+    let name = spaced
+        .split_whitespace()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    match name.is_empty() {
+        true => game_mode.to_string(),
+        false => name,
+    }
+}
+
+/// Returns the internal map name (`MP_Amiens`) from the path Marne reports.
+pub fn internal_map(map_name: &str) -> &str {
+    match map_name.rsplit('/').next() {
+        Some(last_segment) if !last_segment.is_empty() => last_segment,
+        _ => map_name,
+    }
+}
+
+/// Returns the landscape image of an internal map name, `None` if unknown.
+pub fn map_image(internal_map: &str) -> Option<String> {
+    maps::map_image(internal_map)
+}
+
+/// Returns the human readable name of an internal map name.
+pub fn map_name(internal_map: &str) -> String {
+    maps::map_name(internal_map).unwrap_or_else(|| internal_map.to_string())
+}
+
+/// A server with its players and mods, from the per-server detail endpoint
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarneServerDetail {
+    #[serde(flatten)]
+    pub info: MarneServerInfo,
+    #[serde(default)]
+    pub players: PlayerType,
+    #[serde(rename = "modList", default)]
+    pub mods: ModType,
+}
+
+/// Base url of the marne.io API when none are configured
+pub const DEFAULT_API_URL: &str = "https://marne.io/api";
+
+/// Base urls of the API, tried in order
+static API_URLS: OnceLock<Vec<String>> = OnceLock::new();
+/// Base url that answered the last request
+static SOURCE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Sets the base urls of the API and its mirrors, only the first call has an effect
+pub fn set_api_urls(urls: &[String]) {
+    let urls: Vec<String> = urls
+        .iter()
+        .map(|url| url.trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+        .collect();
+    API_URLS.get_or_init(|| match urls.is_empty() {
+        true => vec![DEFAULT_API_URL.to_string()],
+        false => urls,
+    });
+}
+
+/// Base url that answered the last request, `None` before the first answer
+pub fn source() -> Option<String> {
+    SOURCE.lock().unwrap().clone()
+}
+
+/// Prometheus text format of the base url that answered the last request
+pub fn source_metrics() -> String {
+    let mut metrics = String::from(
+        "# HELP marne_bot_api_source Base url of the marne.io API that answered the last request\n\
+        # TYPE marne_bot_api_source gauge\n",
+    );
+    if let Some(source) = source() {
+        metrics += &format!("marne_bot_api_source{{url=\"{}\"}} 1\n", label(&source));
+    }
+    metrics
+}
+
+/// Fetches a path of the API, falls over to the next mirror when one keeps failing
+async fn get_api<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let urls = API_URLS.get_or_init(|| vec![DEFAULT_API_URL.to_string()]);
+    let mut last_error = None;
+    for (index, base) in urls.iter().enumerate() {
+        match get_json(&format!("{}/{}", base, path)).await {
+            Ok(json) => {
+                let mut source = SOURCE.lock().unwrap();
+                if source.as_deref() != Some(base.as_str()) {
+                    log::info!("Using the marne.io API at {}", base);
+                    *source = Some(base.clone());
+                }
+                return Ok(json);
+            }
+            Err(e) => {
+                if let Some(next) = urls.get(index + 1) {
+                    log::warn!("{} failed, trying {}: {}", base, next, e);
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("no marne.io API url configured")))
+}
+
+/// Fetches a marne.io url, retried with an exponential backoff
+async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match try_get_json(url).await {
+            Ok(json) => return Ok(json),
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                let wait = Duration::from_secs(1 << (attempt - 1));
+                log::debug!("{}, retrying in {:?}", e, wait);
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Fetches a marne.io url once, marne sometimes prefixes the json with a BOM
+async fn try_get_json<T: DeserializeOwned>(url: &str) -> Result<T> {
+    match http_client::client().get(url).send().await {
+        Ok(resp) => {
+            let mut json_string = resp.text().await.unwrap_or_default();
+            // remove weird 0 width character
+            // https://github.com/seanmonstar/reqwest/issues/426
+            let json_bytes = json_string.as_bytes();
+            if json_bytes.first() == Some(&239) {
+                json_string.remove(0);
+                ANOMALIES.count(&ANOMALIES.bom_stripped, "BOM stripped from", url);
+            }
+            match serde_json::from_str::<T>(&json_string) {
+                Ok(json_res) => Ok(json_res),
+                Err(e) => {
+                    anyhow::bail!("marne public json is incorrect: {:#?}", e)
+                }
+            }
+        }
+        Err(e) => {
+            anyhow::bail!("marne public url failed: {:#?}", e)
+        }
+    }
+}
+
+/// Server lists older than this aren't reused
+const LIST_MAX_AGE_SECS: i64 = 5 * 60;
+
+/// Last server list per game with the unix time it was fetched, reused by `/servers`
+static LISTS: Mutex<BTreeMap<String, (i64, MarneServerList)>> = Mutex::new(BTreeMap::new());
+
+/// Server lists younger than this many seconds are reused by the monitor loops, so the bots of
+/// the supervisor mode share one poll. 0 fetches a new list every update
+static SHARED_LIST_SECS: AtomicI64 = AtomicI64::new(0);
+
+/// Lets the monitor loops reuse a server list fetched by another loop in the last seconds
+pub fn share_lists(max_age_secs: i64) {
+    SHARED_LIST_SECS.store(max_age_secs, Ordering::Relaxed);
+}
+
+pub async fn server_list(game: &str) -> Result<MarneServerList> {
+    let path = match game {
+        "bfv" => "v/srvlst/",
+        _ => "srvlst/",
+    };
+    let list: MarneServerList = get_api(path).await?;
+    LISTS.lock().unwrap().insert(
+        game.to_string(),
+        (chrono::Utc::now().timestamp(), list.clone()),
+    );
+    Ok(list)
+}
+
+/// The server list of the last update if it is recent, else a new one
+pub async fn recent_server_list(game: &str) -> Result<MarneServerList> {
+    let now = chrono::Utc::now().timestamp();
+    match LISTS.lock().unwrap().get(game) {
+        Some((fetched, list)) if now - fetched < LIST_MAX_AGE_SECS => return Ok(list.clone()),
+        _ => {}
+    }
+    server_list(game).await
+}
+
+fn detail_path(game: &str, server_id: i64) -> String {
+    match game {
+        "bfv" => format!("v/srvdtl/?id={}", server_id),
+        _ => format!("srvdtl/?id={}", server_id),
+    }
+}
+
+/// The details of a server as marne.io sends them, for the archive
+pub async fn server_detail_raw(game: &str, server_id: i64) -> Result<serde_json::Value> {
+    get_api(&detail_path(game, server_id)).await
+}
+
+pub async fn server_detail(game: &str, server_id: i64) -> Result<MarneServerDetail> {
+    let detail: MarneServerDetail = get_api(&detail_path(game, server_id)).await?;
+    if let PlayerType::String(value) = &detail.players {
+        ANOMALIES.count_server(
+            anomalies::DESERIALIZATION_FALLBACK,
+            detail.info.id,
+            &detail.info.name,
+            "player list as string",
+            value,
+        );
+    }
+    if let ModType::String(value) = &detail.mods {
+        ANOMALIES.count_server(
+            anomalies::DESERIALIZATION_FALLBACK,
+            detail.info.id,
+            &detail.info.name,
+            "mod list as string",
+            value,
+        );
+    }
+    Ok(detail)
+}
+
+/// Where the server lists and details come from, `Marne` in the bot and `MockClient` in tests
+#[serenity::async_trait]
+pub trait MarneClient: Send + Sync {
+    async fn list_servers(&self, game: &str) -> Result<MarneServerList>;
+    async fn server_detail(&self, game: &str, server_id: i64) -> Result<MarneServerDetail>;
+}
+
+/// The marne.io API and its mirrors
+pub struct Marne;
+
+#[serenity::async_trait]
+impl MarneClient for Marne {
+    async fn list_servers(&self, game: &str) -> Result<MarneServerList> {
+        let max_age = SHARED_LIST_SECS.load(Ordering::Relaxed);
+        if max_age > 0 {
+            let now = chrono::Utc::now().timestamp();
+            match LISTS.lock().unwrap().get(game) {
+                Some((fetched, list)) if now - fetched < max_age => return Ok(list.clone()),
+                _ => {}
+            }
+        }
+        server_list(game).await
+    }
+
+    async fn server_detail(&self, game: &str, server_id: i64) -> Result<MarneServerDetail> {
+        server_detail(game, server_id).await
+    }
+}
+
+/// Fixed answers without network access, a game or server that isn't set fails like marne.io
+/// being down
+#[derive(Clone, Debug, Default)]
+pub struct MockClient {
+    /// server list per game
+    pub lists: HashMap<String, MarneServerList>,
+    /// details per server id
+    pub details: HashMap<i64, MarneServerDetail>,
+}
+
+#[serenity::async_trait]
+impl MarneClient for MockClient {
+    async fn list_servers(&self, game: &str) -> Result<MarneServerList> {
+        match self.lists.get(game) {
+            Some(list) => Ok(list.clone()),
+            None => anyhow::bail!("no {} server list", game),
+        }
+    }
+
+    async fn server_detail(&self, _game: &str, server_id: i64) -> Result<MarneServerDetail> {
+        match self.details.get(&server_id) {
+            Some(detail) => Ok(detail.clone()),
+            None => anyhow::bail!("no details of server {}", server_id),
+        }
+    }
+}
+
+/// Circuit breaker of the monitor loop, lengthens the polling interval while marne.io keeps
+/// failing so it isn't hammered and the logs aren't flooded
+#[derive(Default)]
+pub struct Backoff {
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn success(&mut self) {
+        if self.failures >= BREAKER_THRESHOLD {
+            log::info!(
+                "marne.io is reachable again after {} failed updates",
+                self.failures
+            );
+        }
+        self.failures = 0;
+    }
+
+    /// Counts a failed update, returns if it should be logged as an error
+    pub fn failure(&mut self) -> bool {
+        self.failures += 1;
+        if self.failures == BREAKER_THRESHOLD {
+            log::warn!("marne.io keeps failing, polling less often until it recovers");
+        }
+        self.failures == 1 || self.failures == BREAKER_THRESHOLD
+    }
+
+    /// The polling interval, doubled for every failed update over the threshold
+    pub fn interval(&self, interval: Duration) -> Duration {
+        if self.failures < BREAKER_THRESHOLD {
+            return interval;
+        }
+        let factor = 1u32 << (self.failures - BREAKER_THRESHOLD + 1).min(10);
+        (interval * factor).min(MAX_BACKOFF.max(interval))
+    }
+}
@@ -0,0 +1,120 @@
+//! Stand-in for the marne.io API with canned responses, for the integration tests in
+//! tests/mock_marne.rs. Only built with the `mock-marne` feature
+use serde_json::{json, Value};
+use std::{collections::HashMap, net::SocketAddr, sync::OnceLock, thread};
+use warp::{
+    http::{Response, StatusCode},
+    Filter,
+};
+
+static ADDRESS: OnceLock<SocketAddr> = OnceLock::new();
+
+/// The bf1 server list, served with a BOM in front like marne.io sometimes does
+pub fn server_list() -> Value {
+    json!({
+        "servers": [
+            server(1, "[MARNE] Conquest #1", "Levels/MP/MP_Amiens/MP_Amiens", "Conquest0", 0, 48),
+            server(2, "[MARNE] Conquest #2", "Levels/MP/MP_Suez/MP_Suez", "Conquest0", 1, 12),
+            server(3, "Friday Frontlines", "Levels/MP/MP_Volga/MP_Volga", "TugOfWar0", 0, 0),
+        ]
+    })
+}
+
+fn server(
+    id: i64,
+    name: &str,
+    map_name: &str,
+    game_mode: &str,
+    password: i64,
+    current_players: i64,
+) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "mapName": map_name,
+        "gameMode": game_mode,
+        "maxPlayers": 64,
+        "tickRate": 60,
+        "password": password,
+        "needSameMods": 0,
+        "allowMoreMods": 1,
+        "currentPlayers": current_players,
+        "region": "EU",
+        "country": "NL",
+    })
+}
+
+/// The detail of a server of the list. Server 1 has lists of players and mods, the others
+/// have the strings marne.io sends instead of empty lists
+pub fn server_detail(id: i64) -> Option<Value> {
+    let mut detail = server_list()["servers"]
+        .as_array()?
+        .iter()
+        .find(|server| server["id"] == id)?
+        .clone();
+    let (players, mods) = match id {
+        1 => (
+            json!([
+                { "name": "Player1", "team": 1 },
+                { "name": "Player2", "team": 2 },
+            ]),
+            json!([{
+                "category": "Gameplay",
+                "file_name": "marne_tweaks.zip",
+                "link": "https://example.com/marne_tweaks.zip",
+                "name": "Marne tweaks",
+                "version": "1.0",
+            }]),
+        ),
+        _ => (json!(""), json!("")),
+    };
+    detail["players"] = players;
+    detail["modList"] = mods;
+    Some(detail)
+}
+
+fn respond(path: &str, query: &HashMap<String, String>) -> Response<String> {
+    let body = match path {
+        "/api/srvlst/" => Some(format!("\u{feff}{}", server_list())),
+        "/api/v/srvlst/" => Some(json!({ "servers": [] }).to_string()),
+        "/api/srvdtl/" => query
+            .get("id")
+            .and_then(|id| id.parse().ok())
+            .and_then(server_detail)
+            .map(|detail| detail.to_string()),
+        _ => None,
+    };
+    match body {
+        Some(body) => Response::builder()
+            .header("content-type", "application/json")
+            .body(body),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(String::new()),
+    }
+    .expect("mock marne.io response")
+}
+
+/// Base url of the mock for `marne::set_api_urls`, it is started on the first call. It runs
+/// on its own thread so it outlives the runtime of a single test
+pub fn api_url() -> String {
+    let address = ADDRESS.get_or_init(|| {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("mock marne.io runtime");
+            runtime.block_on(async {
+                let routes = warp::path::full()
+                    .and(warp::query::<HashMap<String, String>>())
+                    .map(|path: warp::path::FullPath, query| respond(path.as_str(), &query));
+                let (address, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+                let _ = sender.send(address);
+                server.await;
+            });
+        });
+        receiver.recv().expect("mock marne.io didn't start")
+    });
+    format!("http://{}/api", address)
+}
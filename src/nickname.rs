@@ -0,0 +1,73 @@
+use crate::{budget, discord::presence_text, marne::MarneServerInfo};
+use serde::{Deserialize, Serialize};
+use serenity::{client::Context, model::id::GuildId};
+use std::collections::HashMap;
+
+/// Longest nickname Discord accepts
+const MAX_NICKNAME_LENGTH: usize = 32;
+
+/// The nickname of the bot in every Discord server it is in set to the player count, which shows
+/// in the member list next to the presence
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Nickname {
+    pub enabled: bool,
+    /// placeholders of `presence_format` for the first monitored server
+    pub format: String,
+    /// used when none of the servers are in the server list
+    pub offline_format: String,
+}
+
+impl ::std::default::Default for Nickname {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: "{players}/{max} │ {map}".into(),
+            offline_format: "Server offline".into(),
+        }
+    }
+}
+
+impl Nickname {
+    pub fn text(&self, servers: &[MarneServerInfo]) -> String {
+        let text = match servers.first() {
+            Some(first) => presence_text(&self.format, first),
+            None => self.offline_format.clone(),
+        };
+        text.chars().take(MAX_NICKNAME_LENGTH).collect()
+    }
+}
+
+/// Sets the nickname in the Discord servers where it changed. Changes over the rename budget of a
+/// Discord server are skipped, the next update tries again
+pub async fn update(
+    ctx: &Context,
+    nickname: &Nickname,
+    budget: &budget::RateBudget,
+    current: &mut HashMap<GuildId, String>,
+    servers: &[MarneServerInfo],
+) {
+    if !nickname.enabled {
+        return;
+    }
+    let text = nickname.text(servers);
+    for guild_id in ctx.cache.guilds() {
+        if current.get(&guild_id) == Some(&text) {
+            continue;
+        }
+        if !budget.allows(Some(guild_id), budget::Action::Rename) {
+            log::debug!(
+                "Rename budget of {} used up, waiting to set the nickname",
+                guild_id
+            );
+            continue;
+        }
+        match guild_id.edit_nickname(&ctx.http, Some(&text)).await {
+            Ok(_) => {
+                budget.record(Some(guild_id), budget::Action::Rename);
+                current.insert(guild_id, text.clone());
+            }
+            Err(e) => log::warn!("Failed to set the nickname in {}: {}", guild_id, e),
+        }
+    }
+}
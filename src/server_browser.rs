@@ -0,0 +1,165 @@
+use crate::marne::{self, internal_map, map_name, mode_name, MarneServerInfo};
+use anyhow::Result;
+use serenity::{
+    builder::{CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter},
+    model::application::CommandInteraction,
+};
+
+/// Servers shown per page of `/servers`
+const PAGE_SIZE: usize = 10;
+/// Start of the custom id of the page buttons
+pub const BUTTON_PREFIX: &str = "servers:";
+/// Custom ids are limited to 100 characters, the region is cut to fit
+const MAX_REGION_LENGTH: usize = 40;
+
+/// Filters of `/servers`, they travel along in the custom id of the page buttons so the buttons
+/// keep working after a restart
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub game: String,
+    /// part of the region, like `EU`
+    pub region: Option<String>,
+    pub min_players: i64,
+    /// `Some(true)` for only servers with a password, `Some(false)` for only open ones
+    pub password: Option<bool>,
+}
+
+impl Filter {
+    /// The options of the command, `game` is the game of the monitored servers if not set
+    pub fn from_command(command: &CommandInteraction, game: String) -> Self {
+        let option = |name: &str| {
+            command
+                .data
+                .options
+                .iter()
+                .find(|option| option.name == name)
+                .map(|option| &option.value)
+        };
+        Self {
+            game: option("game")
+                .and_then(|value| value.as_str())
+                .map_or(game, str::to_string),
+            region: option("region")
+                .and_then(|value| value.as_str())
+                .map(|region| region.chars().take(MAX_REGION_LENGTH).collect()),
+            min_players: option("min_players")
+                .and_then(|value| value.as_i64())
+                .unwrap_or_default(),
+            password: option("password").and_then(|value| value.as_bool()),
+        }
+    }
+
+    /// Like `servers:2:bf1:10:0:EU`, the region goes last as it may contain a `:`
+    fn custom_id(&self, page: usize) -> String {
+        format!(
+            "{}{}:{}:{}:{}:{}",
+            BUTTON_PREFIX,
+            page,
+            self.game,
+            self.min_players,
+            match self.password {
+                Some(true) => "1",
+                Some(false) => "0",
+                None => "",
+            },
+            self.region.as_deref().unwrap_or_default()
+        )
+    }
+
+    /// The filter and page of a button, `None` if it isn't a page button
+    pub fn from_custom_id(custom_id: &str) -> Option<(Self, usize)> {
+        let mut parts = custom_id.strip_prefix(BUTTON_PREFIX)?.splitn(5, ':');
+        let page = parts.next()?.parse().ok()?;
+        let game = parts.next()?.to_string();
+        let min_players = parts.next()?.parse().ok()?;
+        let password = match parts.next()? {
+            "1" => Some(true),
+            "0" => Some(false),
+            _ => None,
+        };
+        let region = Some(parts.next()?.to_string()).filter(|region| !region.is_empty());
+        Some((
+            Self {
+                game,
+                region,
+                min_players,
+                password,
+            },
+            page,
+        ))
+    }
+
+    fn matches(&self, server: &MarneServerInfo) -> bool {
+        let region = match self.region {
+            Some(ref region) => server
+                .region
+                .to_lowercase()
+                .contains(&region.to_lowercase()),
+            None => true,
+        };
+        let password = match self.password {
+            Some(password) => (server.password != 0) == password,
+            None => true,
+        };
+        region && password && server.current_players >= self.min_players
+    }
+}
+
+fn server_line(server: &MarneServerInfo) -> String {
+    format!(
+        "**{}**{}\n{}/{} · {} · {} · {}",
+        server.name,
+        match server.password != 0 {
+            true => " 🔒",
+            false => "",
+        },
+        server.current_players,
+        server.max_players,
+        map_name(internal_map(&server.map_name)),
+        mode_name(&server.game_mode),
+        server.region
+    )
+}
+
+/// A page of the servers that match the filter, fullest first, with buttons to the other pages.
+/// The server list of the last update is used when it is recent
+pub async fn page(filter: &Filter, page: usize) -> Result<(CreateEmbed, Vec<CreateActionRow>)> {
+    let list = marne::recent_server_list(&filter.game).await?;
+    let mut servers: Vec<&MarneServerInfo> = list
+        .servers
+        .iter()
+        .filter(|server| filter.matches(server))
+        .collect();
+    servers.sort_by_key(|server| -server.current_players);
+
+    let pages = servers.len().div_ceil(PAGE_SIZE).max(1);
+    let page = page.min(pages - 1);
+    let description = match servers.is_empty() {
+        true => "No servers match the filters".to_string(),
+        false => servers
+            .iter()
+            .skip(page * PAGE_SIZE)
+            .take(PAGE_SIZE)
+            .map(|server| server_line(server))
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+    };
+    let embed = CreateEmbed::new()
+        .title(format!("Marne servers ({})", filter.game))
+        .description(description)
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{} · {} servers",
+            page + 1,
+            pages,
+            servers.len()
+        )));
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(filter.custom_id(page.saturating_sub(1)))
+            .label("Previous")
+            .disabled(page == 0),
+        CreateButton::new(filter.custom_id(page + 1))
+            .label("Next")
+            .disabled(page + 1 >= pages),
+    ]);
+    Ok((embed, vec![buttons]))
+}
@@ -0,0 +1,242 @@
+use anyhow::Result;
+use std::env;
+
+/// Name the bot is registered with as a Windows service or launchd job
+#[cfg(windows)]
+pub const SERVICE_NAME: &str = "marne-bot";
+/// launchd label of the generated plist
+pub const LAUNCHD_LABEL: &str = "io.marne.discord-bot";
+
+/// `service install|uninstall|run|launchd`
+pub async fn command(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("install") => install(),
+        Some("uninstall") => uninstall(),
+        Some("run") => run().await,
+        Some("launchd") => {
+            println!("{}", launchd_plist()?);
+            Ok(())
+        }
+        _ => anyhow::bail!("usage: service install|uninstall|run|launchd"),
+    }
+}
+
+/// Services are started outside of the bot's directory (`System32` on Windows),
+/// switch to the directory of the executable so `config.txt` and `history.db` are found
+pub fn enter_install_dir() -> Result<()> {
+    if let Some(dir) = env::current_exe()?.parent() {
+        env::set_current_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// launchd job that keeps the bot running from the current directory
+pub fn launchd_plist() -> Result<String> {
+    let exe = env::current_exe()?;
+    let dir = env::current_dir()?;
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>service</string>
+        <string>run</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{}</string>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardErrorPath</key>
+    <string>{}/marne-bot.log</string>
+</dict>
+</plist>"#,
+        LAUNCHD_LABEL,
+        exe.display(),
+        dir.display(),
+        dir.display(),
+    ))
+}
+
+#[cfg(target_os = "macos")]
+fn launchd_path() -> Result<std::path::PathBuf> {
+    let home = env::var("HOME")?;
+    Ok(std::path::Path::new(&home)
+        .join("Library/LaunchAgents")
+        .join(format!("{}.plist", LAUNCHD_LABEL)))
+}
+
+#[cfg(target_os = "macos")]
+fn install() -> Result<()> {
+    let path = launchd_path()?;
+    std::fs::write(&path, launchd_plist()?)?;
+    log::info!(
+        "Written {}, start it with: launchctl load {}",
+        path.display(),
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn uninstall() -> Result<()> {
+    let path = launchd_path()?;
+    std::fs::remove_file(&path)?;
+    log::info!(
+        "Removed {}, stop a running bot with: launchctl remove {}",
+        path.display(),
+        LAUNCHD_LABEL
+    );
+    Ok(())
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn install() -> Result<()> {
+    anyhow::bail!("installing as a service is only supported on Windows and macOS, use Docker or systemd instead")
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn uninstall() -> Result<()> {
+    anyhow::bail!("installing as a service is only supported on Windows and macOS, use Docker or systemd instead")
+}
+
+/// launchd (and systemd) run the bot as a normal foreground process
+#[cfg(not(windows))]
+async fn run() -> Result<()> {
+    crate::discord::run(crate::config::load()).await
+}
+
+#[cfg(windows)]
+fn install() -> Result<()> {
+    windows::install()
+}
+
+#[cfg(windows)]
+fn uninstall() -> Result<()> {
+    windows::uninstall()
+}
+
+#[cfg(windows)]
+async fn run() -> Result<()> {
+    // blocks until the service is stopped
+    tokio::task::spawn_blocking(windows::dispatch).await?
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::SERVICE_NAME;
+    use anyhow::Result;
+    use std::{env, ffi::OsString, sync::mpsc, time::Duration};
+    use windows_service::{
+        define_windows_service,
+        service::{
+            ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl,
+            ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus,
+            ServiceType,
+        },
+        service_control_handler::{self, ServiceControlHandlerResult},
+        service_dispatcher,
+        service_manager::{ServiceManager, ServiceManagerAccess},
+    };
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn install() -> Result<()> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )?;
+        let info = ServiceInfo {
+            name: OsString::from(SERVICE_NAME),
+            display_name: OsString::from("Marne Discord status bot"),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: env::current_exe()?,
+            launch_arguments: vec![OsString::from("service"), OsString::from("run")],
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        };
+        let service = manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+        service.set_description("Shows the status of a Battlefield marne server on Discord")?;
+        log::info!(
+            "Installed the {} service, start it with: sc start {}",
+            SERVICE_NAME,
+            SERVICE_NAME
+        );
+        Ok(())
+    }
+
+    pub fn uninstall() -> Result<()> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+        let service = manager.open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )?;
+        if service.query_status()?.current_state != ServiceState::Stopped {
+            service.stop()?;
+        }
+        service.delete()?;
+        log::info!("Removed the {} service", SERVICE_NAME);
+        Ok(())
+    }
+
+    pub fn dispatch() -> Result<()> {
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main)?;
+        Ok(())
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        if let Err(e) = run_service() {
+            log::error!("Service failed: {}", e);
+        }
+    }
+
+    fn run_service() -> Result<()> {
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        let stop_tx = shutdown_tx.clone();
+        let status_handle =
+            service_control_handler::register(SERVICE_NAME, move |event| match event {
+                ServiceControl::Stop => {
+                    stop_tx.send(()).ok();
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+                _ => ServiceControlHandlerResult::NotImplemented,
+            })?;
+        let set_state = |state, controls_accepted| {
+            status_handle.set_service_status(ServiceStatus {
+                service_type: ServiceType::OWN_PROCESS,
+                current_state: state,
+                controls_accepted,
+                exit_code: ServiceExitCode::Win32(0),
+                checkpoint: 0,
+                wait_hint: Duration::default(),
+                process_id: None,
+            })
+        };
+        set_state(ServiceState::Running, ServiceControlAccept::STOP)?;
+
+        // the service runs on its own thread, outside of the runtime of main
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.spawn(async move {
+            if let Err(e) = crate::discord::run(crate::config::load()).await {
+                log::error!("Bot stopped: {}", e);
+            }
+            shutdown_tx.send(()).ok();
+        });
+        shutdown_rx.recv().ok();
+        crate::summary::log_shutdown();
+        runtime.shutdown_timeout(Duration::from_secs(5));
+
+        set_state(ServiceState::Stopped, ServiceControlAccept::empty())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use crate::marne::{MarneServerInfo, Player};
+use std::collections::{HashMap, HashSet};
+
+/// Who joined and left a server between two updates
+#[derive(Debug, Clone, Default)]
+pub struct PlayerChanges {
+    pub joined: Vec<String>,
+    pub left: Vec<String>,
+    /// first player list of the server since the start, the players in `joined` were already on
+    pub baseline: bool,
+}
+
+/// The players of each server in the previous update, to derive joins and leaves from the
+/// player lists
+#[derive(Default)]
+pub struct PlayerTracker {
+    online: HashMap<i64, HashSet<String>>,
+}
+
+impl PlayerTracker {
+    /// The changes of a server since its previous player list
+    pub fn update(&mut self, server_id: i64, players: &[Player]) -> PlayerChanges {
+        let current: HashSet<String> = players.iter().map(|player| player.name.clone()).collect();
+        let baseline = !self.online.contains_key(&server_id);
+        let previous = self.online.insert(server_id, current.clone());
+        let previous = previous.unwrap_or_default();
+        let mut joined: Vec<String> = current.difference(&previous).cloned().collect();
+        let mut left: Vec<String> = previous.difference(&current).cloned().collect();
+        joined.sort();
+        left.sort();
+        PlayerChanges {
+            joined,
+            left,
+            baseline,
+        }
+    }
+
+    /// Servers that are gone from the server list, everyone on them left
+    pub fn forget_missing(&mut self, servers: &[MarneServerInfo]) -> Vec<(i64, PlayerChanges)> {
+        let missing: Vec<i64> = self
+            .online
+            .keys()
+            .filter(|id| !servers.iter().any(|server| server.id == **id))
+            .copied()
+            .collect();
+        missing
+            .into_iter()
+            .filter_map(|id| {
+                let mut left: Vec<String> = self.online.remove(&id)?.into_iter().collect();
+                left.sort();
+                Some((
+                    id,
+                    PlayerChanges {
+                        left,
+                        ..Default::default()
+                    },
+                ))
+            })
+            .collect()
+    }
+}
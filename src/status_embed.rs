@@ -0,0 +1,92 @@
+use crate::{
+    budget,
+    config::{self, Artwork, Static},
+    discord::server_embed,
+    marne::{self, MarneServerInfo},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateEmbed, CreateMessage, EditMessage},
+    client::Context,
+    model::id::MessageId,
+};
+
+/// A message in a channel that the bot keeps editing with the current status
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct StatusEmbed {
+    pub channel_id: Option<u64>,
+    /// set by the bot, the message it keeps editing
+    pub message_id: Option<u64>,
+}
+
+async fn embeds(game: &str, artwork: &Artwork, servers: &[MarneServerInfo]) -> Vec<CreateEmbed> {
+    let mut embeds = vec![];
+    // discord allows up to 10 embeds per message
+    for server in servers.iter().take(10) {
+        let embed = match marne::server_detail(game, server.id).await {
+            Ok(detail) => {
+                let mods: Vec<String> = detail
+                    .mods
+                    .mods()
+                    .iter()
+                    .map(|item| format!("[{} ({})]({})", item.name, item.version, item.link))
+                    .collect();
+                let mut embed = server_embed(server, artwork, Some(mods.len()));
+                if !mods.is_empty() {
+                    let mut value = mods.join("\n");
+                    if value.chars().count() > 1024 {
+                        value = format!("{}...", value.chars().take(1020).collect::<String>());
+                    }
+                    embed = embed.field("Mods", value, false);
+                }
+                embed
+            }
+            Err(e) => {
+                log::warn!("Failed to get the mods of {}: {}", server.name, e);
+                server_embed(server, artwork, None)
+            }
+        };
+        embeds.push(embed);
+    }
+    embeds
+}
+
+/// Edits the status message, posts a new one if it was removed.
+/// `message_id` is the message that is being edited, a new one is stored in config.txt
+pub async fn update(
+    ctx: &Context,
+    statics: &Static,
+    game: &str,
+    budget: &budget::RateBudget,
+    channel_id: u64,
+    message_id: &mut Option<u64>,
+    servers: &[MarneServerInfo],
+) -> Result<()> {
+    let channel_id = match config::channel_id(Some(channel_id)) {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let embeds = embeds(game, &statics.artwork, servers).await;
+    if let Some(id) = message_id.filter(|id| *id != 0) {
+        let edit = EditMessage::new().embeds(embeds.clone());
+        match channel_id
+            .edit_message(&ctx.http, MessageId::new(id), edit)
+            .await
+        {
+            Ok(_) => {
+                budget.record_channel(&ctx.cache, channel_id, budget::Action::Edit);
+                return Ok(());
+            }
+            Err(e) => log::warn!("Failed to edit status message, posting a new one: {}", e),
+        }
+    }
+    let message = channel_id
+        .send_message(&ctx.http, CreateMessage::new().embeds(embeds))
+        .await?;
+    budget.record_channel(&ctx.cache, channel_id, budget::Action::Message);
+    *message_id = Some(message.id.get());
+    // keep editing the same message after a restart
+    config::update(|cfg| cfg.status_embed.message_id = *message_id)
+}
@@ -0,0 +1,149 @@
+use crate::{health::ServerStatus, marne::internal_map};
+use chrono::DateTime;
+
+/// Points of the 24 hour sparkline, half an hour each
+pub const SPARKLINE_POINTS: usize = 48;
+
+/// Seconds between reloads of the page in the browser
+const REFRESH_SECS: u32 = 60;
+
+const STYLE: &str =
+    "body{margin:0;padding:24px;background:#1e2228;color:#ddd;font-family:sans-serif}
+.server{max-width:720px;margin:0 auto 24px;background:#282d35;border-radius:8px;overflow:hidden}
+.server img{display:block;width:100%;height:160px;object-fit:cover}
+.info{padding:16px}h1{margin:0 0 8px;font-size:1.4em;color:#fff}
+.count{font-size:1.2em;color:#58a6ff}ul{columns:2;padding-left:20px}
+svg{width:100%;height:60px}footer{text-align:center;color:#888;font-size:.8em}";
+
+/// Average player count per point of the sparkline, points without samples are `None`
+pub fn sparkline(samples: &[(i64, i64)], from: i64, to: i64) -> Vec<Option<f64>> {
+    let mut sums = vec![(0i64, 0i64); SPARKLINE_POINTS];
+    let point_secs = ((to - from) / SPARKLINE_POINTS as i64).max(1);
+    for (timestamp, players) in samples {
+        let point = ((timestamp - from) / point_secs) as usize;
+        if let Some((sum, count)) = sums.get_mut(point) {
+            *sum += players;
+            *count += 1;
+        }
+    }
+    sums.into_iter()
+        .map(|(sum, count)| match count {
+            0 => None,
+            _ => Some(sum as f64 / count as f64),
+        })
+        .collect()
+}
+
+/// Escapes the text of server, player and mod names for the page
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// The sparkline as an inline SVG, gaps without history are left out
+fn sparkline_svg(points: &[Option<f64>], max_players: i64) -> String {
+    let max_players = max_players.max(1) as f64;
+    let mut lines = vec![];
+    let mut line = vec![];
+    for (index, players) in points.iter().enumerate() {
+        match players {
+            Some(players) => line.push(format!(
+                "{:.1},{:.1}",
+                index as f64 * 100.0 / (SPARKLINE_POINTS - 1) as f64,
+                30.0 - 28.0 * (players / max_players).min(1.0)
+            )),
+            None if !line.is_empty() => lines.push(std::mem::take(&mut line)),
+            None => {}
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    let polylines: String = lines
+        .iter()
+        .map(|line| {
+            format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"#58a6ff\" stroke-width=\"1\" \
+                 vector-effect=\"non-scaling-stroke\"/>",
+                line.join(" ")
+            )
+        })
+        .collect();
+    format!(
+        "<svg viewBox=\"0 0 100 32\" preserveAspectRatio=\"none\">{}</svg>",
+        polylines
+    )
+}
+
+fn server_section(status: &ServerStatus) -> String {
+    let server = &status.info;
+    let mut html = format!(
+        "<section class=\"server\"><img src=\"/image/map/{}?width=720\" alt=\"{}\">\
+         <div class=\"info\"><h1>{}</h1><div class=\"count\">{}/{} players</div><p>{} · {}</p>",
+        escape(internal_map(&server.map_name)),
+        escape(&status.map),
+        escape(&server.name),
+        server.current_players,
+        server.max_players,
+        escape(&status.mode),
+        escape(&status.map),
+    );
+    if status.sparkline.iter().any(Option::is_some) {
+        html += "<h2>Last 24 hours</h2>";
+        html += &sparkline_svg(&status.sparkline, server.max_players);
+    }
+    if let Some(players) = &status.players {
+        html += "<h2>Players</h2>";
+        match players.is_empty() {
+            true => html += "<p>Nobody is on</p>",
+            false => {
+                html += "<ul>";
+                for player in players {
+                    html += &format!("<li>{}</li>", escape(player));
+                }
+                html += "</ul>";
+            }
+        }
+    }
+    if let Some(mods) = status.mods.as_ref().filter(|mods| !mods.is_empty()) {
+        html += "<h2>Mods</h2><ul>";
+        for item in mods {
+            html += &format!(
+                "<li><a href=\"{}\">{}</a> {}</li>",
+                escape(&item.link),
+                escape(&item.name),
+                escape(&item.version)
+            );
+        }
+        html += "</ul>";
+    }
+    html + "</div></section>"
+}
+
+/// The page of `/` for browsers with a section per server of the last update
+pub fn render(servers: &[ServerStatus], last_update: Option<i64>) -> String {
+    let title = match servers {
+        [server] => escape(&server.info.name),
+        _ => "Server status".to_string(),
+    };
+    let mut body: String = servers.iter().map(server_section).collect();
+    if servers.is_empty() {
+        body = "<section class=\"server\"><div class=\"info\"><h1>Server offline</h1>\
+                <p>The server isn't in the server list</p></div></section>"
+            .to_string();
+    }
+    let updated = last_update
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .map(|time| format!("Updated {} UTC", time.format("%Y-%m-%d %H:%M")))
+        .unwrap_or_else(|| "Waiting for the first update".to_string());
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <meta http-equiv=\"refresh\" content=\"{}\"><title>{}</title><style>{}</style></head>\
+         <body>{}<footer>{}</footer></body></html>",
+        REFRESH_SECS, title, STYLE, body, updated
+    )
+}
@@ -0,0 +1,187 @@
+use crate::{
+    budget::RateBudget,
+    config::{self, Static},
+    version::VersionReport,
+};
+use chrono::Utc;
+use serenity::{
+    builder::{CreateEmbed, CreateMessage},
+    client::Context,
+    http::Http,
+};
+use std::sync::{
+    atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+    Arc,
+};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::{layer, Layer};
+
+/// Counters of the run, for the shutdown summary
+pub struct RunStats {
+    /// unix time of the start
+    started: AtomicI64,
+    pub polls: AtomicU64,
+    pub failed_polls: AtomicU64,
+    /// errors logged
+    pub errors: AtomicU64,
+}
+
+pub static STATS: RunStats = RunStats {
+    started: AtomicI64::new(0),
+    polls: AtomicU64::new(0),
+    failed_polls: AtomicU64::new(0),
+    errors: AtomicU64::new(0),
+};
+
+/// Set once the startup summary is out, `ready` runs again on every reconnect
+static ANNOUNCED: AtomicBool = AtomicBool::new(false);
+
+/// Counts the errors logged, for the shutdown summary
+pub struct ErrorCountLayer;
+
+impl<S: Subscriber> Layer<S> for ErrorCountLayer {
+    fn on_event(&self, event: &Event<'_>, _: layer::Context<'_, S>) {
+        if *event.metadata().level() == Level::ERROR {
+            STATS.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Starts the uptime of the summary
+pub fn start() {
+    STATS
+        .started
+        .store(Utc::now().timestamp(), Ordering::Relaxed);
+}
+
+/// A channel of the config with its name, or that the bot can't see it
+async fn resolve(http: &Http, name: &str, channel_id: Option<u64>) -> Option<String> {
+    let channel_id = config::channel_id(channel_id)?;
+    let resolved = match channel_id.to_channel(http).await {
+        Ok(channel) => match channel.guild() {
+            Some(channel) => format!("#{} ({})", channel.name, channel_id),
+            None => format!("{} (not a server channel)", channel_id),
+        },
+        Err(e) => format!("{} (not found: {})", channel_id, e),
+    };
+    Some(format!("{}: {}", name, resolved))
+}
+
+/// Logs the targets, enabled features and configured channels once after the first start, and
+/// posts them in `summary_channel_id` so every admin can see what the bot is doing
+pub async fn startup(ctx: &Context, cfg: &Static, report: &VersionReport, budget: &RateBudget) {
+    if ANNOUNCED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let targets: Vec<String> = cfg
+        .targets()
+        .iter()
+        .map(|target| match (&target.name, target.id) {
+            (Some(name), _) => name.clone(),
+            (None, Some(id)) => format!("id {}", id),
+            (None, None) => "nothing".into(),
+        })
+        .collect();
+    let features = match report.features.is_empty() {
+        true => "none".to_string(),
+        false => report.features.join(", "),
+    };
+    let mut channels = vec![];
+    for (name, channel_id) in [
+        ("Alerts", cfg.alerts.channel_id),
+        ("Seeding", cfg.seeding.channel_id),
+        ("Status message", cfg.status_embed.channel_id),
+        ("Channel counter", cfg.channel_counter.channel_id),
+        ("Digest", cfg.digest.channel_id),
+        ("Scrim staff", cfg.scrim.staff_channel_id),
+        ("Log", cfg.log_channel_id),
+        ("Owner", cfg.owner_channel_id),
+        ("Summary", cfg.summary_channel_id),
+    ] {
+        channels.extend(resolve(&ctx.http, name, channel_id).await);
+    }
+    tracing::info!(
+        version = %report.version,
+        commit = %report.commit,
+        targets = ?targets,
+        game = ?cfg.game,
+        features = %features,
+        channels = ?channels,
+        "Startup summary"
+    );
+
+    let channel_id = match config::channel_id(cfg.summary_channel_id) {
+        Some(channel_id) => channel_id,
+        None => return,
+    };
+    let guild_id = crate::budget::guild_of(&ctx.cache, channel_id);
+    if !budget.allows(guild_id, crate::budget::Action::Message) {
+        return;
+    }
+    let embed = CreateEmbed::new()
+        .title("Bot started")
+        .field(
+            "Version",
+            format!("{} ({})", report.version, report.commit),
+            true,
+        )
+        .field("Game", cfg.game.clone().unwrap_or("bf1".into()), true)
+        .field("Servers", targets.join("\n"), false)
+        .field("Features", features, false)
+        .field(
+            "Channels",
+            match channels.is_empty() {
+                true => "none".to_string(),
+                false => channels.join("\n"),
+            },
+            false,
+        );
+    match channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await
+    {
+        Ok(_) => budget.record(guild_id, crate::budget::Action::Message),
+        Err(e) => log::warn!("Failed to post the startup summary: {}", e),
+    }
+}
+
+/// Uptime like `3d 4h 12m`, polls and errors of the run
+fn shutdown_text() -> String {
+    let minutes = (Utc::now().timestamp() - STATS.started.load(Ordering::Relaxed)) / 60;
+    format!(
+        "Uptime {}d {}h {}m, {} polls of which {} failed, {} errors logged",
+        minutes / 1440,
+        minutes / 60 % 24,
+        minutes % 60,
+        STATS.polls.load(Ordering::Relaxed),
+        STATS.failed_polls.load(Ordering::Relaxed),
+        STATS.errors.load(Ordering::Relaxed)
+    )
+}
+
+/// Logs the uptime, polls and errors of the run
+pub fn log_shutdown() {
+    tracing::info!(
+        uptime_secs = Utc::now().timestamp() - STATS.started.load(Ordering::Relaxed),
+        polls = STATS.polls.load(Ordering::Relaxed),
+        failed_polls = STATS.failed_polls.load(Ordering::Relaxed),
+        errors = STATS.errors.load(Ordering::Relaxed),
+        "Shutdown summary: {}",
+        shutdown_text()
+    );
+}
+
+/// Logs the shutdown summary and posts it in `summary_channel_id`
+pub async fn shutdown(http: Arc<Http>, summary_channel_id: Option<u64>) {
+    log_shutdown();
+    if let Some(channel_id) = config::channel_id(summary_channel_id) {
+        let message = CreateMessage::new().embed(
+            CreateEmbed::new()
+                .title("Bot stopped")
+                .description(shutdown_text()),
+        );
+        if let Err(e) = channel_id.send_message(&http, message).await {
+            log::warn!("Failed to post the shutdown summary: {}", e);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{config, http_client};
+use anyhow::Result;
+use serde::Deserialize;
+use serenity::{builder::CreateMessage, http::Http};
+use std::{sync::Arc, time};
+
+const RELEASES_URL: &str =
+    "https://api.github.com/repos/community-network/marne-bot/releases/latest";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Release {
+    pub tag_name: String,
+    pub html_url: String,
+}
+
+async fn latest_release() -> Result<Release> {
+    // the GitHub API rejects requests without an user agent, the shared client sets one
+    let resp = http_client::client()
+        .get(RELEASES_URL)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(resp.json::<Release>().await?)
+}
+
+/// Numeric parts of a version like `v1.2.3`
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().unwrap_or_default())
+        .collect()
+}
+
+/// If `tag` is a newer version than the one running
+pub fn is_newer(tag: &str) -> bool {
+    version_parts(tag) > version_parts(env!("CARGO_PKG_VERSION"))
+}
+
+/// Checks for a new release on startup and once a day, it is only announced
+/// in the log and the optional channel, the bot never updates itself
+pub async fn watch(http: Arc<Http>, channel_id: Option<u64>) {
+    let mut announced: Option<String> = None;
+    loop {
+        match latest_release().await {
+            Ok(release) if is_newer(&release.tag_name) => {
+                if announced.as_ref() != Some(&release.tag_name) {
+                    let message = format!(
+                        "A new marne-bot version is available: {} (running {})\nChangelog: {}",
+                        release.tag_name,
+                        env!("CARGO_PKG_VERSION"),
+                        release.html_url
+                    );
+                    log::warn!("{}", message);
+                    if let Some(channel_id) = config::channel_id(channel_id) {
+                        if let Err(e) = channel_id
+                            .send_message(&http, CreateMessage::new().content(message))
+                            .await
+                        {
+                            log::error!("Failed to post update notification: {:#?}", e);
+                        }
+                    }
+                    announced = Some(release.tag_name);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Failed to check for updates: {}", e),
+        }
+        tokio::time::sleep(time::Duration::from_secs(24 * 60 * 60)).await;
+    }
+}
@@ -0,0 +1,56 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Version and build info reported by `/version` and `/api/version`
+pub struct BuildInfo {
+    started: DateTime<Utc>,
+    /// optional functionality enabled in the config
+    features: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VersionReport {
+    pub version: String,
+    pub commit: String,
+    pub build_date: String,
+    pub features: Vec<String>,
+    pub uptime_secs: i64,
+}
+
+impl BuildInfo {
+    pub fn new(features: Vec<String>) -> Self {
+        Self {
+            started: Utc::now(),
+            features,
+        }
+    }
+
+    pub fn report(&self) -> VersionReport {
+        let build_date = env!("BUILD_TIMESTAMP")
+            .parse::<i64>()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_else(|| "unknown".into());
+        VersionReport {
+            version: env!("CARGO_PKG_VERSION").into(),
+            commit: env!("GIT_COMMIT").into(),
+            build_date,
+            features: self.features.clone(),
+            uptime_secs: (Utc::now() - self.started).num_seconds(),
+        }
+    }
+}
+
+impl VersionReport {
+    /// Uptime like `3d 4h 12m`
+    pub fn uptime(&self) -> String {
+        let minutes = self.uptime_secs / 60;
+        format!(
+            "{}d {}h {}m",
+            minutes / 1440,
+            minutes / 60 % 24,
+            minutes % 60
+        )
+    }
+}
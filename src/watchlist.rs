@@ -0,0 +1,93 @@
+use crate::config;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+/// Players a member can watch at most
+pub const MAX_WATCHES_PER_USER: usize = 25;
+
+/// A Discord member and a player they get a DM about when the player joins a monitored server,
+/// set with `/watch add`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Watch {
+    pub user_id: u64,
+    pub player: String,
+}
+
+/// The watched players, kept in config.txt so they survive a restart
+pub struct Watchlist {
+    watches: RwLock<Vec<Watch>>,
+}
+
+impl Watchlist {
+    pub fn new(watches: Vec<Watch>) -> Self {
+        Self {
+            watches: RwLock::new(watches),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.read().unwrap().is_empty()
+    }
+
+    /// Adds a player to the watches of a member, returns false if they already watch it
+    pub fn add(&self, user_id: u64, player: &str) -> Result<bool> {
+        let mut watches = self.watches.write().unwrap();
+        let own = watches.iter().filter(|watch| watch.user_id == user_id);
+        if own
+            .clone()
+            .any(|watch| watch.player.eq_ignore_ascii_case(player))
+        {
+            return Ok(false);
+        }
+        if own.count() >= MAX_WATCHES_PER_USER {
+            anyhow::bail!("you can watch up to {} players", MAX_WATCHES_PER_USER);
+        }
+        watches.push(Watch {
+            user_id,
+            player: player.to_string(),
+        });
+        store(&watches)?;
+        Ok(true)
+    }
+
+    /// Removes a player from the watches of a member, all of them without a player. Returns how
+    /// many were removed
+    pub fn remove(&self, user_id: u64, player: Option<&str>) -> Result<usize> {
+        let mut watches = self.watches.write().unwrap();
+        let before = watches.len();
+        watches.retain(|watch| {
+            watch.user_id != user_id
+                || player.is_some_and(|player| !watch.player.eq_ignore_ascii_case(player))
+        });
+        let removed = before - watches.len();
+        if removed > 0 {
+            store(&watches)?;
+        }
+        Ok(removed)
+    }
+
+    /// The players a member watches
+    pub fn players(&self, user_id: u64) -> Vec<String> {
+        let watches = self.watches.read().unwrap();
+        watches
+            .iter()
+            .filter(|watch| watch.user_id == user_id)
+            .map(|watch| watch.player.clone())
+            .collect()
+    }
+
+    /// The members watching a player, names are matched ignoring case
+    pub fn watchers(&self, player: &str) -> Vec<u64> {
+        let watches = self.watches.read().unwrap();
+        watches
+            .iter()
+            .filter(|watch| watch.player.eq_ignore_ascii_case(player))
+            .map(|watch| watch.user_id)
+            .collect()
+    }
+}
+
+fn store(watches: &[Watch]) -> Result<()> {
+    config::update(|cfg| cfg.watches = watches.to_vec())
+}
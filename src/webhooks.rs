@@ -0,0 +1,152 @@
+use crate::{events::Event, http_client, marne::MarneServerInfo};
+use anyhow::Result;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+/// Header with the hex encoded HMAC-SHA256 of the body, like `sha256=...`
+const SIGNATURE_HEADER: &str = "X-Marne-Bot-Signature";
+/// Header with the type of the payload
+const EVENT_HEADER: &str = "X-Marne-Bot-Event";
+
+/// An url that gets a JSON POST for the events of the monitored servers
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Webhook {
+    pub url: String,
+    /// (optional) key of the signature header, so the receiver can check the bot sent it
+    pub secret: Option<String>,
+    /// types sent, like `offline`, `online`, `map_change`, `server_full` or `player_threshold`,
+    /// all if empty
+    pub events: Vec<String>,
+    /// player counts that send a `player_threshold` when a server goes over or under them
+    pub player_thresholds: Vec<i64>,
+}
+
+impl Webhook {
+    fn wants(&self, kind: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|event| event == kind)
+    }
+}
+
+/// Sends the events of the monitor loop to the configured webhooks, in the background so a slow
+/// receiver doesn't hold up the updates
+pub struct Webhooks {
+    hooks: Vec<Webhook>,
+}
+
+impl Webhooks {
+    pub fn new(hooks: Vec<Webhook>) -> Self {
+        Self {
+            hooks: hooks
+                .into_iter()
+                .filter(|hook| !hook.url.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Sends an event with its `type` and the unix `timestamp`
+    pub fn send_event(&self, event: &Event) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        let mut payload = match serde_json::to_value(event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::error!("Failed to serialize the event for the webhooks: {}", e);
+                return;
+            }
+        };
+        payload["timestamp"] = json!(Utc::now().timestamp());
+        let kind = payload["type"].as_str().unwrap_or_default().to_string();
+        self.send(&kind, payload, |_| true);
+    }
+
+    /// Sends a `player_threshold` for every threshold a server went over or under since the
+    /// previous update
+    pub fn check_thresholds(&self, previous: &[MarneServerInfo], servers: &[MarneServerInfo]) {
+        for after in servers {
+            let before = match previous.iter().find(|before| before.id == after.id) {
+                Some(before) => before,
+                None => continue,
+            };
+            let mut thresholds: Vec<i64> = self
+                .hooks
+                .iter()
+                .flat_map(|hook| hook.player_thresholds.iter().copied())
+                .collect();
+            thresholds.sort_unstable();
+            thresholds.dedup();
+            for threshold in thresholds {
+                let direction = match (
+                    before.current_players >= threshold,
+                    after.current_players >= threshold,
+                ) {
+                    (false, true) => "up",
+                    (true, false) => "down",
+                    _ => continue,
+                };
+                let payload = json!({
+                    "type": "player_threshold",
+                    "timestamp": Utc::now().timestamp(),
+                    "server": after.name,
+                    "server_id": after.id,
+                    "threshold": threshold,
+                    "direction": direction,
+                    "players": after.current_players,
+                    "max_players": after.max_players,
+                });
+                self.send("player_threshold", payload, |hook| {
+                    hook.player_thresholds.contains(&threshold)
+                });
+            }
+        }
+    }
+
+    fn send(&self, kind: &str, payload: Value, filter: impl Fn(&Webhook) -> bool) {
+        let body = payload.to_string();
+        for hook in self
+            .hooks
+            .iter()
+            .filter(|hook| hook.wants(kind) && filter(hook))
+        {
+            let hook = hook.clone();
+            let kind = kind.to_string();
+            let body = body.clone();
+            tokio::spawn(async move {
+                if let Err(e) = deliver(&hook, &kind, body).await {
+                    log::warn!("Webhook {} failed for {}: {}", hook.url, kind, e);
+                }
+            });
+        }
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of the body
+pub fn signature(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+async fn deliver(hook: &Webhook, kind: &str, body: String) -> Result<()> {
+    let mut request = http_client::client()
+        .post(&hook.url)
+        .header("content-type", "application/json")
+        .header(EVENT_HEADER, kind);
+    if let Some(ref secret) = hook.secret {
+        request = request.header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", signature(secret, &body)),
+        );
+    }
+    request.body(body).send().await?.error_for_status()?;
+    Ok(())
+}
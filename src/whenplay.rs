@@ -0,0 +1,315 @@
+use crate::{
+    budget::{self, RateBudget},
+    config,
+    events::{Event, EventBus},
+};
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateMessage, CreatePoll, CreatePollAnswer, CreateScheduledEvent},
+    client::Context,
+    model::{
+        guild::ScheduledEventType,
+        id::{ChannelId, GuildId, MessageId},
+        Timestamp,
+    },
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time,
+};
+
+/// Discord allows up to 10 answers per poll
+const MAX_SLOTS: usize = 10;
+/// Discord allows polls of up to 32 days
+const MAX_POLL_HOURS: u64 = 32 * 24;
+/// Discord needs a moment to count the votes of a closed poll, after this it is given up
+const FINALIZE_GRACE_SECS: i64 = 30 * 60;
+
+/// `/whenplay`, a poll for the time to play that schedules the seeding at the winning time
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WhenPlay {
+    /// times (UTC) offered in the poll, like `20:00`
+    pub slots: Vec<String>,
+    /// how long the poll is open
+    pub poll_hours: u64,
+    /// minutes before the winning time the seeding alert is sent
+    pub alert_before_mins: i64,
+    /// length of the scheduled Discord event
+    pub event_minutes: i64,
+    /// seeding alert, `{time}` and `{votes}` are filled in
+    pub message: String,
+    /// set by the bot, polls that are open or waiting for their seeding alert
+    pub pending: Vec<PendingPoll>,
+}
+
+impl ::std::default::Default for WhenPlay {
+    fn default() -> Self {
+        Self {
+            slots: vec![
+                "18:00".into(),
+                "19:00".into(),
+                "20:00".into(),
+                "21:00".into(),
+            ],
+            poll_hours: 4,
+            alert_before_mins: 15,
+            event_minutes: 120,
+            message: "Seeding starts {time} as {votes} of you voted, join now!".into(),
+            pending: vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingPoll {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+    /// unix time of each answer, in the order of the poll
+    pub slots: Vec<i64>,
+    /// unix time
+    pub closes_at: i64,
+    /// unix time of the slot that won, once the poll closed
+    pub winner: Option<i64>,
+    pub votes: u64,
+}
+
+/// Next time of each `HH:MM` slot after the poll closes, sorted
+fn slot_times(slots: &[String], closes_at: DateTime<Utc>) -> Result<Vec<i64>> {
+    let mut times = vec![];
+    for slot in slots {
+        let time = match NaiveTime::parse_from_str(slot.trim(), "%H:%M") {
+            Ok(time) => time,
+            Err(_) => anyhow::bail!("`{}` isn't a time like 20:00", slot.trim()),
+        };
+        let mut at = closes_at.date_naive().and_time(time).and_utc();
+        if at <= closes_at {
+            at += Duration::days(1);
+        }
+        times.push(at.timestamp());
+    }
+    times.sort_unstable();
+    times.dedup();
+    if times.is_empty() || times.len() > MAX_SLOTS {
+        anyhow::bail!("A poll needs 1 to {} times", MAX_SLOTS);
+    }
+    Ok(times)
+}
+
+fn slot_text(time: i64) -> String {
+    DateTime::from_timestamp(time, 0)
+        .map(|time| time.format("%a %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+/// Stores the pending polls in config.txt, so they survive a restart
+fn save(pending: &[PendingPoll]) {
+    if let Err(e) = config::update(|cfg| cfg.whenplay.pending = pending.to_vec()) {
+        log::error!("Failed to save the /whenplay polls: {}", e);
+    }
+}
+
+/// Posts the poll in a channel, `slots` (comma separated) replaces the configured times
+pub async fn create(
+    ctx: &Context,
+    whenplay: &WhenPlay,
+    pending: &RwLock<Vec<PendingPoll>>,
+    budget: &RateBudget,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    slots: Option<&str>,
+) -> Result<String> {
+    let poll_hours = whenplay.poll_hours.clamp(1, MAX_POLL_HOURS);
+    let closes_at = Utc::now() + Duration::hours(poll_hours as i64);
+    let slots: Vec<String> = match slots {
+        Some(slots) => slots.split(',').map(str::to_string).collect(),
+        None => whenplay.slots.clone(),
+    };
+    let times = slot_times(&slots, closes_at)?;
+
+    let answers = times
+        .iter()
+        .map(|time| CreatePollAnswer::new().text(slot_text(*time)))
+        .collect();
+    let poll = CreatePoll::new()
+        .question("When will you play?")
+        .answers(answers)
+        .duration(time::Duration::from_secs(poll_hours * 60 * 60));
+    // poll answers are plain text, the message shows the times in the time zone of the reader
+    let content = format!(
+        "Vote for the time you can play, the server is seeded at the winning time:\n{}",
+        times
+            .iter()
+            .map(|time| format!("- {}: <t:{}:F>", slot_text(*time), time))
+            .collect::<Vec<String>>()
+            .join("\n")
+    );
+    let message = channel_id
+        .send_message(&ctx.http, CreateMessage::new().content(content).poll(poll))
+        .await?;
+    budget.record(Some(guild_id), budget::Action::Message);
+
+    let mut pending = pending.write().unwrap();
+    pending.push(PendingPoll {
+        guild_id: guild_id.get(),
+        channel_id: channel_id.get(),
+        message_id: message.id.get(),
+        slots: times,
+        closes_at: closes_at.timestamp(),
+        winner: None,
+        votes: 0,
+    });
+    save(&pending);
+    Ok(format!(
+        "Poll posted, it closes <t:{}:R>",
+        closes_at.timestamp()
+    ))
+}
+
+/// Index of the winning answer and its votes, `None` while Discord is still counting.
+/// A tie goes to the earliest time
+async fn tally(ctx: &Context, poll: &PendingPoll) -> Result<Option<(usize, u64)>> {
+    let message = ChannelId::new(poll.channel_id)
+        .message(&ctx.http, MessageId::new(poll.message_id))
+        .await?;
+    let discord_poll = match message.poll {
+        Some(discord_poll) => discord_poll,
+        None => anyhow::bail!("the message has no poll"),
+    };
+    let results = match discord_poll.results {
+        Some(ref results) if results.is_finalized => results,
+        _ => return Ok(None),
+    };
+    let mut winner = (0, 0);
+    for (index, answer) in discord_poll.answers.iter().enumerate() {
+        let votes = results
+            .answer_counts
+            .iter()
+            .find(|count| count.id == answer.answer_id)
+            .map_or(0, |count| count.count);
+        if votes > winner.1 {
+            winner = (index, votes);
+        }
+    }
+    Ok(Some(winner))
+}
+
+/// Adds the winning time as a Discord event and announces it under the poll
+async fn schedule(
+    ctx: &Context,
+    whenplay: &WhenPlay,
+    budget: &RateBudget,
+    poll: &PendingPoll,
+    location: &str,
+    time: i64,
+) -> Result<()> {
+    let guild_id = GuildId::new(poll.guild_id);
+    ChannelId::new(poll.channel_id)
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().content(format!(
+                "<t:{}:F> won with {} votes, the seeding alert follows {} minutes before.",
+                time, poll.votes, whenplay.alert_before_mins
+            )),
+        )
+        .await?;
+    budget.record(Some(guild_id), budget::Action::Message);
+
+    let event = CreateScheduledEvent::new(
+        ScheduledEventType::External,
+        "Seeding",
+        Timestamp::from_unix_timestamp(time)?,
+    )
+    .end_time(Timestamp::from_unix_timestamp(
+        time + whenplay.event_minutes * 60,
+    )?)
+    .location(location.chars().take(100).collect::<String>())
+    .description("Picked with /whenplay, join at the start to get the server going");
+    guild_id.create_scheduled_event(&ctx.http, event).await?;
+    Ok(())
+}
+
+/// Closes the polls and sends the seeding alerts of the winning times, checked every minute.
+/// `location` is shown on the Discord event
+pub async fn watch(
+    ctx: Context,
+    whenplay: WhenPlay,
+    pending: Arc<RwLock<Vec<PendingPoll>>>,
+    events: Arc<EventBus>,
+    budget: Arc<RateBudget>,
+    location: String,
+) {
+    static RUNNING: AtomicBool = AtomicBool::new(false);
+    if RUNNING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    loop {
+        let now = Utc::now().timestamp();
+        let polls = pending.read().unwrap().clone();
+        for mut poll in polls {
+            let message_id = poll.message_id;
+            // `None` removes the poll
+            let updated = match poll.winner {
+                None if now >= poll.closes_at => match tally(&ctx, &poll).await {
+                    Ok(Some((_, 0))) => {
+                        log::info!("Nobody voted in the /whenplay poll {}", poll.message_id);
+                        None
+                    }
+                    Ok(Some((index, votes))) => {
+                        let time = poll.slots.get(index).copied().unwrap_or_default();
+                        poll.winner = Some(time);
+                        poll.votes = votes;
+                        if let Err(e) =
+                            schedule(&ctx, &whenplay, &budget, &poll, &location, time).await
+                        {
+                            log::error!("Failed to schedule the /whenplay event: {}", e);
+                        }
+                        Some(poll)
+                    }
+                    Ok(None) | Err(_) if now < poll.closes_at + FINALIZE_GRACE_SECS => continue,
+                    Ok(None) => {
+                        log::warn!(
+                            "Gave up waiting for the votes of /whenplay poll {}",
+                            poll.message_id
+                        );
+                        None
+                    }
+                    Err(e) => {
+                        log::error!(
+                            "Failed to get the /whenplay poll {}: {}",
+                            poll.message_id,
+                            e
+                        );
+                        None
+                    }
+                },
+                Some(time) if now >= time - whenplay.alert_before_mins * 60 => {
+                    let event = Event::PlannedSeeding {
+                        time,
+                        votes: poll.votes,
+                        text: whenplay
+                            .message
+                            .replace("{time}", &format!("<t:{}:R>", time))
+                            .replace("{votes}", &poll.votes.to_string()),
+                    };
+                    if let Err(e) = events.publish(&ctx, &event, false).await {
+                        log::error!("Failed to publish event: {:#?}", e);
+                    }
+                    None
+                }
+                _ => continue,
+            };
+            let mut pending = pending.write().unwrap();
+            pending.retain(|poll| poll.message_id != message_id);
+            pending.extend(updated);
+            save(&pending);
+        }
+        tokio::time::sleep(time::Duration::from_secs(60)).await;
+    }
+}
@@ -0,0 +1,50 @@
+//! A burst of events is shown as one message that keeps the first and latest events
+
+use discord_bot::batcher::{Batch, Batcher};
+use serenity::model::id::{ChannelId, MessageId};
+
+fn batch(max_lines: usize, lines: &[String]) -> Batch {
+    let batcher = Batcher::new(60, max_lines);
+    let channel_id = ChannelId::new(1);
+    batcher.start(channel_id, MessageId::new(1), lines[0].clone());
+    let mut batch = None;
+    for line in &lines[1..] {
+        batch = batcher.add(channel_id, line.clone());
+    }
+    batch.unwrap()
+}
+
+fn events(amount: usize) -> Vec<String> {
+    (1..=amount).map(|i| format!("event {}", i)).collect()
+}
+
+#[test]
+fn shows_all_events_up_to_max_lines() {
+    let lines = events(5);
+    assert_eq!(batch(5, &lines).content(5), lines.join("\n"));
+}
+
+#[test]
+fn counts_the_events_over_max_lines() {
+    let lines = events(6);
+    assert_eq!(
+        batch(5, &lines).content(5),
+        "event 1\n*… 2 more events …*\nevent 4\nevent 5\nevent 6"
+    );
+}
+
+#[test]
+fn shows_at_least_the_first_event_and_the_count() {
+    let lines = events(4);
+    assert_eq!(batch(0, &lines).content(0), "event 1\n*… 3 more events …*");
+    assert_eq!(batch(1, &lines).content(1), "event 1\n*… 3 more events …*");
+}
+
+#[test]
+fn cuts_messages_over_the_discord_limit() {
+    let lines = vec!["first".to_string(), "é".repeat(2500)];
+    let content = batch(5, &lines).content(5);
+    assert_eq!(content.chars().count(), 2000);
+    assert!(content.starts_with("first\né"));
+    assert!(content.ends_with("é..."));
+}
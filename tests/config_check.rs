@@ -0,0 +1,60 @@
+//! `check-config` and the template of `generate-config`
+
+use discord_bot::config;
+use std::fs;
+
+fn check(name: &str, text: &str) -> Vec<String> {
+    let path = std::env::temp_dir().join(format!("marne-bot-{}-{}.txt", name, std::process::id()));
+    fs::write(&path, text).unwrap();
+    let problems = config::check(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    problems
+}
+
+#[test]
+fn template_only_misses_the_token_and_server() {
+    let problems = check("template", config::TEMPLATE);
+    assert!(
+        problems
+            .iter()
+            .all(|problem| problem.contains("`token`") || problem.contains("`server_name`")),
+        "{:?}",
+        problems
+    );
+    let filled = config::TEMPLATE
+        .replace("token = ''", "token = 'abc'")
+        .replace("server_name = ''", "server_name = 'SUPER@ [SiC] S1'");
+    assert_eq!(check("filled", &filled), Vec::<String>::new());
+}
+
+#[test]
+fn finds_typos_and_bad_values() {
+    let problems = check(
+        "typos",
+        "token = 'abc'\nserver_name = 'S1'\nupdate_intervall_secs = 30\ngame = 'bf4'\n\n[alerts]\nchanel_id = 1\n",
+    );
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    for expected in ["`update_intervall_secs`", "`alerts.chanel_id`", "`bf4`"] {
+        assert!(
+            problems.iter().any(|problem| problem.contains(expected)),
+            "{:?}",
+            problems
+        );
+    }
+}
+
+#[test]
+fn rejects_zero_ids() {
+    let problems = check(
+        "zero-ids",
+        "token = 'abc'\nserver_name = 'S1'\nsummary_channel_id = 0\n\n[alerts]\nchannel_id = 0\nrole_id = 1\n",
+    );
+    assert_eq!(problems.len(), 2, "{:?}", problems);
+    for expected in ["`summary_channel_id`", "`alerts.channel_id`"] {
+        assert!(
+            problems.iter().any(|problem| problem.contains(expected)),
+            "{:?}",
+            problems
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! End-to-end tests against a real Discord test server, run them by hand before a release:
+//!
+//! ```bash
+//! MARNE_BOT_TEST_TOKEN=... MARNE_BOT_TEST_GUILD=... MARNE_BOT_TEST_CHANNEL=... \
+//!     cargo test --features e2e --test discord_e2e -- --test-threads=1
+//! ```
+//!
+//! The bot of the token has to be in the guild, allowed to post in the channel and have the
+//! presence intent enabled in the developer portal. The tests clean up what they create.
+#![cfg(feature = "e2e")]
+
+use discord_bot::{
+    config::{Artwork, Static},
+    discord,
+    marne::{internal_map, map_name, MarneServerInfo},
+};
+use serenity::{
+    all::{GatewayIntents, Presence, Ready},
+    async_trait,
+    builder::CreateMessage,
+    client::{Client, Context, EventHandler},
+    http::Http,
+    model::id::{ChannelId, GuildId},
+};
+use std::{env, time::Duration};
+use tokio::sync::mpsc;
+
+struct TestEnv {
+    token: String,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+}
+
+impl TestEnv {
+    fn new() -> Self {
+        let var = |name: &str| {
+            env::var(name).unwrap_or_else(|_| panic!("{} has to be set for the e2e tests", name))
+        };
+        let id = |name: &str| {
+            var(name)
+                .parse::<u64>()
+                .unwrap_or_else(|_| panic!("{} has to be an id", name))
+        };
+        Self {
+            token: var("MARNE_BOT_TEST_TOKEN"),
+            guild_id: GuildId::new(id("MARNE_BOT_TEST_GUILD")),
+            channel_id: ChannelId::new(id("MARNE_BOT_TEST_CHANNEL")),
+        }
+    }
+}
+
+/// A server like the ones in the marne.io server list
+fn server() -> MarneServerInfo {
+    serde_json::from_value(serde_json::json!({
+        "id": 1234,
+        "name": "marne-bot e2e test",
+        "mapName": "Levels/MP/MP_Amiens/MP_Amiens",
+        "gameMode": "Conquest0",
+        "maxPlayers": 64,
+        "tickRate": 60,
+        "password": 0,
+        "needSameMods": 0,
+        "allowMoreMods": 0,
+        "currentPlayers": 20,
+        "region": "EU",
+        "country": "NL",
+    }))
+    .unwrap()
+}
+
+async fn command_names(http: &Http, guild_id: GuildId) -> Vec<String> {
+    let mut names: Vec<String> = guild_id
+        .get_commands(http)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|command| command.name)
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn registers_commands() {
+    let test = TestEnv::new();
+    let http = Http::new(&test.token);
+    let commands = [discord::public_commands(), discord::admin_commands()].concat();
+    discord::sync_commands(&http, Some(test.guild_id), commands.clone())
+        .await
+        .unwrap();
+    let registered = command_names(&http, test.guild_id).await;
+    assert_eq!(registered.len(), commands.len());
+    assert!(registered.contains(&"status".to_string()));
+
+    // unchanged commands are left alone
+    discord::sync_commands(&http, Some(test.guild_id), commands)
+        .await
+        .unwrap();
+    assert_eq!(command_names(&http, test.guild_id).await, registered);
+
+    // and commands that are gone are removed
+    discord::sync_commands(&http, Some(test.guild_id), discord::public_commands())
+        .await
+        .unwrap();
+    assert!(!command_names(&http, test.guild_id)
+        .await
+        .contains(&"setserver".to_string()));
+    discord::sync_commands(&http, Some(test.guild_id), vec![])
+        .await
+        .unwrap();
+    assert!(command_names(&http, test.guild_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn posts_status_embed() {
+    let test = TestEnv::new();
+    let http = Http::new(&test.token);
+    let server = server();
+    let embed = discord::server_embed(&server, &Artwork::default(), None);
+    let message = test
+        .channel_id
+        .send_message(&http, CreateMessage::new().embed(embed))
+        .await
+        .unwrap();
+    let posted = test.channel_id.message(&http, message.id).await.unwrap();
+    message.delete(&http).await.unwrap();
+
+    let embed = &posted.embeds[0];
+    assert_eq!(embed.title.as_deref(), Some("marne-bot e2e test"));
+    let field = |name: &str| {
+        embed
+            .fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| field.value.clone())
+    };
+    assert!(field("Map")
+        .unwrap()
+        .contains(&map_name(internal_map(&server.map_name))));
+    assert!(field("Players").unwrap().contains("20/64"));
+}
+
+/// Sets the presence once ready, and passes on the presence Discord sends back for the bot
+struct PresenceHandler {
+    activities: mpsc::UnboundedSender<String>,
+}
+
+#[async_trait]
+impl EventHandler for PresenceHandler {
+    async fn ready(&self, ctx: Context, _: Ready) {
+        discord::update_presence(&ctx, &Static::default(), &[server()], 0, false);
+    }
+
+    async fn presence_update(&self, ctx: Context, presence: Presence) {
+        if presence.user.id != ctx.cache.current_user().id {
+            return;
+        }
+        for activity in presence.activities {
+            let _ = self.activities.send(activity.name);
+        }
+    }
+}
+
+#[tokio::test]
+async fn updates_presence() {
+    let test = TestEnv::new();
+    let (sender, mut activities) = mpsc::unbounded_channel();
+    let intents = GatewayIntents::GUILDS | GatewayIntents::GUILD_PRESENCES;
+    let mut client = Client::builder(&test.token, intents)
+        .event_handler(PresenceHandler { activities: sender })
+        .await
+        .unwrap();
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move { client.start().await });
+
+    let server = server();
+    let expected = format!("20/64 - {}", map_name(internal_map(&server.map_name)));
+    let shown = tokio::time::timeout(Duration::from_secs(60), async {
+        while let Some(activity) = activities.recv().await {
+            if activity == expected {
+                return true;
+            }
+        }
+        false
+    })
+    .await;
+    shard_manager.shutdown_all().await;
+    assert_eq!(
+        shown,
+        Ok(true),
+        "the presence wasn't updated to {}",
+        expected
+    );
+}
@@ -0,0 +1,152 @@
+//! Bad input falls back instead of panicking the event handler or the monitor loop
+
+use discord_bot::{
+    config, images, locales, maps,
+    marne::{internal_map, MarneServerDetail, MarneServerList},
+};
+use std::fs;
+
+#[test]
+fn internal_map_of_odd_paths() {
+    assert_eq!(internal_map("Levels/MP/MP_Amiens/MP_Amiens"), "MP_Amiens");
+    assert_eq!(internal_map("MP_Amiens"), "MP_Amiens");
+    assert_eq!(internal_map(""), "");
+    assert_eq!(internal_map("Levels/MP/"), "Levels/MP/");
+}
+
+#[tokio::test]
+async fn history_chart_without_buckets() {
+    let samples = [(1_000, 10), (2_000, 20)];
+    assert!(images::history_chart(&samples, 0, 3_000, 64, 0, "%H:%M")
+        .await
+        .is_ok());
+    // from after to, and labels wider than the chart
+    let format = "%A %d %B %Y %H:%M:%S ".repeat(8);
+    assert!(images::history_chart(&samples, 3_000, 0, 64, 24, &format)
+        .await
+        .is_ok());
+}
+
+#[test]
+fn broken_config_keeps_the_last_working_one() {
+    let path = std::env::temp_dir().join(format!("marne-bot-config-{}.txt", std::process::id()));
+    let path_str = path.to_str().unwrap();
+
+    fs::write(&path, "token = 'first'\n").unwrap();
+    assert_eq!(config::reload_path(path_str).token, "first");
+    fs::write(&path, "token = 'half written").unwrap();
+    assert_eq!(config::reload_path(path_str).token, "first");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn zero_channel_ids_are_left_unset() {
+    assert_eq!(config::channel_id(Some(0)), None);
+    assert_eq!(config::channel_id(None), None);
+    assert_eq!(config::channel_id(Some(42)).map(|id| id.get()), Some(42));
+
+    let path = std::env::temp_dir().join(format!("marne-bot-zero-ids-{}.txt", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    fs::write(
+        &path,
+        "log_channel_id = 0\n\n[alerts]\nchannel_id = 0\nrole_id = 5\n\n[status_embed]\nchannel_id = 7\nmessage_id = 0\n",
+    )
+    .unwrap();
+    let cfg = config::reload_path(path_str);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(cfg.log_channel_id, None);
+    assert_eq!(cfg.alerts.channel_id, None);
+    assert_eq!(cfg.alerts.role_id, Some(5));
+    assert_eq!(cfg.status_embed.channel_id, Some(7));
+    assert_eq!(cfg.status_embed.message_id, None);
+}
+
+#[tokio::test]
+async fn unusable_fonts_fall_back_to_the_builtin_one() {
+    let not_a_font =
+        std::env::temp_dir().join(format!("marne-bot-font-{}.ttf", std::process::id()));
+    fs::write(&not_a_font, "not a font").unwrap();
+    images::load_fonts(&[
+        "/nonexistent/font.ttf".to_string(),
+        not_a_font.to_str().unwrap().to_string(),
+    ]);
+    fs::remove_file(&not_a_font).unwrap();
+
+    // the text is still drawn, so the banners of two names differ
+    let first = images::server_banner(None, "[MARNE] Conquest #1", "Amiens")
+        .await
+        .unwrap();
+    let second = images::server_banner(None, "[MARNE] Frontlines", "Amiens")
+        .await
+        .unwrap();
+    assert_ne!(first, second);
+    // characters the font has no glyphs for don't stop the banner
+    assert!(images::server_banner(None, "[MARNE] 征服", "アミアン")
+        .await
+        .is_ok());
+}
+
+#[test]
+fn reload_keeps_the_environment_overrides() {
+    let path = std::env::temp_dir().join(format!("marne-bot-env-{}.txt", std::process::id()));
+    let path_str = path.to_str().unwrap();
+
+    fs::write(&path, "mins_between_avatar_change = 5\n").unwrap();
+    std::env::set_var("MARNE_BOT_MINS_BETWEEN_AVATAR_CHANGE", "30");
+    let cfg = config::reload_path(path_str);
+    std::env::remove_var("MARNE_BOT_MINS_BETWEEN_AVATAR_CHANGE");
+    fs::remove_file(&path).unwrap();
+    assert_eq!(cfg.mins_between_avatar_change, 30);
+}
+
+#[test]
+fn bundled_data_loads() {
+    assert_eq!(maps::map_name("MP_Amiens").as_deref(), Some("Amiens"));
+    assert!(!locales::translations("status").is_empty());
+}
+
+#[test]
+fn unknown_teams_are_numbered() {
+    assert_eq!(
+        maps::team_name("MP_Amiens", "Conquest0", 1),
+        "British Empire"
+    );
+    assert_eq!(maps::team_name("MP_Amiens", "Conquest0", 3), "Team 3");
+    assert_eq!(maps::team_name("MP_Unknown", "Conquest0", 0), "Team 0");
+}
+
+#[test]
+fn server_list_survives_schema_drift() {
+    let list: MarneServerList = serde_json::from_value(serde_json::json!({
+        "servers": [
+            {
+                "id": 1,
+                "name": "numbers as strings",
+                "maxPlayers": "64",
+                "currentPlayers": " 12 ",
+                "tickRate": 60.0,
+                "password": null,
+                "someNewField": true,
+            },
+            { "name": "no id" },
+            { "id": 3, "name": "bad number", "maxPlayers": "lots" },
+            { "id": 4, "name": "bare" },
+        ]
+    }))
+    .unwrap();
+    let ids: Vec<i64> = list.servers.iter().map(|server| server.id).collect();
+    assert_eq!(ids, [1, 4]);
+    assert_eq!(list.servers[0].max_players, 64);
+    assert_eq!(list.servers[0].current_players, 12);
+    assert_eq!(list.servers[0].tick_rate, 60);
+    assert_eq!(list.servers[1].map_name, "");
+}
+
+#[test]
+fn server_detail_without_lists() {
+    let detail: MarneServerDetail =
+        serde_json::from_value(serde_json::json!({ "id": "7", "name": "detail" })).unwrap();
+    assert_eq!(detail.info.id, 7);
+    assert!(detail.players.players().is_empty());
+    assert!(detail.mods.mods().is_empty());
+}
@@ -0,0 +1,204 @@
+//! Matching, presence and alerts on fixed server lists, without network access
+
+use discord_bot::{
+    config::{NameMatching, Static},
+    discord::presence_text,
+    events::{self, Event},
+    marne::{self, MarneServerInfo, MarneServerList, MockClient},
+};
+use std::collections::HashMap;
+
+fn server(id: i64, name: &str, map: &str, players: i64) -> MarneServerInfo {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "name": name,
+        "mapName": format!("Levels/MP/{0}/{0}", map),
+        "gameMode": "Conquest0",
+        "maxPlayers": 64,
+        "tickRate": 60,
+        "password": 0,
+        "needSameMods": 0,
+        "allowMoreMods": 0,
+        "currentPlayers": players,
+        "region": "EU",
+        "country": "NL",
+    }))
+    .unwrap()
+}
+
+fn client(servers: Vec<MarneServerInfo>) -> MockClient {
+    let mut client = MockClient::default();
+    client
+        .lists
+        .insert("bf1".into(), MarneServerList { servers });
+    client
+}
+
+fn statics(name: &str, name_matching: NameMatching) -> Static {
+    Static {
+        server_name: Some(name.to_string()),
+        name_matching,
+        ..Static::default()
+    }
+}
+
+#[tokio::test]
+async fn finds_the_closest_server() {
+    let client = client(vec![
+        server(1, "[MARNE] Conquest #1", "MP_Amiens", 10),
+        server(2, "[MARNE] Conquest #2", "MP_Suez", 20),
+    ]);
+    let found = marne::fetch_servers(
+        &client,
+        &statics("[MARNE] Conquest #2", NameMatching::Fuzzy),
+        &marne::GameDetection::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, 2);
+}
+
+#[tokio::test]
+async fn fails_when_marne_is_down() {
+    let client = MockClient::default();
+    let statics = statics("[MARNE] Conquest #1", NameMatching::Exact);
+    assert!(
+        marne::fetch_servers(&client, &statics, &marne::GameDetection::default())
+            .await
+            .is_err()
+    );
+}
+
+#[test]
+fn formats_the_presence() {
+    let server = server(1, "[MARNE] Conquest #1", "MP_Amiens", 10);
+    assert_eq!(
+        presence_text("{players}/{max} - {map} {password_lock}", &server),
+        "10/64 - Amiens"
+    );
+    assert_eq!(
+        presence_text("{mode} on {name}", &server),
+        "Conquest on [MARNE] Conquest #1"
+    );
+    let locked = MarneServerInfo {
+        password: 1,
+        ..server
+    };
+    assert_eq!(presence_text("{players}/{max}", &locked), "10/64 🔒");
+}
+
+#[test]
+fn alerts_on_map_change_and_full_server() {
+    let before = [server(1, "[MARNE] Conquest #1", "MP_Amiens", 63)];
+    let after = [server(1, "[MARNE] Conquest #1", "MP_Suez", 64)];
+    let changes = events::diff(&before, &after);
+    assert_eq!(changes.len(), 2);
+    assert!(matches!(
+        &changes[0],
+        Event::MapChange { previous_map, map, .. } if previous_map == "Amiens" && map == "Suez"
+    ));
+    assert!(matches!(changes[1], Event::ServerFull { players: 64, .. }));
+    assert!(events::diff(&after, &after).is_empty());
+}
+
+#[test]
+fn switching_the_server_doesnt_alert_offline() {
+    let old = [server(1, "[MARNE] Conquest #1", "MP_Amiens", 20)];
+    let new = [server(2, "[MARNE] Conquest #2", "MP_Amiens", 20)];
+    let mut kept = events::Availability::default();
+    kept.update(&old, 2);
+    let offline: Vec<Event> = (0..2).flat_map(|_| kept.update(&new, 2)).collect();
+    assert!(matches!(&offline[..], [Event::Offline { server }] if server == "[MARNE] Conquest #1"));
+
+    // `/setserver` switched from the old to the new server
+    let mut switched = events::Availability::default();
+    switched.update(&old, 2);
+    switched.forget_missing(&new);
+    for _ in 0..3 {
+        assert!(switched.update(&new, 2).is_empty());
+    }
+    assert!(!switched.any_offline());
+    assert_eq!(switched.states(&new), vec![(2, true)]);
+}
+
+#[tokio::test]
+async fn announces_rank_milestones_once_per_cooldown() {
+    let statics = statics("[MARNE] Ranked", NameMatching::Exact);
+    let detection = marne::GameDetection::default();
+    let ranking = events::Ranking {
+        milestones: vec![1, 3],
+        ..Default::default()
+    };
+    let (mut previous, mut announced) = (HashMap::new(), HashMap::new());
+    let update = |players: i64| {
+        let client = client(vec![
+            server(101, "[MARNE] Ranked", "MP_Amiens", players),
+            server(102, "[MARNE] Other #1", "MP_Suez", 20),
+            server(103, "[MARNE] Other #2", "MP_Suez", 30),
+        ]);
+        let statics = &statics;
+        let detection = &detection;
+        async move {
+            marne::fetch_servers(&client, statics, detection)
+                .await
+                .unwrap()
+        }
+    };
+
+    // the first rank has nothing to compare to
+    let servers = update(10).await;
+    assert_eq!(marne::rank(101).unwrap().overall, 3);
+    assert!(ranking
+        .check("bf1", &servers, &mut previous, &mut announced)
+        .is_empty());
+
+    // up from third to first, only the best milestone is announced
+    let servers = update(40).await;
+    let events = ranking.check("bf1", &servers, &mut previous, &mut announced);
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        &events[0],
+        Event::RankMilestone { rank: 1, text, .. } if text.contains("top 1 ") && text.contains("BF1")
+    ));
+
+    // down and up again within the cooldown
+    let servers = update(25).await;
+    assert!(ranking
+        .check("bf1", &servers, &mut previous, &mut announced)
+        .is_empty());
+    let servers = update(40).await;
+    assert!(ranking
+        .check("bf1", &servers, &mut previous, &mut announced)
+        .is_empty());
+
+    // an empty server shares the last place without a rank
+    let servers = update(0).await;
+    assert!(ranking
+        .check("bf1", &servers, &mut previous, &mut announced)
+        .is_empty());
+    assert!(!previous.contains_key(&101));
+}
+
+#[tokio::test]
+async fn ranks_servers_found_in_the_other_game() {
+    let mut client = client(vec![server(201, "[MARNE] Conquest #1", "MP_Amiens", 10)]);
+    client.lists.insert(
+        "bfv".into(),
+        MarneServerList {
+            servers: vec![
+                server(202, "[MARNE] BFV #1", "MP_Amiens", 30),
+                server(203, "[MARNE] BFV #2", "MP_Amiens", 20),
+            ],
+        },
+    );
+    let statics = statics("[MARNE] BFV #2", NameMatching::Exact);
+    let detection = marne::GameDetection::default();
+    let found = marne::fetch_servers(&client, &statics, &detection)
+        .await
+        .unwrap();
+    assert_eq!(found[0].id, 203);
+    assert_eq!(detection.game(&None), "bfv");
+    let rank = marne::rank(203).unwrap();
+    assert_eq!((rank.overall, rank.servers), (2, 2));
+}
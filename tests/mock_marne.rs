@@ -0,0 +1,117 @@
+//! The marne.io client against the mock API, run them with
+//!
+//! ```bash
+//! cargo test --features mock-marne --test mock_marne
+//! ```
+#![cfg(feature = "mock-marne")]
+
+use discord_bot::{
+    anomalies::ANOMALIES,
+    config::{NameMatching, Static},
+    marne::{self, PlayerType},
+    mock_marne,
+};
+use std::sync::atomic::Ordering;
+
+/// Points the client at the mock, all tests share it as the urls can only be set once
+fn mock() -> String {
+    let url = mock_marne::api_url();
+    marne::set_api_urls(std::slice::from_ref(&url));
+    url
+}
+
+fn statics(name: &str, name_matching: NameMatching) -> Static {
+    Static {
+        server_name: Some(name.to_string()),
+        name_matching,
+        ..Static::default()
+    }
+}
+
+#[tokio::test]
+async fn gets_the_server_list() {
+    let url = mock();
+    let list = marne::server_list("bf1").await.unwrap();
+    assert_eq!(list.servers.len(), 3);
+    assert_eq!(list.servers[0].name, "[MARNE] Conquest #1");
+    assert_eq!(marne::source(), Some(url));
+}
+
+#[tokio::test]
+async fn strips_the_bom() {
+    mock();
+    let before = ANOMALIES.bom_stripped.load(Ordering::Relaxed);
+    assert!(marne::server_list("bf1").await.is_ok());
+    assert!(ANOMALIES.bom_stripped.load(Ordering::Relaxed) > before);
+}
+
+#[tokio::test]
+async fn parses_lists_and_strings() {
+    mock();
+    let detail = marne::server_detail("bf1", 1).await.unwrap();
+    assert_eq!(detail.info.name, "[MARNE] Conquest #1");
+    assert_eq!(detail.players.players().len(), 2);
+    assert_eq!(detail.mods.mods()[0].name, "Marne tweaks");
+
+    let detail = marne::server_detail("bf1", 2).await.unwrap();
+    assert!(matches!(detail.players, PlayerType::String(_)));
+    assert!(detail.players.players().is_empty());
+    assert!(detail.mods.mods().is_empty());
+}
+
+#[tokio::test]
+async fn matches_the_monitored_servers() {
+    mock();
+    let ids = |servers: Vec<marne::MarneServerInfo>| -> Vec<i64> {
+        servers.iter().map(|server| server.id).collect()
+    };
+    let detection = marne::GameDetection::default();
+    let exact = statics("[MARNE] Conquest #2", NameMatching::Exact);
+    assert_eq!(
+        ids(marne::fetch_servers(&marne::Marne, &exact, &detection)
+            .await
+            .unwrap()),
+        [2]
+    );
+
+    let case = statics(" [marne] conquest #1", NameMatching::CaseInsensitive);
+    assert_eq!(
+        ids(marne::fetch_servers(&marne::Marne, &case, &detection)
+            .await
+            .unwrap()),
+        [1]
+    );
+
+    let substring = statics("frontlines", NameMatching::Substring);
+    assert_eq!(
+        ids(marne::fetch_servers(&marne::Marne, &substring, &detection)
+            .await
+            .unwrap()),
+        [3]
+    );
+
+    let fuzzy = statics("[MARNE] Conqest #1", NameMatching::Fuzzy);
+    assert_eq!(
+        ids(marne::fetch_servers(&marne::Marne, &fuzzy, &detection)
+            .await
+            .unwrap()),
+        [1]
+    );
+
+    let by_id = Static {
+        server_id: Some(3),
+        ..Static::default()
+    };
+    assert_eq!(
+        ids(marne::fetch_servers(&marne::Marne, &by_id, &detection)
+            .await
+            .unwrap()),
+        [3]
+    );
+
+    let missing = statics("Not a server", NameMatching::Exact);
+    assert!(marne::fetch_servers(&marne::Marne, &missing, &detection)
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,193 @@
+//! Matching of the configured server names and redaction of player names
+
+use discord_bot::{
+    config::{NameMatching, Privacy, Redaction, Redactor, ServerTarget, Static, Surface},
+    discord::public_name,
+    marne::{self, MarneServerInfo, MarneServerList, MockClient},
+};
+use serenity::model::id::GuildId;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+};
+
+fn server(id: i64, name: &str) -> MarneServerInfo {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "name": name,
+        "mapName": "Levels/MP/MP_Amiens/MP_Amiens",
+        "gameMode": "Conquest0",
+        "maxPlayers": 64,
+        "currentPlayers": 10,
+    }))
+    .unwrap()
+}
+
+fn target(name: &str) -> ServerTarget {
+    ServerTarget {
+        name: Some(name.to_string()),
+        id: None,
+    }
+}
+
+fn redactor(rules: &[(&str, Option<&str>)]) -> Redactor {
+    let rules: Vec<Redaction> = rules
+        .iter()
+        .map(|(pattern, replacement)| Redaction {
+            pattern: pattern.to_string(),
+            replacement: replacement.map(str::to_string),
+        })
+        .collect();
+    Redactor::new(&rules)
+}
+
+#[test]
+fn exact_names_are_closest() {
+    let server = server(1, "[MARNE] Conquest #1");
+    for matching in [
+        NameMatching::Exact,
+        NameMatching::CaseInsensitive,
+        NameMatching::Substring,
+        NameMatching::Fuzzy,
+    ] {
+        assert_eq!(
+            target("[MARNE] Conquest #1").distance(&server, matching, 3),
+            Some(0)
+        );
+    }
+    assert_eq!(
+        target("[marne] conquest #1").distance(&server, NameMatching::Exact, 3),
+        None
+    );
+    assert_eq!(
+        target(" [marne] conquest #1 ").distance(&server, NameMatching::CaseInsensitive, 3),
+        Some(1)
+    );
+}
+
+#[test]
+fn near_misses_match_within_the_fuzzy_distance() {
+    let server = server(1, "[MARNE] Conquest #1");
+    // one missing letter
+    assert_eq!(
+        target("[MARNE] Conqest #1").distance(&server, NameMatching::Fuzzy, 3),
+        Some(2)
+    );
+    // case doesn't count as an edit
+    assert_eq!(
+        target("[marne] CONQEST #1").distance(&server, NameMatching::Fuzzy, 3),
+        Some(2)
+    );
+    assert_eq!(
+        target("[MARNE] Frontlines #1").distance(&server, NameMatching::Fuzzy, 3),
+        None
+    );
+    assert_eq!(
+        target("[MARNE] Conqest #1").distance(&server, NameMatching::Fuzzy, 0),
+        None
+    );
+    // a shorter name in the server name is closer than a longer one
+    let short = target("conquest").distance(&server, NameMatching::Substring, 3);
+    let long = target("marne] conquest").distance(&server, NameMatching::Substring, 3);
+    assert!(long < short, "{:?} {:?}", long, short);
+}
+
+#[test]
+fn empty_names_only_match_on_id() {
+    let server = server(7, "");
+    for matching in [NameMatching::Substring, NameMatching::Fuzzy] {
+        assert_eq!(target("").distance(&server, matching, 3), None);
+        assert_eq!(target("  ").distance(&server, matching, 3), None);
+    }
+    let by_id = ServerTarget {
+        name: Some(String::new()),
+        id: Some(7),
+    };
+    assert_eq!(by_id.distance(&server, NameMatching::Substring, 3), Some(0));
+}
+
+#[tokio::test]
+async fn ties_go_to_the_first_server_of_the_list() {
+    let mut client = MockClient::default();
+    client.lists.insert(
+        "bf1".into(),
+        MarneServerList {
+            servers: vec![
+                server(1, "[MARNE] Conquest #1"),
+                server(3, "[MARNE] Conquest #3"),
+            ],
+        },
+    );
+    let statics = Static {
+        server_name: Some("[MARNE] Conquest #2".to_string()),
+        name_matching: NameMatching::Fuzzy,
+        ..Static::default()
+    };
+    let found = marne::fetch_servers(&client, &statics, &marne::GameDetection::default())
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, 1);
+}
+
+#[test]
+fn redaction_masks_and_hides_names() {
+    let redactor = redactor(&[("(?i)admin", Some("***")), ("(?i)https?://", None)]);
+    assert_eq!(redactor.apply("Admin_Bob").as_deref(), Some("***_Bob"));
+    assert_eq!(redactor.apply("ADMINadmin").as_deref(), Some("******"));
+    // near misses stay as they are
+    assert_eq!(redactor.apply("Adm1n_Bob").as_deref(), Some("Adm1n_Bob"));
+    assert_eq!(
+        redactor.apply("http:/example").as_deref(),
+        Some("http:/example")
+    );
+    assert_eq!(redactor.apply("https://example.com"), None);
+}
+
+#[test]
+fn redaction_rules_apply_in_order() {
+    // the first rule replaces the part the second one would hide the name for
+    let masked = redactor(&[("bad", Some("b*d")), ("bad", None)]);
+    assert_eq!(masked.apply("badname").as_deref(), Some("b*dname"));
+    let hidden = redactor(&[("bad", None), ("bad", Some("b*d"))]);
+    assert_eq!(hidden.apply("badname"), None);
+}
+
+#[test]
+fn redaction_of_empty_names_and_broken_rules() {
+    assert_eq!(redactor(&[]).apply("").as_deref(), Some(""));
+    assert_eq!(redactor(&[("x", None)]).apply("").as_deref(), Some(""));
+    // a rule that doesn't compile is left out
+    let broken = redactor(&[("(unclosed", None), ("x", Some("y"))]);
+    assert_eq!(broken.apply("xx").as_deref(), Some("yy"));
+}
+
+#[test]
+fn public_names_follow_the_privacy_of_the_guild() {
+    let scrim = AtomicBool::new(false);
+    let hidden_roster = Privacy {
+        roster: false,
+        ..Privacy::default()
+    };
+    let privacy = RwLock::new(HashMap::from([("1".to_string(), hidden_roster)]));
+    let redactor = redactor(&[("(?i)admin", Some("***"))]);
+    let name = |guild_id: u64, surface| {
+        public_name(
+            &scrim,
+            &privacy,
+            &redactor,
+            Some(GuildId::new(guild_id)),
+            surface,
+            "Admin_Bob",
+        )
+    };
+    // linked members on `/online` are shown on the roster surface
+    assert_eq!(name(1, Surface::Roster), None);
+    assert_eq!(name(1, Surface::JoinLeaveLog).as_deref(), Some("***_Bob"));
+    assert_eq!(name(2, Surface::Roster).as_deref(), Some("***_Bob"));
+    scrim.store(true, Ordering::Relaxed);
+    assert_eq!(name(2, Surface::Roster), None);
+}
@@ -0,0 +1,11 @@
+//! Receivers check the signature of the webhooks against their own HMAC-SHA256
+
+use discord_bot::webhooks::signature;
+
+#[test]
+fn signs_with_hmac_sha256() {
+    assert_eq!(
+        signature("key", "The quick brown fox jumps over the lazy dog"),
+        "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+}